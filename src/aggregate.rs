@@ -0,0 +1,282 @@
+use super::{
+    parse_column_type, read_column_data, read_var_u64, AggregateMode, AggregateType, ColumnData,
+    ColumnType, NativeOptions,
+};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Read};
+
+/// Parses the arguments of `AggregateFunction([version,] func[(params)], T...)`.
+pub(crate) fn parse_aggregate_function(args: &[String]) -> AggregateType {
+    let args = match args.first() {
+        Some(version) if version.parse::<u64>().is_ok() => &args[1..],
+        _ => args,
+    };
+    let function = args.first().map(String::as_str).unwrap_or_default();
+    let name = function.split('(').next().unwrap_or_default().trim();
+    AggregateType {
+        function: name.to_string(),
+        arguments: args
+            .iter()
+            .skip(1)
+            .map(|arg| parse_column_type(arg).0)
+            .collect(),
+    }
+}
+
+/// Type the state of `sum` is accumulated in.
+fn sum_type(argument: &ColumnType) -> Option<ColumnType> {
+    Some(match argument {
+        ColumnType::UInt8 | ColumnType::UInt16 | ColumnType::UInt32 | ColumnType::UInt64 => {
+            ColumnType::UInt64
+        }
+        ColumnType::Int8 | ColumnType::Int16 | ColumnType::Int32 | ColumnType::Int64 => {
+            ColumnType::Int64
+        }
+        ColumnType::Float32 | ColumnType::Float64 => ColumnType::Float64,
+        ColumnType::Decimal(precision, scale) if *precision <= 38 => {
+            ColumnType::Decimal(38, *scale)
+        }
+        _ => return None,
+    })
+}
+
+/// Whether `min`, `max` and `any` states of the type can be decoded: fixed
+/// width values and strings.
+fn single_value_supported(argument: &ColumnType) -> bool {
+    matches!(
+        argument,
+        ColumnType::String
+            | ColumnType::UInt8
+            | ColumnType::UInt16
+            | ColumnType::UInt32
+            | ColumnType::UInt64
+            | ColumnType::Int8
+            | ColumnType::Int16
+            | ColumnType::Int32
+            | ColumnType::Int64
+            | ColumnType::Float32
+            | ColumnType::Float64
+            | ColumnType::Date
+            | ColumnType::Date32
+            | ColumnType::DateTime(_)
+            | ColumnType::DateTime64(..)
+            | ColumnType::Decimal(..)
+            | ColumnType::Uuid
+    )
+}
+
+/// Size of the keys a `uniqExact` state stores: the value itself for fixed
+/// width types, a 128-bit hash for strings and for several arguments.
+fn uniq_exact_key_size(arguments: &[ColumnType]) -> Option<usize> {
+    if arguments.len() != 1 {
+        return Some(16);
+    }
+    Some(match non_nullable(&arguments[0]) {
+        ColumnType::UInt8 | ColumnType::Int8 | ColumnType::Bool | ColumnType::Enum8(_) => 1,
+        ColumnType::UInt16 | ColumnType::Int16 | ColumnType::Date | ColumnType::Enum16(_) => 2,
+        ColumnType::UInt32
+        | ColumnType::Int32
+        | ColumnType::Float32
+        | ColumnType::Date32
+        | ColumnType::DateTime(_) => 4,
+        ColumnType::UInt64
+        | ColumnType::Int64
+        | ColumnType::Float64
+        | ColumnType::DateTime64(..) => 8,
+        ColumnType::String | ColumnType::FixedString(_) | ColumnType::Uuid => 16,
+        _ => return None,
+    })
+}
+
+fn non_nullable(argument: &ColumnType) -> &ColumnType {
+    match argument {
+        ColumnType::Nullable(inner) => inner,
+        other => other,
+    }
+}
+
+/// Type of the finalized value of a state this reader can decode.
+pub(crate) fn finalized_type(aggregate: &AggregateType) -> Option<ColumnType> {
+    let argument = aggregate.arguments.first().map(non_nullable);
+    Some(match (aggregate.function.as_str(), argument) {
+        ("count", _) => ColumnType::UInt64,
+        ("uniqExact", _) if uniq_exact_key_size(&aggregate.arguments).is_some() => {
+            ColumnType::UInt64
+        }
+        ("sum", Some(argument)) => ColumnType::Nullable(Box::new(sum_type(argument)?)),
+        ("avg", Some(argument)) if sum_type(argument).is_some() => {
+            ColumnType::Nullable(Box::new(ColumnType::Float64))
+        }
+        ("min" | "max" | "any" | "anyLast", Some(argument)) if single_value_supported(argument) => {
+            ColumnType::Nullable(Box::new(argument.clone()))
+        }
+        _ => return None,
+    })
+}
+
+/// Keeps a copy of everything read through it, so a state can be decoded
+/// and kept as raw bytes at the same time.
+struct Recorder<'a> {
+    inner: &'a mut dyn Read,
+    bytes: Vec<u8>,
+}
+
+impl Read for Recorder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn read_one(reader: &mut impl Read, column_type: &ColumnType) -> io::Result<ColumnData> {
+    Ok(read_column_data(reader, column_type, 1)?
+        .pop()
+        .unwrap_or(ColumnData::Null))
+}
+
+/// Reads a `SingleValueData` state: an Int32 size (-1 when empty) and the
+/// zero-terminated bytes for strings, a has-value flag and the value
+/// otherwise.
+fn read_single_value(reader: &mut impl Read, argument: &ColumnType) -> io::Result<ColumnData> {
+    if let ColumnType::String = argument {
+        let size = reader.read_i32::<LittleEndian>()?;
+        if size < 0 {
+            return Ok(ColumnData::Null);
+        }
+        let mut buffer = vec![0; size as usize];
+        reader.read_exact(&mut buffer)?;
+        if buffer.last() == Some(&0) {
+            buffer.pop();
+        }
+        return Ok(ColumnData::String(
+            String::from_utf8_lossy(&buffer).into_owned(),
+        ));
+    }
+    if reader.read_u8()? == 0 {
+        return Ok(ColumnData::Null);
+    }
+    read_one(reader, argument)
+}
+
+fn as_f64(value: &ColumnData, argument: &ColumnType) -> Option<f64> {
+    Some(match (value, argument) {
+        (ColumnData::UInt64(v), _) => *v as f64,
+        (ColumnData::Int64(v), _) => *v as f64,
+        (ColumnData::Float64(v), _) => *v,
+        (ColumnData::Decimal(v), ColumnType::Decimal(_, scale)) => {
+            *v as f64 / 10f64.powi(*scale as i32)
+        }
+        _ => return None,
+    })
+}
+
+fn read_state(reader: &mut impl Read, aggregate: &AggregateType) -> io::Result<ColumnData> {
+    let argument = aggregate.arguments.first();
+    // Aggregates over a Nullable argument prefix their state with a flag
+    // telling whether any non-NULL value was aggregated.
+    if aggregate.function != "count" {
+        if let Some(ColumnType::Nullable(_)) = argument {
+            if reader.read_u8()? == 0 {
+                return Ok(ColumnData::Null);
+            }
+        }
+    }
+    let argument = argument.map(non_nullable);
+
+    match (aggregate.function.as_str(), argument) {
+        ("count", _) => Ok(ColumnData::UInt64(read_var_u64(reader)?)),
+        ("uniq", _) => {
+            // skip degree, then the 32-bit hashes kept in the sample
+            let _skip_degree = reader.read_u8()?;
+            let count = read_var_u64(reader)?;
+            io::copy(&mut reader.by_ref().take(count * 4), &mut io::sink())?;
+            Ok(ColumnData::Null)
+        }
+        ("uniqExact", _) => {
+            let key_size =
+                uniq_exact_key_size(&aggregate.arguments).ok_or_else(|| unsupported(aggregate))?;
+            let count = read_var_u64(reader)?;
+            io::copy(
+                &mut reader.by_ref().take(count * key_size as u64),
+                &mut io::sink(),
+            )?;
+            Ok(ColumnData::UInt64(count))
+        }
+        ("sum", Some(argument)) => {
+            let sum_type = sum_type(argument).ok_or_else(|| unsupported(aggregate))?;
+            read_one(reader, &sum_type)
+        }
+        ("avg", Some(argument)) => {
+            let sum_type = sum_type(argument).ok_or_else(|| unsupported(aggregate))?;
+            let numerator = read_one(reader, &sum_type)?;
+            let denominator = read_var_u64(reader)?;
+            Ok(match as_f64(&numerator, &sum_type) {
+                Some(sum) if denominator > 0 => ColumnData::Float64(sum / denominator as f64),
+                _ => ColumnData::Null,
+            })
+        }
+        ("min" | "max" | "any" | "anyLast", Some(argument)) if single_value_supported(argument) => {
+            read_single_value(reader, argument)
+        }
+        _ => Err(unsupported(aggregate)),
+    }
+}
+
+fn unsupported(aggregate: &AggregateType) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Cannot read AggregateFunction({}) states: their serialization is not known",
+            aggregate.function
+        ),
+    )
+}
+
+/// Reads the states of an `AggregateFunction` column. States are written
+/// back to back without sizes, so each one is decoded to find where it ends;
+/// its raw bytes are kept alongside the finalized value.
+pub(crate) fn read_aggregate_data(
+    reader: &mut impl Read,
+    aggregate: &AggregateType,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    (0..rows)
+        .map(|_| {
+            let mut recorder = Recorder {
+                inner: reader,
+                bytes: Vec::new(),
+            };
+            let value = read_state(&mut recorder, aggregate)?;
+            Ok(ColumnData::Aggregate(recorder.bytes, Box::new(value)))
+        })
+        .collect()
+}
+
+/// Exposes a fully read `AggregateFunction` column as its finalized values
+/// or as the raw serialized states.
+pub(crate) fn resolve_aggregate(
+    column_type: &mut ColumnType,
+    values: Vec<&mut ColumnData>,
+    options: &NativeOptions,
+) -> io::Result<()> {
+    let ColumnType::AggregateFunction(aggregate) = column_type else {
+        return Ok(());
+    };
+    let finalized = match options.aggregate_as {
+        AggregateMode::Value => finalized_type(aggregate),
+        AggregateMode::State => None,
+    };
+    for value in values {
+        if let ColumnData::Aggregate(state, finalized_value) = value {
+            *value = match finalized {
+                Some(_) => std::mem::replace(finalized_value.as_mut(), ColumnData::Null),
+                None => ColumnData::Blob(std::mem::take(state)),
+            };
+        }
+    }
+    if let Some(finalized) = finalized {
+        *column_type = finalized;
+    }
+    Ok(())
+}
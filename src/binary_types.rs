@@ -0,0 +1,239 @@
+use super::{read_string, read_var_u64};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Read};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_var_i64(reader: &mut impl Read) -> io::Result<i64> {
+    let value = read_var_u64(reader)?;
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn backquote_if_needed(name: &str) -> String {
+    let plain = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("`{}`", name.replace('`', "\\`"))
+    }
+}
+
+fn read_types(reader: &mut impl Read, count: u64) -> io::Result<Vec<String>> {
+    (0..count).map(|_| read_binary_type(reader)).collect()
+}
+
+fn read_named_types(reader: &mut impl Read) -> io::Result<String> {
+    let count = read_var_u64(reader)?;
+    let mut elements = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = read_string(reader)?;
+        let type_name = read_binary_type(reader)?;
+        elements.push(format!("{} {}", backquote_if_needed(&name), type_name));
+    }
+    Ok(elements.join(", "))
+}
+
+fn read_enum(reader: &mut impl Read, wide: bool) -> io::Result<String> {
+    let count = read_var_u64(reader)?;
+    let mut values = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = read_string(reader)?;
+        let value = if wide {
+            reader.read_i16::<LittleEndian>()?
+        } else {
+            reader.read_i8()? as i16
+        };
+        values.push(format!("{} = {}", quote(&name), value));
+    }
+    Ok(values.join(", "))
+}
+
+fn interval_kind(kind: u8) -> io::Result<&'static str> {
+    Ok(match kind {
+        0x00 => "Nanosecond",
+        0x01 => "Microsecond",
+        0x02 => "Millisecond",
+        0x03 => "Second",
+        0x04 => "Minute",
+        0x05 => "Hour",
+        0x06 => "Day",
+        0x07 => "Week",
+        0x08 => "Month",
+        0x09 => "Quarter",
+        0x0A => "Year",
+        other => return Err(invalid(format!("Unknown interval kind {}", other))),
+    })
+}
+
+/// Reads a `Field` value used as an aggregate function parameter and
+/// renders it as a literal.
+fn read_field(reader: &mut impl Read) -> io::Result<String> {
+    Ok(match reader.read_u8()? {
+        0x00 => "NULL".to_string(),
+        0x01 => read_var_u64(reader)?.to_string(),
+        0x02 => read_var_i64(reader)?.to_string(),
+        0x03 => reader.read_u128::<LittleEndian>()?.to_string(),
+        0x04 => reader.read_i128::<LittleEndian>()?.to_string(),
+        0x07 => reader.read_f64::<LittleEndian>()?.to_string(),
+        0x0C => quote(&read_string(reader)?),
+        0x0D | 0x0E => {
+            let count = read_var_u64(reader)?;
+            let items = (0..count)
+                .map(|_| read_field(reader))
+                .collect::<io::Result<Vec<_>>>()?
+                .join(", ");
+            format!("[{}]", items)
+        }
+        0x13 => (reader.read_u8()? != 0).to_string(),
+        0xFE => "-inf".to_string(),
+        0xFF => "inf".to_string(),
+        other => return Err(invalid(format!("Unsupported field type {:#04x}", other))),
+    })
+}
+
+/// Reads `<name><parameters><arguments>` of an aggregate function type.
+fn read_aggregate_function(reader: &mut impl Read) -> io::Result<String> {
+    let name = read_string(reader)?;
+    let parameter_count = read_var_u64(reader)?;
+    let parameters = (0..parameter_count)
+        .map(|_| read_field(reader))
+        .collect::<io::Result<Vec<_>>>()?;
+    let argument_count = read_var_u64(reader)?;
+    let arguments = read_types(reader, argument_count)?;
+
+    let function = if parameters.is_empty() {
+        name
+    } else {
+        format!("{}({})", name, parameters.join(", "))
+    };
+    Ok(std::iter::once(function)
+        .chain(arguments)
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Reads a type written with ClickHouse's binary type encoding
+/// (`output_format_native_encode_types_in_binary_format`) and returns its
+/// canonical name, so it goes through the same `parse_column_type` mapping
+/// as a textual header.
+pub(crate) fn read_binary_type(reader: &mut impl Read) -> io::Result<String> {
+    let code = reader.read_u8()?;
+    Ok(match code {
+        0x00 => "Nothing".to_string(),
+        0x01 => "UInt8".to_string(),
+        0x02 => "UInt16".to_string(),
+        0x03 => "UInt32".to_string(),
+        0x04 => "UInt64".to_string(),
+        0x05 => "UInt128".to_string(),
+        0x06 => "UInt256".to_string(),
+        0x07 => "Int8".to_string(),
+        0x08 => "Int16".to_string(),
+        0x09 => "Int32".to_string(),
+        0x0A => "Int64".to_string(),
+        0x0B => "Int128".to_string(),
+        0x0C => "Int256".to_string(),
+        0x0D => "Float32".to_string(),
+        0x0E => "Float64".to_string(),
+        0x0F => "Date".to_string(),
+        0x10 => "Date32".to_string(),
+        0x11 => "DateTime".to_string(),
+        0x12 => format!("DateTime({})", quote(&read_string(reader)?)),
+        0x13 => format!("DateTime64({})", reader.read_u8()?),
+        0x14 => {
+            let precision = reader.read_u8()?;
+            format!(
+                "DateTime64({}, {})",
+                precision,
+                quote(&read_string(reader)?)
+            )
+        }
+        0x15 => "String".to_string(),
+        0x16 => format!("FixedString({})", read_var_u64(reader)?),
+        0x17 => format!("Enum8({})", read_enum(reader, false)?),
+        0x18 => format!("Enum16({})", read_enum(reader, true)?),
+        0x19..=0x1C => {
+            let precision = reader.read_u8()?;
+            let scale = reader.read_u8()?;
+            format!("Decimal({}, {})", precision, scale)
+        }
+        0x1D => "UUID".to_string(),
+        0x1E => format!("Array({})", read_binary_type(reader)?),
+        0x1F => {
+            let count = read_var_u64(reader)?;
+            format!("Tuple({})", read_types(reader, count)?.join(", "))
+        }
+        0x20 => format!("Tuple({})", read_named_types(reader)?),
+        0x21 => "Set".to_string(),
+        0x22 => format!("Interval{}", interval_kind(reader.read_u8()?)?),
+        0x23 => format!("Nullable({})", read_binary_type(reader)?),
+        0x24 => {
+            let count = read_var_u64(reader)?;
+            let arguments = read_types(reader, count)?;
+            let result = read_binary_type(reader)?;
+            format!("Function(({}) -> {})", arguments.join(", "), result)
+        }
+        0x25 => {
+            let version = read_var_u64(reader)?;
+            let function = read_aggregate_function(reader)?;
+            if version == 0 {
+                format!("AggregateFunction({})", function)
+            } else {
+                format!("AggregateFunction({}, {})", version, function)
+            }
+        }
+        0x26 => format!("LowCardinality({})", read_binary_type(reader)?),
+        0x27 => {
+            let key = read_binary_type(reader)?;
+            let value = read_binary_type(reader)?;
+            format!("Map({}, {})", key, value)
+        }
+        0x28 => "IPv4".to_string(),
+        0x29 => "IPv6".to_string(),
+        0x2A => {
+            let count = read_var_u64(reader)?;
+            format!("Variant({})", read_types(reader, count)?.join(", "))
+        }
+        0x2B => format!("Dynamic(max_types={})", reader.read_u8()?),
+        0x2C => read_string(reader)?,
+        0x2D => "Bool".to_string(),
+        0x2E => format!(
+            "SimpleAggregateFunction({})",
+            read_aggregate_function(reader)?
+        ),
+        0x2F => format!("Nested({})", read_named_types(reader)?),
+        0x30 => {
+            let _serialization_version = reader.read_u8()?;
+            let mut params = vec![
+                format!("max_dynamic_paths={}", read_var_i64(reader)?),
+                format!("max_dynamic_types={}", reader.read_u8()?),
+            ];
+            for _ in 0..read_var_u64(reader)? {
+                let path = read_string(reader)?;
+                let type_name = read_binary_type(reader)?;
+                params.push(format!("{} {}", backquote_if_needed(&path), type_name));
+            }
+            for _ in 0..read_var_u64(reader)? {
+                params.push(format!(
+                    "SKIP {}",
+                    backquote_if_needed(&read_string(reader)?)
+                ));
+            }
+            for _ in 0..read_var_u64(reader)? {
+                params.push(format!("SKIP REGEXP {}", quote(&read_string(reader)?)));
+            }
+            format!("JSON({})", params.join(", "))
+        }
+        0x31 => "BFloat16".to_string(),
+        0x32 => "Time".to_string(),
+        0x34 => format!("Time64({})", reader.read_u8()?),
+        other => return Err(invalid(format!("Unknown binary type code {:#04x}", other))),
+    })
+}
@@ -0,0 +1,93 @@
+use super::{bind_columns, native_reader, read_native_format, ClickHouseInitData, NativeOptions};
+use duckdb::{
+    core::{DataChunkHandle, LogicalTypeHandle, LogicalTypeId},
+    vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab, Value},
+    Connection, Result,
+};
+use std::{error::Error, io::Cursor};
+
+/// Recovers the bytes of a BLOB from DuckDB's text form of it, where bytes
+/// other than printable ASCII, `\`, `'` and `"` are written as `\xHH`. The
+/// table function API only hands over parameters through that conversion.
+fn blob_bytes(value: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+    let text = value.to_string();
+    let mut text = text.as_bytes().iter();
+    let mut bytes = Vec::with_capacity(text.len());
+    while let Some(&c) = text.next() {
+        if c != b'\\' {
+            bytes.push(c);
+            continue;
+        }
+        let escape: Vec<u8> = text.by_ref().take(3).copied().collect();
+        let byte = match escape.as_slice() {
+            [b'x', hex @ ..] if hex.len() == 2 => {
+                u8::from_str_radix(std::str::from_utf8(hex)?, 16).ok()
+            }
+            _ => None,
+        };
+        bytes.push(byte.ok_or("Invalid escape in BLOB text")?);
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug)]
+struct BlobBindData {
+    data: Vec<u8>,
+    options: NativeOptions,
+}
+
+struct ClickHouseBlobVTab;
+
+impl VTab for ClickHouseBlobVTab {
+    type InitData = ClickHouseInitData;
+    type BindData = BlobBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let value = bind.get_parameter(0);
+        if value.is_null() {
+            return Err("clickhouse_native_blob needs a BLOB, got NULL".into());
+        }
+        let data = blob_bytes(&value)?;
+        let options = NativeOptions::from_bind(bind)?;
+
+        let mut reader = native_reader(Cursor::new(data.clone()))?;
+        let columns = read_native_format(&mut reader, &options)?;
+        if columns.is_empty() {
+            return Err("BLOB holds no Native columns".into());
+        }
+
+        bind_columns(bind, &columns);
+
+        Ok(BlobBindData { data, options })
+    }
+
+    fn init(info: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        let bind_data = info.get_bind_data::<BlobBindData>();
+        let bind_data = unsafe { &*bind_data };
+        let mut reader = native_reader(Cursor::new(bind_data.data.clone()))?;
+
+        let columns = read_native_format(&mut reader, &bind_data.options)?;
+        Ok(ClickHouseInitData::new(columns))
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        func.get_init_data().scan(output)?;
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Blob)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        Some(NativeOptions::named_parameters())
+    }
+}
+
+pub fn register_blob_function(con: &Connection) -> Result<(), Box<dyn Error>> {
+    con.register_table_function::<ClickHouseBlobVTab>("clickhouse_native_blob")?;
+    Ok(())
+}
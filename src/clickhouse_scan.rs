@@ -1,11 +1,21 @@
-use clickhouse_rs::{types::SqlType, Pool};
+use super::{set_value, ColumnData};
+use chrono::{DateTime, NaiveDate};
+use clickhouse_rs::{
+    errors::Error as ClickHouseError,
+    types::{Block, ColumnType, FromSql, SqlType},
+    Pool,
+};
 use duckdb::{
-    core::{DataChunkHandle, Inserter, LogicalTypeHandle, LogicalTypeId},
+    core::{DataChunkHandle, FlatVector, Inserter, LogicalTypeHandle, LogicalTypeId},
     vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
     Connection, Result,
 };
-use std::{error::Error, sync::Arc};
-use tokio::runtime::Runtime;
+use futures_util::StreamExt;
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
+use tokio::{runtime::Runtime, sync::mpsc};
 
 #[repr(C)]
 struct ClickHouseScanBindData {
@@ -13,35 +23,253 @@ struct ClickHouseScanBindData {
     user: String,
     password: String,
     query: String,
-    column_names: Vec<String>,
-    column_types: Vec<LogicalTypeId>,
+    /// The result of a query `DESCRIBE` cannot wrap, which bind ran to find
+    /// its columns, for the first scan to emit instead of running it again.
+    prefetched: Mutex<Option<Vec<Vec<ColumnData>>>>,
 }
 
 #[repr(C)]
 struct ClickHouseScanInitData {
     runtime: Option<Arc<Runtime>>,
-    block_data: Option<Vec<Vec<String>>>,
-    column_types: Vec<LogicalTypeId>,
-    column_names: Vec<String>,
+    state: Mutex<ScanState>,
+}
+
+/// The block `func` is emitting and the channel the next ones arrive on.
+struct ScanState {
+    receiver: mpsc::Receiver<BlockResult>,
+    block_data: Vec<Vec<ColumnData>>,
     current_row: usize,
     total_rows: usize,
     done: bool,
 }
 
-fn map_clickhouse_type(sql_type: SqlType) -> LogicalTypeId {
-    match sql_type {
-        SqlType::Int8 | SqlType::Int16 | SqlType::Int32 => LogicalTypeId::Integer,
-        SqlType::Int64 => LogicalTypeId::Bigint,
-        SqlType::UInt8 | SqlType::UInt16 | SqlType::UInt32 => LogicalTypeId::UInteger,
-        SqlType::UInt64 => LogicalTypeId::UBigint,
-        SqlType::Float32 => LogicalTypeId::Float,
-        SqlType::Float64 => LogicalTypeId::Double,
-        SqlType::String | SqlType::FixedString(_) => LogicalTypeId::Varchar,
-        SqlType::Date => LogicalTypeId::Date,
-        SqlType::DateTime(_) => LogicalTypeId::Timestamp,
-        SqlType::Bool => LogicalTypeId::Boolean,
-        _ => LogicalTypeId::Integer,
+/// The values of a block by column, or the error that ended the query.
+type BlockResult = Result<Vec<Vec<ColumnData>>, String>;
+
+/// Blocks read ahead of `func`. The server sends blocks of up to
+/// `max_block_size` rows, so this bounds the memory a scan holds.
+const BLOCK_BUFFER: usize = 2;
+
+/// Maps a type as `DESCRIBE` names it, such as `FixedString(16)` or
+/// `Nullable(DateTime64(3))`, to the DuckDB type `block_values` converts
+/// it to. Returns `None` for the types the scan cannot read.
+fn map_clickhouse_type(type_name: &str) -> Option<LogicalTypeId> {
+    let type_name = type_name.trim();
+    if let Some(inner) = type_name
+        .strip_prefix("Nullable(")
+        .and_then(|inner| inner.strip_suffix(')'))
+    {
+        return map_clickhouse_type(inner);
+    }
+    Some(
+        match type_name.split('(').next().unwrap_or_default().trim() {
+            "Int8" | "Int16" | "Int32" => LogicalTypeId::Integer,
+            "Int64" => LogicalTypeId::Bigint,
+            "UInt8" | "UInt16" | "UInt32" => LogicalTypeId::UInteger,
+            "UInt64" => LogicalTypeId::UBigint,
+            "Float32" => LogicalTypeId::Float,
+            "Float64" => LogicalTypeId::Double,
+            "String" | "FixedString" => LogicalTypeId::Varchar,
+            "Date" => LogicalTypeId::Date,
+            "DateTime" | "DateTime64" => LogicalTypeId::Timestamp,
+            "Bool" => LogicalTypeId::Boolean,
+            _ => return None,
+        },
+    )
+}
+
+/// The DuckDB type of a result column, or an error naming a type the scan
+/// cannot read, before the query runs.
+fn bind_type(name: &str, type_name: &str) -> Result<LogicalTypeId, Box<dyn Error>> {
+    map_clickhouse_type(type_name).ok_or_else(|| {
+        format!(
+            "Column {} has type {}, which clickhouse_scan cannot read",
+            name, type_name
+        )
+        .into()
+    })
+}
+
+/// Wraps a query to describe its result columns. `DESCRIBE` analyzes the
+/// query without running it, so only `init` executes it.
+fn describe_query(query: &str) -> String {
+    let query = query.trim().trim_end_matches(';');
+    format!("DESCRIBE TABLE ({})", query)
+}
+
+/// The code of the server error for a query it cannot parse, as when a
+/// statement that cannot be a subquery is wrapped in `DESCRIBE TABLE`.
+const SYNTAX_ERROR: u32 = 62;
+
+/// Read-only statements that cannot be a subquery. Their results list
+/// server metadata, so they are small enough to hold whole.
+const UNDESCRIBABLE_STATEMENTS: [&str; 3] = ["SHOW", "EXISTS", "EXPLAIN"];
+
+fn is_undescribable(query: &str) -> bool {
+    let keyword = query.split_whitespace().next().unwrap_or_default();
+    UNDESCRIBABLE_STATEMENTS
+        .iter()
+        .any(|statement| statement.eq_ignore_ascii_case(keyword))
+}
+
+/// The result columns of `query`, with its whole result for the
+/// `UNDESCRIBABLE_STATEMENTS` the server cannot describe. Such a query is
+/// run here, and its columns are taken from the header block of its result.
+/// Any other query `DESCRIBE` fails on returns that error.
+async fn query_columns(
+    pool: &Pool,
+    query: &str,
+) -> Result<
+    (
+        Vec<String>,
+        Vec<LogicalTypeId>,
+        Option<Vec<Vec<ColumnData>>>,
+    ),
+    Box<dyn Error>,
+> {
+    let mut client = pool.get_handle().await?;
+    match client.query(describe_query(query)).fetch_all().await {
+        Ok(block) => {
+            let mut names = Vec::new();
+            let mut types = Vec::new();
+            for row in block.rows() {
+                let name = row.get::<String, _>("name")?;
+                types.push(bind_type(&name, &row.get::<String, _>("type")?)?);
+                names.push(name);
+            }
+            Ok((names, types, None))
+        }
+        Err(ClickHouseError::Server(err))
+            if err.code == SYNTAX_ERROR && is_undescribable(query) =>
+        {
+            let mut client = pool.get_handle().await?;
+            let block = client.query(query).fetch_all().await?;
+            let mut names = Vec::new();
+            let mut types = Vec::new();
+            for col in block.columns() {
+                types.push(bind_type(col.name(), &col.sql_type().to_string())?);
+                names.push(col.name().to_string());
+            }
+            Ok((names, types, Some(block_values(&block)?)))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Reads a cell, `None` for a NULL in a Nullable column.
+fn cell<'a, T: FromSql<'a>, K: ColumnType>(
+    block: &'a Block<K>,
+    row: usize,
+    col: usize,
+    nullable: bool,
+) -> Result<Option<T>, ClickHouseError> {
+    if nullable {
+        block.get::<Option<T>, _>(row, col)
+    } else {
+        block.get::<T, _>(row, col).map(Some)
+    }
+}
+
+/// The values of a column in the DuckDB type `map_clickhouse_type` binds
+/// its type to.
+fn column_values<K: ColumnType>(
+    block: &Block<K>,
+    col: usize,
+) -> Result<Vec<ColumnData>, ClickHouseError> {
+    let (sql_type, nullable) = match block.columns()[col].sql_type() {
+        SqlType::Nullable(inner) => (inner.clone(), true),
+        sql_type => (sql_type, false),
+    };
+    let unix_epoch = NaiveDate::default();
+
+    (0..block.row_count())
+        .map(|row| {
+            let value =
+                match &sql_type {
+                    SqlType::Bool => cell(block, row, col, nullable)?.map(ColumnData::Bool),
+                    SqlType::UInt8 => cell::<u8, _>(block, row, col, nullable)?
+                        .map(|v| ColumnData::UInt32(v.into())),
+                    SqlType::UInt16 => cell::<u16, _>(block, row, col, nullable)?
+                        .map(|v| ColumnData::UInt32(v.into())),
+                    SqlType::UInt32 => cell(block, row, col, nullable)?.map(ColumnData::UInt32),
+                    SqlType::UInt64 => cell(block, row, col, nullable)?.map(ColumnData::UInt64),
+                    SqlType::Int8 => cell::<i8, _>(block, row, col, nullable)?
+                        .map(|v| ColumnData::Int32(v.into())),
+                    SqlType::Int16 => cell::<i16, _>(block, row, col, nullable)?
+                        .map(|v| ColumnData::Int32(v.into())),
+                    SqlType::Int32 => cell(block, row, col, nullable)?.map(ColumnData::Int32),
+                    SqlType::Int64 => cell(block, row, col, nullable)?.map(ColumnData::Int64),
+                    SqlType::Float32 => cell(block, row, col, nullable)?.map(ColumnData::Float32),
+                    SqlType::Float64 => cell(block, row, col, nullable)?.map(ColumnData::Float64),
+                    // FixedString values are padded with zero bytes.
+                    SqlType::String | SqlType::FixedString(_) => {
+                        cell::<&[u8], _>(block, row, col, nullable)?.map(|bytes| {
+                            let value = String::from_utf8_lossy(bytes);
+                            ColumnData::String(value.trim_end_matches('\0').to_string())
+                        })
+                    }
+                    SqlType::Date => cell::<NaiveDate, _>(block, row, col, nullable)?
+                        .map(|date| ColumnData::Date((date - unix_epoch).num_days() as i32)),
+                    SqlType::DateTime(_) => cell::<DateTime<_>, _>(block, row, col, nullable)?
+                        .map(|time| ColumnData::Timestamp(time.timestamp_micros())),
+                    other => {
+                        return Err(ClickHouseError::Other(
+                            format!("Cannot read a {} column", other).into(),
+                        ))
+                    }
+                };
+            Ok(value.unwrap_or(ColumnData::Null))
+        })
+        .collect()
+}
+
+/// The values of a block, by column.
+fn block_values<K: ColumnType>(block: &Block<K>) -> Result<Vec<Vec<ColumnData>>, ClickHouseError> {
+    (0..block.columns().len())
+        .map(|col| column_values(block, col))
+        .collect()
+}
+
+/// Writes values converted by `column_values` to a column of their bound
+/// type.
+fn write_values(vector: &mut FlatVector, values: &[ColumnData]) {
+    for (row, value) in values.iter().enumerate() {
+        match value {
+            ColumnData::String(s) => vector.insert(row, s.as_str()),
+            ColumnData::UInt32(v) => set_value(vector, row, *v),
+            ColumnData::UInt64(v) => set_value(vector, row, *v),
+            ColumnData::Int32(v) => set_value(vector, row, *v),
+            ColumnData::Int64(v) => set_value(vector, row, *v),
+            ColumnData::Float32(v) => set_value(vector, row, *v),
+            ColumnData::Float64(v) => set_value(vector, row, *v),
+            ColumnData::Bool(v) => set_value(vector, row, *v),
+            ColumnData::Date(v) => set_value(vector, row, *v),
+            ColumnData::Timestamp(v) => set_value(vector, row, *v),
+            _ => vector.set_null(row),
+        }
+    }
+}
+
+/// Runs the query and sends its blocks as they arrive. Sending waits while
+/// the channel is full, and stops the query once the scan is dropped.
+async fn stream_blocks(
+    url: &str,
+    query: &str,
+    sender: &mpsc::Sender<BlockResult>,
+) -> Result<(), clickhouse_rs::errors::Error> {
+    let pool = Pool::new(url);
+    let mut client = pool.get_handle().await?;
+    let mut blocks = client.query(query).stream_blocks();
+    while let Some(block) = blocks.next().await {
+        let block = block?;
+        if block.row_count() == 0 {
+            continue;
+        }
+        if sender.send(Ok(block_values(&block)?)).await.is_err() {
+            break;
+        }
     }
+    Ok(())
 }
 
 struct ClickHouseScanVTab;
@@ -70,43 +298,14 @@ impl VTab for ClickHouseScanVTab {
             .map(|v| v.to_string())
             .unwrap_or_else(|| std::env::var("CLICKHOUSE_PASSWORD").unwrap_or_default());
 
-        let runtime = Arc::new(Runtime::new().map_err(|e| format!("Failed to create runtime: {}", e))?);
+        let runtime =
+            Arc::new(Runtime::new().map_err(|e| format!("Failed to create runtime: {}", e))?);
 
-        let result = runtime.block_on(async {
-            let pool = Pool::new(url.clone());
-            let mut client = pool.get_handle().await?;
-            let block = client.query(&query).fetch_all().await?;
+        let pool = Pool::new(url.clone());
+        let (names, types, prefetched) = runtime.block_on(query_columns(&pool, &query))?;
 
-            let columns = block.columns();
-            let mut names = Vec::new();
-            let mut types = Vec::new();
-
-            for col in columns {
-                names.push(col.name().to_string());
-                types.push(map_clickhouse_type(col.sql_type()));
-            }
-
-            Ok::<(Vec<String>, Vec<LogicalTypeId>), Box<dyn Error>>((names, types))
-        })?;
-
-        let (names, types) = result;
-
-        for (name, type_id) in names.iter().zip(types.iter()) {
-            let logical_type = match type_id {
-                LogicalTypeId::Integer => LogicalTypeId::Integer,
-                LogicalTypeId::Bigint => LogicalTypeId::Bigint,
-                LogicalTypeId::UInteger => LogicalTypeId::UInteger,
-                LogicalTypeId::UBigint => LogicalTypeId::UBigint,
-                LogicalTypeId::Float => LogicalTypeId::Float,
-                LogicalTypeId::Double => LogicalTypeId::Double,
-                LogicalTypeId::Varchar => LogicalTypeId::Varchar,
-                LogicalTypeId::Date => LogicalTypeId::Date,
-                LogicalTypeId::Timestamp => LogicalTypeId::Timestamp,
-                LogicalTypeId::Boolean => LogicalTypeId::Boolean,
-                _ => LogicalTypeId::Varchar,
-            };
-            let type_handle = LogicalTypeHandle::from(logical_type);
-            bind.add_result_column(name, type_handle);
+        for (name, type_id) in names.iter().zip(types) {
+            bind.add_result_column(name, LogicalTypeHandle::from(type_id));
         }
 
         Ok(ClickHouseScanBindData {
@@ -114,8 +313,7 @@ impl VTab for ClickHouseScanVTab {
             user,
             password,
             query,
-            column_names: names,
-            column_types: types,
+            prefetched: Mutex::new(prefetched),
         })
     }
 
@@ -123,134 +321,85 @@ impl VTab for ClickHouseScanVTab {
         let bind_data = info.get_bind_data::<ClickHouseScanBindData>();
         let bind_data = unsafe { &*bind_data };
 
-        let runtime = Arc::new(Runtime::new().map_err(|e| format!("Failed to create runtime: {}", e))?);
-
-        let result = runtime.block_on(async {
-            let pool = Pool::new(bind_data.url.clone());
-            let mut client = pool.get_handle().await?;
-            let block = client.query(&bind_data.query).fetch_all().await?;
-
-            let columns = block.columns();
-            let mut data: Vec<Vec<String>> = Vec::new();
-
-            for _ in columns {
-                data.push(Vec::new());
-            }
-
-            let mut row_count = 0;
-            for row in block.rows() {
-                for (col_idx, col) in columns.iter().enumerate() {
-                    let value = match col.sql_type() {
-                        SqlType::UInt8 => match row.get::<u8, &str>(col.name()) {
-                            Ok(val) => val.to_string(),
-                            Err(_) => "0".to_string(),
-                        },
-                        // ... rest of type handling ...
-                        _ => match row.get::<String, &str>(col.name()) {
-                            Ok(val) => val,
-                            Err(_) => "0".to_string(),
-                        },
-                    };
-                    data[col_idx].push(value);
+        let runtime =
+            Arc::new(Runtime::new().map_err(|e| format!("Failed to create runtime: {}", e))?);
+
+        // The task only reads the next block off the connection once `func`
+        // has taken one out of the channel, so a full channel stops reading
+        // from the socket and the server waits on TCP flow control.
+        let (sender, receiver) = mpsc::channel(BLOCK_BUFFER);
+        let prefetched = bind_data
+            .prefetched
+            .lock()
+            .map_err(|_| "Bind data is poisoned")?
+            .take();
+        if let Some(block) = prefetched {
+            // Dropping the sender ends the scan after this block.
+            let _ = sender.try_send(Ok(block));
+        } else {
+            let url = bind_data.url.clone();
+            let query = bind_data.query.clone();
+            runtime.spawn(async move {
+                if let Err(err) = stream_blocks(&url, &query, &sender).await {
+                    let _ = sender.send(Err(err.to_string())).await;
                 }
-                row_count += 1;
-            }
-
-            Ok::<(Vec<Vec<String>>, usize), Box<dyn Error>>((data, row_count))
-        })?;
-
-        let (block_data, total_rows) = result;
-
-        let column_types = bind_data.column_types.iter().map(|t| match t {
-            LogicalTypeId::Integer => LogicalTypeId::Integer,
-            LogicalTypeId::Bigint => LogicalTypeId::Bigint,
-            LogicalTypeId::UInteger => LogicalTypeId::UInteger,
-            LogicalTypeId::UBigint => LogicalTypeId::UBigint,
-            LogicalTypeId::Float => LogicalTypeId::Float,
-            LogicalTypeId::Double => LogicalTypeId::Double,
-            LogicalTypeId::Varchar => LogicalTypeId::Varchar,
-            LogicalTypeId::Date => LogicalTypeId::Date,
-            LogicalTypeId::Timestamp => LogicalTypeId::Timestamp,
-            LogicalTypeId::Boolean => LogicalTypeId::Boolean,
-            _ => LogicalTypeId::Varchar,
-        }).collect();
-        let column_names = bind_data.column_names.iter().cloned().collect();
+            });
+        }
 
         Ok(ClickHouseScanInitData {
             runtime: Some(runtime),
-            block_data: Some(block_data),
-            column_types,
-            column_names,
-            current_row: 0,
-            total_rows,
-            done: false,
+            state: Mutex::new(ScanState {
+                receiver,
+                block_data: Vec::new(),
+                current_row: 0,
+                total_rows: 0,
+                done: false,
+            }),
         })
     }
 
-    fn func(func: &TableFunctionInfo<Self>, output: &mut DataChunkHandle) -> Result<(), Box<dyn Error>> {
-        let init_data = func.get_init_data() as *const ClickHouseScanInitData as *mut ClickHouseScanInitData;
-        
-        unsafe {
-            if (*init_data).done || (*init_data).current_row >= (*init_data).total_rows {
-                output.set_len(0);
-                (*init_data).done = true;
-                return Ok(());
-            }
-
-            let block_data = match (*init_data).block_data.as_ref() {
-                Some(data) => data,
-                None => return Err("Block data is not available".into()),
-            };
-
-            let batch_size = 1024.min((*init_data).total_rows - (*init_data).current_row);
-
-            for col_idx in 0..(*init_data).column_types.len() {
-                let mut vector = output.flat_vector(col_idx);
-                let type_id = &(*init_data).column_types[col_idx];
-
-                match type_id {
-                    LogicalTypeId::Integer | LogicalTypeId::UInteger => {
-                        let slice = vector.as_mut_slice::<i32>();
-                        for row_offset in 0..batch_size {
-                            let row_idx = (*init_data).current_row + row_offset;
-                            let val_str = &block_data[col_idx][row_idx];
-
-                            let val = if let Ok(v) = val_str.parse::<i32>() {
-                                v
-                            } else if let Ok(v) = val_str.parse::<u32>() {
-                                v as i32
-                            } else if let Ok(v) = i32::from_str_radix(val_str.trim(), 10) {
-                                v
-                            } else {
-                                0
-                            };
-                            slice[row_offset] = val;
-                        }
-                    }
-                    LogicalTypeId::Bigint => {
-                        let slice = vector.as_mut_slice::<i64>();
-                        for row_offset in 0..batch_size {
-                            let row_idx = (*init_data).current_row + row_offset;
-                            if let Ok(val) = block_data[col_idx][row_idx].parse::<i64>() {
-                                slice[row_offset] = val;
-                            } else {
-                                slice[row_offset] = 0;
-                            }
-                        }
-                    }
-                    _ => {
-                        for row_offset in 0..batch_size {
-                            let row_idx = (*init_data).current_row + row_offset;
-                            let val = block_data[col_idx][row_idx].as_str();
-                            vector.insert(row_offset, val);
-                        }
-                    }
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        let init_data = func.get_init_data();
+        let mut state = init_data
+            .state
+            .lock()
+            .map_err(|_| "Scan state is poisoned")?;
+        let state = &mut *state;
+
+        // Wait for the next block once this one is emitted, skipping the
+        // empty blocks the server sends for progress and totals.
+        while !state.done && state.current_row >= state.total_rows {
+            match state.receiver.blocking_recv() {
+                Some(Ok(block)) => {
+                    state.total_rows = block.first().map_or(0, Vec::len);
+                    state.block_data = block;
+                    state.current_row = 0;
+                }
+                Some(Err(err)) => {
+                    state.done = true;
+                    return Err(err.into());
                 }
+                None => state.done = true,
             }
+        }
+        if state.done {
+            output.set_len(0);
+            return Ok(());
+        }
 
-            (*init_data).current_row += batch_size;
-            output.set_len(batch_size);
+        let block_data = &state.block_data;
+        let batch_size = 1024.min(state.total_rows - state.current_row);
+
+        for (col_idx, values) in block_data.iter().enumerate() {
+            let values = &values[state.current_row..state.current_row + batch_size];
+            write_values(&mut output.flat_vector(col_idx), values);
         }
+
+        state.current_row += batch_size;
+        output.set_len(batch_size);
         Ok(())
     }
 
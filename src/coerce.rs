@@ -0,0 +1,508 @@
+use super::{set_value, Column, ColumnData, ColumnType};
+use duckdb::{
+    core::{FlatVector, Inserter, LogicalTypeId},
+    vtab::{BindInfo, Value},
+};
+use std::{error::Error, io};
+
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A DuckDB type a column can be cast to with `columns` or `types_as`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CastType {
+    Boolean,
+    TinyInt,
+    SmallInt,
+    Integer,
+    BigInt,
+    HugeInt,
+    UTinyInt,
+    USmallInt,
+    UInteger,
+    UBigInt,
+    Float,
+    Double,
+    Varchar,
+    Date,
+    Timestamp,
+    TimestampTz,
+}
+
+impl CastType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.trim().to_uppercase().as_str() {
+            "BOOLEAN" | "BOOL" => CastType::Boolean,
+            "TINYINT" | "INT1" => CastType::TinyInt,
+            "SMALLINT" | "INT2" => CastType::SmallInt,
+            "INTEGER" | "INT" | "INT4" => CastType::Integer,
+            "BIGINT" | "INT8" => CastType::BigInt,
+            "HUGEINT" | "INT128" => CastType::HugeInt,
+            "UTINYINT" => CastType::UTinyInt,
+            "USMALLINT" => CastType::USmallInt,
+            "UINTEGER" => CastType::UInteger,
+            "UBIGINT" => CastType::UBigInt,
+            "FLOAT" | "REAL" | "FLOAT4" => CastType::Float,
+            "DOUBLE" | "FLOAT8" => CastType::Double,
+            "VARCHAR" | "TEXT" | "STRING" => CastType::Varchar,
+            "DATE" => CastType::Date,
+            "TIMESTAMP" => CastType::Timestamp,
+            "TIMESTAMPTZ" | "TIMESTAMP WITH TIME ZONE" => CastType::TimestampTz,
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn type_id(self) -> LogicalTypeId {
+        match self {
+            CastType::Boolean => LogicalTypeId::Boolean,
+            CastType::TinyInt => LogicalTypeId::Tinyint,
+            CastType::SmallInt => LogicalTypeId::Smallint,
+            CastType::Integer => LogicalTypeId::Integer,
+            CastType::BigInt => LogicalTypeId::Bigint,
+            CastType::HugeInt => LogicalTypeId::Hugeint,
+            CastType::UTinyInt => LogicalTypeId::UTinyint,
+            CastType::USmallInt => LogicalTypeId::USmallint,
+            CastType::UInteger => LogicalTypeId::UInteger,
+            CastType::UBigInt => LogicalTypeId::UBigint,
+            CastType::Float => LogicalTypeId::Float,
+            CastType::Double => LogicalTypeId::Double,
+            CastType::Varchar => LogicalTypeId::Varchar,
+            CastType::Date => LogicalTypeId::Date,
+            CastType::Timestamp => LogicalTypeId::Timestamp,
+            CastType::TimestampTz => LogicalTypeId::TimestampTZ,
+        }
+    }
+
+    fn name(self) -> String {
+        format!("{:?}", self).to_uppercase()
+    }
+
+    /// The values an integer type holds.
+    fn range(self) -> Option<(i128, i128)> {
+        Some(match self {
+            CastType::TinyInt => (i8::MIN as i128, i8::MAX as i128),
+            CastType::SmallInt => (i16::MIN as i128, i16::MAX as i128),
+            CastType::Integer => (i32::MIN as i128, i32::MAX as i128),
+            CastType::BigInt => (i64::MIN as i128, i64::MAX as i128),
+            CastType::HugeInt => (i128::MIN, i128::MAX),
+            CastType::UTinyInt => (0, u8::MAX as i128),
+            CastType::USmallInt => (0, u16::MAX as i128),
+            CastType::UInteger => (0, u32::MAX as i128),
+            CastType::UBigInt => (0, u64::MAX as i128),
+            _ => return None,
+        })
+    }
+}
+
+/// The kinds of ClickHouse types a cast starts from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    Integer,
+    Float,
+    Decimal(u8),
+    Text,
+    Bool,
+    Date,
+    /// Microseconds, or nanoseconds when set.
+    Timestamp(bool),
+    Uuid,
+}
+
+fn source(column_type: &ColumnType) -> Option<Source> {
+    Some(match column_type {
+        ColumnType::UInt8
+        | ColumnType::UInt16
+        | ColumnType::UInt32
+        | ColumnType::UInt64
+        | ColumnType::Int
+        | ColumnType::Int8
+        | ColumnType::Int16
+        | ColumnType::Int32
+        | ColumnType::Int64 => Source::Integer,
+        ColumnType::Float32 | ColumnType::Float64 => Source::Float,
+        ColumnType::Decimal(precision, _) if *precision > 38 => Source::Float,
+        ColumnType::Decimal(_, scale) => Source::Decimal(*scale),
+        ColumnType::String
+        | ColumnType::FixedString(_)
+        | ColumnType::Enum8(_)
+        | ColumnType::Enum16(_) => Source::Text,
+        ColumnType::Bool => Source::Bool,
+        ColumnType::Date | ColumnType::Date32 => Source::Date,
+        ColumnType::DateTime(_) => Source::Timestamp(false),
+        ColumnType::DateTime64(precision, tz) => Source::Timestamp(*precision > 6 && tz.is_none()),
+        ColumnType::Uuid => Source::Uuid,
+        _ => return None,
+    })
+}
+
+/// Whether values of `source` can be cast to `target` at all. Value ranges
+/// are checked while casting.
+fn castable(source: Source, target: CastType) -> bool {
+    match target {
+        CastType::Float | CastType::Double => matches!(
+            source,
+            Source::Integer | Source::Float | Source::Decimal(_) | Source::Text
+        ),
+        CastType::Boolean => matches!(source, Source::Integer | Source::Text | Source::Bool),
+        CastType::Varchar => !matches!(source, Source::Date | Source::Timestamp(_)),
+        CastType::Date => matches!(source, Source::Date | Source::Timestamp(_)),
+        CastType::Timestamp | CastType::TimestampTz => {
+            matches!(source, Source::Date | Source::Timestamp(_))
+        }
+        _ => matches!(
+            source,
+            Source::Integer | Source::Float | Source::Decimal(_) | Source::Text | Source::Bool
+        ),
+    }
+}
+
+pub(crate) fn integer_value(value: &ColumnData) -> Option<i128> {
+    Some(match value {
+        ColumnData::UInt8(v) => *v as i128,
+        ColumnData::UInt16(v) => *v as i128,
+        ColumnData::UInt32(v) => *v as i128,
+        ColumnData::UInt64(v) => *v as i128,
+        ColumnData::Int(v) | ColumnData::Int32(v) => *v as i128,
+        ColumnData::Int8(v) => *v as i128,
+        ColumnData::Int16(v) => *v as i128,
+        ColumnData::Int64(v) => *v as i128,
+        _ => return None,
+    })
+}
+
+fn float_value(value: &ColumnData) -> Option<f64> {
+    match value {
+        ColumnData::Float32(v) => Some(*v as f64),
+        ColumnData::Float64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn text_value(value: &ColumnData) -> Option<&str> {
+    match value {
+        ColumnData::String(s) | ColumnData::Enum8(s) | ColumnData::Enum16(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn decimal_text(value: i128, scale: u8) -> String {
+    if scale == 0 {
+        return value.to_string();
+    }
+    let digits = format!(
+        "{:0>width$}",
+        value.unsigned_abs(),
+        width = scale as usize + 1
+    );
+    let (whole, fraction) = digits.split_at(digits.len() - scale as usize);
+    let sign = if value < 0 { "-" } else { "" };
+    format!("{}{}.{}", sign, whole, fraction)
+}
+
+fn uuid_text(value: i128) -> String {
+    // DuckDB stores UUIDs with the top bit flipped, see `uuid_from_parts`.
+    let hex = format!("{:032x}", (value as u128) ^ (1u128 << 127));
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Casts one non-NULL value, or returns `None` when it does not fit.
+fn cast_value(value: &ColumnData, source: Source, target: CastType) -> Option<ColumnData> {
+    if let Some((min, max)) = target.range() {
+        let integer = match source {
+            Source::Integer => integer_value(value)?,
+            Source::Bool => matches!(value, ColumnData::Bool(true)) as i128,
+            Source::Float => {
+                let v = float_value(value)?.round();
+                if !v.is_finite() || v < min as f64 || v > max as f64 {
+                    return None;
+                }
+                v as i128
+            }
+            Source::Decimal(scale) => {
+                let ColumnData::Decimal(v) = value else {
+                    return None;
+                };
+                let divisor = 10i128.checked_pow(scale as u32)?;
+                // Rounds half away from zero, as DuckDB does.
+                let rounded = (v.abs() + divisor / 2) / divisor;
+                if *v < 0 {
+                    -rounded
+                } else {
+                    rounded
+                }
+            }
+            Source::Text => text_value(value)?.trim().parse().ok()?,
+            _ => return None,
+        };
+        return (min..=max)
+            .contains(&integer)
+            .then_some(ColumnData::HugeInt(integer));
+    }
+
+    Some(match target {
+        CastType::Float | CastType::Double => {
+            let v = match source {
+                Source::Integer => integer_value(value)? as f64,
+                Source::Float => float_value(value)?,
+                Source::Decimal(scale) => match value {
+                    ColumnData::Decimal(v) => *v as f64 / 10f64.powi(scale as i32),
+                    _ => return None,
+                },
+                Source::Text => text_value(value)?.trim().parse().ok()?,
+                _ => return None,
+            };
+            if target == CastType::Float && v.is_finite() && (v as f32).is_infinite() {
+                return None;
+            }
+            ColumnData::Float64(v)
+        }
+        CastType::Boolean => ColumnData::Bool(match source {
+            Source::Bool => matches!(value, ColumnData::Bool(true)),
+            Source::Integer => integer_value(value)? != 0,
+            Source::Text => match text_value(value)?.trim().to_lowercase().as_str() {
+                "true" | "t" | "1" => true,
+                "false" | "f" | "0" => false,
+                _ => return None,
+            },
+            _ => return None,
+        }),
+        CastType::Varchar => ColumnData::String(match (source, value) {
+            (Source::Text, _) => text_value(value)?.to_string(),
+            (Source::Integer, _) => integer_value(value)?.to_string(),
+            (Source::Float, _) => format!("{:?}", float_value(value)?),
+            (Source::Bool, ColumnData::Bool(v)) => v.to_string(),
+            (Source::Decimal(scale), ColumnData::Decimal(v)) => decimal_text(*v, scale),
+            (Source::Uuid, ColumnData::Uuid(v)) => uuid_text(*v),
+            _ => return None,
+        }),
+        CastType::Date => ColumnData::Date(match (source, value) {
+            (Source::Date, ColumnData::Date(days)) => *days,
+            (Source::Timestamp(nanos), ColumnData::Timestamp(v)) => {
+                let per_day = MICROS_PER_DAY * if nanos { 1000 } else { 1 };
+                i32::try_from(v.div_euclid(per_day)).ok()?
+            }
+            _ => return None,
+        }),
+        _ => ColumnData::Timestamp(match (source, value) {
+            (Source::Date, ColumnData::Date(days)) => *days as i64 * MICROS_PER_DAY,
+            (Source::Timestamp(true), ColumnData::Timestamp(v)) => v.div_euclid(1000),
+            (Source::Timestamp(false), ColumnData::Timestamp(v)) => *v,
+            _ => return None,
+        }),
+    })
+}
+
+/// Writes a value cast by `cast_column` in the width of its type.
+pub(crate) fn set_cast_value(
+    vector: &mut FlatVector,
+    row: usize,
+    target: CastType,
+    value: &ColumnData,
+) {
+    match (target, value) {
+        (CastType::TinyInt, ColumnData::HugeInt(v)) => set_value(vector, row, *v as i8),
+        (CastType::SmallInt, ColumnData::HugeInt(v)) => set_value(vector, row, *v as i16),
+        (CastType::Integer, ColumnData::HugeInt(v)) => set_value(vector, row, *v as i32),
+        (CastType::BigInt, ColumnData::HugeInt(v)) => set_value(vector, row, *v as i64),
+        (CastType::HugeInt, ColumnData::HugeInt(v)) => set_value(vector, row, *v),
+        (CastType::UTinyInt, ColumnData::HugeInt(v)) => set_value(vector, row, *v as u8),
+        (CastType::USmallInt, ColumnData::HugeInt(v)) => set_value(vector, row, *v as u16),
+        (CastType::UInteger, ColumnData::HugeInt(v)) => set_value(vector, row, *v as u32),
+        (CastType::UBigInt, ColumnData::HugeInt(v)) => set_value(vector, row, *v as u64),
+        (CastType::Float, ColumnData::Float64(v)) => set_value(vector, row, *v as f32),
+        (CastType::Double, ColumnData::Float64(v)) => set_value(vector, row, *v),
+        (CastType::Boolean, ColumnData::Bool(v)) => set_value(vector, row, *v),
+        (CastType::Varchar, ColumnData::String(s)) => vector.insert(row, s.as_str()),
+        (CastType::Date, ColumnData::Date(v)) => set_value(vector, row, *v),
+        (CastType::Timestamp | CastType::TimestampTz, ColumnData::Timestamp(v)) => {
+            set_value(vector, row, *v)
+        }
+        _ => vector.set_null(row),
+    }
+}
+
+fn cast_column(column: &mut Column, target: CastType) -> io::Result<()> {
+    let source_type = match &column.type_ {
+        ColumnType::Nullable(inner) => inner.as_ref(),
+        column_type => column_type,
+    };
+    let source = source(source_type)
+        .filter(|&source| castable(source, target))
+        .ok_or_else(|| {
+            let from = type_class(source_type)
+                .map(|class| format!(" from {}", class))
+                .unwrap_or_default();
+            invalid(format!(
+                "Cannot cast column {}{} to {}",
+                column.name,
+                from,
+                target.name()
+            ))
+        })?;
+
+    for (row, value) in column.data.iter_mut().enumerate() {
+        if let ColumnData::Null = value {
+            continue;
+        }
+        *value = cast_value(value, source, target).ok_or_else(|| {
+            let text = match cast_value(value, source, CastType::Varchar) {
+                Some(ColumnData::String(text)) => text,
+                _ => format!("{:?}", value),
+            };
+            invalid(format!(
+                "Cannot cast {} in row {} of column {} to {}",
+                text,
+                row + 1,
+                column.name,
+                target.name()
+            ))
+        })?;
+    }
+    column.type_ = ColumnType::Cast(target);
+    Ok(())
+}
+
+/// Splits DuckDB's text form of a STRUCT, `{'a': 1, 'b': 'x, y'}`, into its
+/// field names and values. Strings are quoted when they hold separators.
+pub(crate) fn struct_fields(text: &str) -> Option<Vec<(String, String)>> {
+    let inner = text.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut chars = inner.chars().peekable();
+    let mut fields = Vec::new();
+
+    let read_item = |chars: &mut std::iter::Peekable<std::str::Chars>, end: &[char]| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut item = String::new();
+        if chars.next_if_eq(&'\'').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => item.push(chars.next()?),
+                    '\'' => break,
+                    c => item.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !end.contains(c)) {
+                item.push(c);
+            }
+            item = item.trim().to_string();
+        }
+        Some(item)
+    };
+
+    while chars.peek().is_some() {
+        let name = read_item(&mut chars, &[':'])?;
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        chars.next_if_eq(&':')?;
+        let value = read_item(&mut chars, &[','])?;
+        fields.push((name, value));
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next().is_some_and(|c| c != ',') {
+            return None;
+        }
+    }
+    Some(fields)
+}
+
+/// Reads a `{'key': 'TYPE'}` STRUCT parameter into keys and cast types.
+fn cast_types(parameter: &str, value: &Value) -> Result<Vec<(String, CastType)>, Box<dyn Error>> {
+    let fields = match value.logical_type_id() {
+        LogicalTypeId::Struct => struct_fields(&value.to_string()),
+        _ => None,
+    }
+    .ok_or_else(|| format!("{} must be a STRUCT such as {{'id': 'UBIGINT'}}", parameter))?;
+    fields
+        .into_iter()
+        .map(|(key, type_name)| match CastType::parse(&type_name) {
+            Some(target) => Ok((key, target)),
+            None => Err(format!("Unsupported type {} in {}", type_name, parameter).into()),
+        })
+        .collect()
+}
+
+/// The ClickHouse type name `types_as` matches a column by, with `Enum`
+/// covering both enum widths.
+fn type_class(column_type: &ColumnType) -> Option<&'static str> {
+    Some(match column_type {
+        ColumnType::Nullable(inner) => return type_class(inner),
+        ColumnType::String => "String",
+        ColumnType::FixedString(_) => "FixedString",
+        ColumnType::UInt8 => "UInt8",
+        ColumnType::UInt16 => "UInt16",
+        ColumnType::UInt32 => "UInt32",
+        ColumnType::UInt64 => "UInt64",
+        ColumnType::Int | ColumnType::Int32 => "Int32",
+        ColumnType::Int8 => "Int8",
+        ColumnType::Int16 => "Int16",
+        ColumnType::Int64 => "Int64",
+        ColumnType::Float32 => "Float32",
+        ColumnType::Float64 => "Float64",
+        ColumnType::Bool => "Bool",
+        ColumnType::Date => "Date",
+        ColumnType::Date32 => "Date32",
+        ColumnType::DateTime(_) => "DateTime",
+        ColumnType::DateTime64(..) => "DateTime64",
+        ColumnType::Decimal(..) => "Decimal",
+        ColumnType::Enum8(_) | ColumnType::Enum16(_) => "Enum",
+        ColumnType::Uuid => "UUID",
+        _ => return None,
+    })
+}
+
+/// The `columns` and `types_as` parameters of `clickhouse_native`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TypeOverrides {
+    /// Types of single columns, by name.
+    columns: Vec<(String, CastType)>,
+    /// Types of every top-level column of a ClickHouse type.
+    types_as: Vec<(String, CastType)>,
+}
+
+impl TypeOverrides {
+    pub(crate) fn from_bind(bind: &BindInfo) -> Result<Self, Box<dyn Error>> {
+        Ok(TypeOverrides {
+            columns: match bind.get_named_parameter("columns") {
+                Some(value) => cast_types("columns", &value)?,
+                None => Vec::new(),
+            },
+            types_as: match bind.get_named_parameter("types_as") {
+                Some(value) => cast_types("types_as", &value)?,
+                None => Vec::new(),
+            },
+        })
+    }
+
+    /// Casts the columns picked by `types_as`, then by `columns`, which
+    /// takes precedence.
+    pub(crate) fn apply(&self, columns: &mut [Column]) -> io::Result<()> {
+        if let Some((name, _)) = self
+            .columns
+            .iter()
+            .find(|(name, _)| !columns.iter().any(|column| &column.name == name))
+        {
+            return Err(invalid(format!("Column {} is not in the file", name)));
+        }
+
+        for column in columns {
+            let by_name = self.columns.iter().find(|(name, _)| name == &column.name);
+            let by_type = type_class(&column.type_).and_then(|class| {
+                self.types_as
+                    .iter()
+                    .find(|(type_name, _)| type_name.eq_ignore_ascii_case(class))
+            });
+            if let Some((_, target)) = by_name.or(by_type) {
+                cast_column(column, *target)?;
+            }
+        }
+        Ok(())
+    }
+}
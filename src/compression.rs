@@ -0,0 +1,507 @@
+use clickhouse_rs_cityhash_sys::city_hash_128;
+use std::{
+    fs::File,
+    io::{self, BufRead, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Method bytes of ClickHouse's compressed-block framing, where every frame
+/// is a 16-byte CityHash128 checksum, the method byte, the compressed size
+/// (including the 9-byte header) and the decompressed size.
+const METHOD_NONE: u8 = 0x02;
+const METHOD_LZ4: u8 = 0x82;
+const METHOD_ZSTD: u8 = 0x90;
+
+const CHECKSUM_SIZE: usize = 16;
+const HEADER_SIZE: usize = 9;
+/// Largest frame ClickHouse accepts (`DBMS_MAX_COMPRESSED_SIZE`).
+const MAX_FRAME_SIZE: u32 = 0x4000_0000;
+/// Bytes gathered into a frame before it is written, the default
+/// `max_compress_block_size`.
+const FRAME_SIZE: usize = 1 << 20;
+/// ClickHouse's default ZSTD level.
+const ZSTD_LEVEL: i32 = 1;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn checksum(frame: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let hash = city_hash_128(frame);
+    let mut checksum = [0u8; CHECKSUM_SIZE];
+    checksum[..8].copy_from_slice(&hash.lo.to_le_bytes());
+    checksum[8..].copy_from_slice(&hash.hi.to_le_bytes());
+    checksum
+}
+
+/// The method and sizes in a frame header, if they are plausible.
+fn frame_header(header: &[u8]) -> Option<(u8, u32, u32)> {
+    let method = header[0];
+    let compressed = u32::from_le_bytes(header[1..5].try_into().unwrap());
+    let decompressed = u32::from_le_bytes(header[5..9].try_into().unwrap());
+    let valid = matches!(method, METHOD_NONE | METHOD_LZ4 | METHOD_ZSTD)
+        && (HEADER_SIZE as u32..=MAX_FRAME_SIZE).contains(&compressed)
+        && decompressed <= MAX_FRAME_SIZE;
+    valid.then_some((method, compressed, decompressed))
+}
+
+/// Decompresses a frame without its checksum, verified by the caller.
+fn decompress_frame(frame: &[u8]) -> io::Result<Vec<u8>> {
+    let (method, _, decompressed) = frame_header(frame)
+        .ok_or_else(|| invalid("Invalid compressed block header".to_string()))?;
+    let payload = &frame[HEADER_SIZE..];
+    let data = match method {
+        METHOD_NONE => payload.to_vec(),
+        METHOD_LZ4 => lz4::block::decompress(payload, Some(decompressed as i32))?,
+        _ => zstd::bulk::decompress(payload, decompressed as usize)?,
+    };
+    if data.len() != decompressed as usize {
+        return Err(invalid(format!(
+            "Compressed block holds {} bytes, expected {}",
+            data.len(),
+            decompressed
+        )));
+    }
+    Ok(data)
+}
+
+/// Reads a stream of compressed blocks, as written by `clickhouse-client
+/// --compression` or the HTTP interface with `compress=1`.
+struct CompressedReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> CompressedReader<R> {
+    fn new(inner: R) -> Self {
+        CompressedReader {
+            inner,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Reads and checks the next frame. Returns false at the end of the
+    /// stream.
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let mut head = [0u8; CHECKSUM_SIZE + HEADER_SIZE];
+        if self.inner.read(&mut head[..1])? == 0 {
+            return Ok(false);
+        }
+        self.inner.read_exact(&mut head[1..])?;
+        let (_, compressed, _) = frame_header(&head[CHECKSUM_SIZE..])
+            .ok_or_else(|| invalid("Invalid compressed block header".to_string()))?;
+
+        let mut frame = head[CHECKSUM_SIZE..].to_vec();
+        frame.resize(compressed as usize, 0);
+        self.inner.read_exact(&mut frame[HEADER_SIZE..])?;
+        if checksum(&frame) != head[..CHECKSUM_SIZE] {
+            return Err(invalid("Checksum mismatch in compressed block".to_string()));
+        }
+
+        self.buffer = decompress_frame(&frame)?;
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for CompressedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: Read> BufRead for CompressedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.position == self.buffer.len() {
+            if !self.read_frame()? {
+                break;
+            }
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = (self.position + amt).min(self.buffer.len());
+    }
+}
+
+/// Wraps a stream in a `CompressedReader` when it starts with a compressed
+/// block whose checksum matches. A plain Native stream is returned as is.
+pub(crate) fn decompress_blocks(
+    mut reader: Box<dyn BufRead + Send>,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let head = reader.fill_buf()?;
+    if head.len() < CHECKSUM_SIZE + HEADER_SIZE {
+        return Ok(reader);
+    }
+    let Some((_, compressed, _)) = frame_header(&head[CHECKSUM_SIZE..]) else {
+        return Ok(reader);
+    };
+
+    let mut frame = Vec::new();
+    (&mut reader)
+        .take((CHECKSUM_SIZE + compressed as usize) as u64)
+        .read_to_end(&mut frame)?;
+    let matches = frame.len() == CHECKSUM_SIZE + compressed as usize
+        && checksum(&frame[CHECKSUM_SIZE..]) == frame[..CHECKSUM_SIZE];
+    let reader = Cursor::new(frame).chain(reader);
+    if matches {
+        Ok(Box::new(CompressedReader::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// A compressed block of a file, located from its header.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Frame {
+    /// Where the block starts in the file, at its checksum.
+    pub(crate) offset: u64,
+    /// Its size in the file, checksum and header included.
+    pub(crate) size: u64,
+    /// The size of its data once decompressed.
+    pub(crate) decompressed: u64,
+}
+
+/// Locates the compressed blocks of a file from their headers, seeking past
+/// their data without reading it.
+fn scan_frames(file: &mut (impl Read + Seek), path: &Path) -> io::Result<Vec<Frame>> {
+    let truncated = || invalid(format!("Truncated compressed block in {}", path.display()));
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut frames = Vec::new();
+    let mut offset = 0;
+
+    while offset < len {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut head = [0u8; CHECKSUM_SIZE + HEADER_SIZE];
+        file.read_exact(&mut head).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => truncated(),
+            _ => err,
+        })?;
+        let header = &head[CHECKSUM_SIZE..];
+        if !matches!(header[0], METHOD_NONE | METHOD_LZ4 | METHOD_ZSTD) {
+            return Err(invalid(format!(
+                "Unsupported compression method 0x{:02x} in {}",
+                header[0],
+                path.display()
+            )));
+        }
+        let (_, compressed, decompressed) = frame_header(header)
+            .ok_or_else(|| invalid("Invalid compressed block header".to_string()))?;
+        let size = CHECKSUM_SIZE as u64 + compressed as u64;
+        if offset + size > len {
+            return Err(truncated());
+        }
+        frames.push(Frame {
+            offset,
+            size,
+            decompressed: decompressed as u64,
+        });
+        offset += size;
+    }
+    Ok(frames)
+}
+
+/// Reads a compressed block and checks its checksum, returning it without
+/// the checksum.
+fn read_frame(file: &mut (impl Read + Seek), frame: &Frame) -> io::Result<Option<Vec<u8>>> {
+    file.seek(SeekFrom::Start(frame.offset))?;
+    let mut block = vec![0u8; frame.size as usize];
+    file.read_exact(&mut block)?;
+    let matches = checksum(&block[CHECKSUM_SIZE..]) == block[..CHECKSUM_SIZE];
+    Ok(matches.then(|| block.split_off(CHECKSUM_SIZE)))
+}
+
+/// Locates the compressed blocks of a file read from `path`. Returns `None`
+/// when the file does not start with a compressed block whose checksum
+/// matches.
+pub(crate) fn read_frames(
+    file: &mut (impl Read + Seek),
+    path: &Path,
+) -> io::Result<Option<Vec<Frame>>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut head = [0u8; CHECKSUM_SIZE + HEADER_SIZE];
+    file.seek(SeekFrom::Start(0))?;
+    if len < head.len() as u64 {
+        return Ok(None);
+    }
+    file.read_exact(&mut head)?;
+    let Some((_, compressed, decompressed)) = frame_header(&head[CHECKSUM_SIZE..]) else {
+        return Ok(None);
+    };
+    let first = Frame {
+        offset: 0,
+        size: CHECKSUM_SIZE as u64 + compressed as u64,
+        decompressed: decompressed as u64,
+    };
+    if first.size > len || read_frame(file, &first)?.is_none() {
+        return Ok(None);
+    }
+    let frames = scan_frames(file, path)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(Some(frames))
+}
+
+/// A file that can be read from anywhere.
+pub(crate) trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// A file of compressed blocks, as MergeTree parts store their column
+/// streams and marks. Only the block headers are read when it is opened: a
+/// block is read and decompressed once reading reaches it, so seeking to a
+/// mark skips the blocks before it.
+pub(crate) struct CompressedFile {
+    file: Box<dyn ReadSeek>,
+    path: PathBuf,
+    frames: Vec<Frame>,
+    /// The block whose data is in `data`, and where reading is in it.
+    frame: Option<usize>,
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl CompressedFile {
+    /// Locates the blocks of `file`, read from `path`.
+    pub(crate) fn open(mut file: Box<dyn ReadSeek>, path: &Path) -> io::Result<Self> {
+        let frames = scan_frames(&mut file, path)?;
+        Ok(CompressedFile {
+            file,
+            path: path.to_path_buf(),
+            frames,
+            frame: None,
+            data: Vec::new(),
+            position: 0,
+        })
+    }
+
+    fn load(&mut self, frame: usize) -> io::Result<()> {
+        if self.frame != Some(frame) {
+            let block = read_frame(&mut self.file, &self.frames[frame])?.ok_or_else(|| {
+                invalid(format!(
+                    "Checksum mismatch in compressed block of {}",
+                    self.path.display()
+                ))
+            })?;
+            self.data = decompress_frame(&block)?;
+            self.frame = Some(frame);
+        }
+        self.position = 0;
+        Ok(())
+    }
+
+    /// Moves to a mark, given as the offset of a block in the file and an
+    /// offset in its decompressed data.
+    pub(crate) fn seek(&mut self, compressed: u64, decompressed: u64) -> io::Result<()> {
+        let frame = self
+            .frames
+            .binary_search_by_key(&compressed, |frame| frame.offset)
+            .map_err(|_| invalid(format!("No compressed block at offset {}", compressed)))?;
+        self.load(frame)?;
+        self.position = decompressed as usize;
+        Ok(())
+    }
+
+    /// The size of the file's data once decompressed, from the block
+    /// headers.
+    pub(crate) fn decompressed_len(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.decompressed).sum()
+    }
+
+    /// Decompresses the rest of the file.
+    pub(crate) fn read_all(mut self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl Read for CompressedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.data.len() {
+            let next = self.frame.map_or(0, |frame| frame + 1);
+            if next >= self.frames.len() {
+                return Ok(0);
+            }
+            self.load(next)?;
+        }
+        let available = &self.data[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Codec of the compressed blocks written by `CompressedWriter`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum BlockCompression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl BlockCompression {
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(BlockCompression::None),
+            "lz4" => Some(BlockCompression::Lz4),
+            "zstd" => Some(BlockCompression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Buffers a stream and writes it out in frames of about `FRAME_SIZE`
+/// bytes, compressed and checksummed unless no block compression is set.
+pub(crate) struct CompressedWriter<W: Write> {
+    inner: W,
+    compression: Option<BlockCompression>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub(crate) fn new(inner: W, compression: Option<BlockCompression>) -> Self {
+        CompressedWriter {
+            inner,
+            compression,
+            buffer: Vec::with_capacity(FRAME_SIZE),
+        }
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let Some(compression) = self.compression else {
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+            return Ok(());
+        };
+
+        let (method, payload) = match compression {
+            BlockCompression::None => (METHOD_NONE, self.buffer.clone()),
+            BlockCompression::Lz4 => (METHOD_LZ4, lz4::block::compress(&self.buffer, None, false)?),
+            BlockCompression::Zstd => {
+                (METHOD_ZSTD, zstd::bulk::compress(&self.buffer, ZSTD_LEVEL)?)
+            }
+        };
+        let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+        frame.push(method);
+        frame.extend_from_slice(&((HEADER_SIZE + payload.len()) as u32).to_le_bytes());
+        frame.extend_from_slice(&(self.buffer.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        self.inner.write_all(&checksum(&frame))?;
+        self.inner.write_all(&frame)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes the last frame and returns the inner writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.write_frame()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= FRAME_SIZE {
+            self.write_frame()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_frame()?;
+        self.inner.flush()
+    }
+}
+
+/// Compression of the whole output file, as `INTO OUTFILE ... COMPRESSION`
+/// writes it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FileCompression {
+    None,
+    Zstd,
+    Gzip,
+}
+
+impl FileCompression {
+    /// Parses a `FILE_COMPRESSION` option; `auto` picks the compression from
+    /// the file name.
+    pub(crate) fn parse(name: &str, path: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(FileCompression::None),
+            "zstd" => Some(FileCompression::Zstd),
+            "gzip" => Some(FileCompression::Gzip),
+            "auto" => {
+                let path = path.to_lowercase();
+                Some(if path.ends_with(".zst") || path.ends_with(".zstd") {
+                    FileCompression::Zstd
+                } else if path.ends_with(".gz") {
+                    FileCompression::Gzip
+                } else {
+                    FileCompression::None
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An output file, compressed as a whole or not.
+pub(crate) enum FileWriter {
+    Plain(File),
+    Zstd(zstd::Encoder<'static, File>),
+    Gzip(flate2::write::GzEncoder<File>),
+}
+
+impl FileWriter {
+    pub(crate) fn create(path: &str, compression: FileCompression) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(match compression {
+            FileCompression::None => FileWriter::Plain(file),
+            FileCompression::Zstd => FileWriter::Zstd(zstd::Encoder::new(file, 0)?),
+            FileCompression::Gzip => FileWriter::Gzip(flate2::write::GzEncoder::new(
+                file,
+                flate2::Compression::default(),
+            )),
+        })
+    }
+
+    /// Ends the compressed stream and flushes the file.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            FileWriter::Plain(file) => file,
+            FileWriter::Zstd(encoder) => encoder.finish()?,
+            FileWriter::Gzip(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::Plain(file) => file.write(buf),
+            FileWriter::Zstd(encoder) => encoder.write(buf),
+            FileWriter::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Plain(file) => file.flush(),
+            FileWriter::Zstd(encoder) => encoder.flush(),
+            FileWriter::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
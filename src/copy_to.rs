@@ -0,0 +1,658 @@
+use super::{
+    compression::{BlockCompression, CompressedWriter, FileCompression, FileWriter},
+    writer, ColumnData, ColumnType,
+};
+use duckdb::core::LogicalTypeId;
+use libduckdb_sys as ffi;
+use std::{
+    error::Error,
+    ffi::{c_char, c_void, CStr, CString},
+    ptr,
+    sync::Mutex,
+};
+
+/// Rows gathered before a block is written, the same as ClickHouse's
+/// default `max_block_size`.
+const DEFAULT_ROWS_PER_BLOCK: usize = 65536;
+
+/// A logical type owned by the extension.
+struct LogicalType(ffi::duckdb_logical_type);
+
+impl Drop for LogicalType {
+    fn drop(&mut self) {
+        unsafe { ffi::duckdb_destroy_logical_type(&mut self.0) };
+    }
+}
+
+/// A value owned by the extension.
+struct Value(ffi::duckdb_value);
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        unsafe { ffi::duckdb_destroy_value(&mut self.0) };
+    }
+}
+
+/// Takes a string allocated by DuckDB.
+unsafe fn take_string(ptr: *mut c_char) -> String {
+    let string = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    ffi::duckdb_free(ptr.cast());
+    string
+}
+
+fn duckdb_type_name(type_id: ffi::duckdb_type) -> String {
+    format!("{:?}", LogicalTypeId::from(type_id)).to_uppercase()
+}
+
+/// Maps a DuckDB type to the ClickHouse type it is written as, the inverse
+/// of `logical_type`. Scalars are Nullable, and ENUM columns become
+/// LowCardinality strings since they are DuckDB's dictionary-encoded strings.
+unsafe fn clickhouse_type(logical_type: ffi::duckdb_logical_type) -> Result<ColumnType, String> {
+    let nullable = |column_type| Ok(ColumnType::Nullable(Box::new(column_type)));
+    let type_id = ffi::duckdb_get_type_id(logical_type);
+    match type_id {
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_BOOLEAN => nullable(ColumnType::Bool),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TINYINT => nullable(ColumnType::Int8),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_SMALLINT => nullable(ColumnType::Int16),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_INTEGER => nullable(ColumnType::Int32),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_BIGINT => nullable(ColumnType::Int64),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UTINYINT => nullable(ColumnType::UInt8),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_USMALLINT => nullable(ColumnType::UInt16),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UINTEGER => nullable(ColumnType::UInt32),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UBIGINT => nullable(ColumnType::UInt64),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_FLOAT => nullable(ColumnType::Float32),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_DOUBLE => nullable(ColumnType::Float64),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_VARCHAR | ffi::DUCKDB_TYPE_DUCKDB_TYPE_BLOB => {
+            nullable(ColumnType::String)
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_DATE => nullable(ColumnType::Date32),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIME => nullable(ColumnType::Time64(6)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIME_NS => nullable(ColumnType::Time64(9)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_S => nullable(ColumnType::DateTime(None)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_MS => nullable(ColumnType::DateTime64(3, None)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP => nullable(ColumnType::DateTime64(6, None)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_NS => nullable(ColumnType::DateTime64(9, None)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_TZ => {
+            nullable(ColumnType::DateTime64(6, Some("UTC".to_string())))
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_DECIMAL => nullable(ColumnType::Decimal(
+            ffi::duckdb_decimal_width(logical_type),
+            ffi::duckdb_decimal_scale(logical_type),
+        )),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UUID => nullable(ColumnType::Uuid),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_ENUM => Ok(ColumnType::LowCardinality(Box::new(
+            ColumnType::Nullable(Box::new(ColumnType::String)),
+        ))),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_LIST => {
+            let child = LogicalType(ffi::duckdb_list_type_child_type(logical_type));
+            Ok(ColumnType::Array(Box::new(clickhouse_type(child.0)?)))
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_ARRAY => {
+            let child = LogicalType(ffi::duckdb_array_type_child_type(logical_type));
+            Ok(ColumnType::Array(Box::new(clickhouse_type(child.0)?)))
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_STRUCT => {
+            let count = ffi::duckdb_struct_type_child_count(logical_type);
+            let elements = (0..count)
+                .map(|idx| {
+                    let name = take_string(ffi::duckdb_struct_type_child_name(logical_type, idx));
+                    let child = LogicalType(ffi::duckdb_struct_type_child_type(logical_type, idx));
+                    Ok((name, clickhouse_type(child.0)?))
+                })
+                .collect::<Result<_, String>>()?;
+            Ok(ColumnType::Tuple(elements))
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_MAP => {
+            let key = LogicalType(ffi::duckdb_map_type_key_type(logical_type));
+            let value = LogicalType(ffi::duckdb_map_type_value_type(logical_type));
+            // Map keys cannot be Nullable in ClickHouse.
+            let key = match clickhouse_type(key.0)? {
+                ColumnType::Nullable(inner) => *inner,
+                ColumnType::LowCardinality(inner) => match *inner {
+                    ColumnType::Nullable(inner) => ColumnType::LowCardinality(inner),
+                    inner => ColumnType::LowCardinality(Box::new(inner)),
+                },
+                key => key,
+            };
+            Ok(ColumnType::Map(
+                Box::new(key),
+                Box::new(clickhouse_type(value.0)?),
+            ))
+        }
+        other => Err(duckdb_type_name(other)),
+    }
+}
+
+unsafe fn string_at(data: *mut c_void, row: usize) -> Vec<u8> {
+    let string = data.cast::<ffi::duckdb_string_t>().add(row);
+    let len = ffi::duckdb_string_t_length(*string) as usize;
+    let ptr = ffi::duckdb_string_t_data(string);
+    std::slice::from_raw_parts(ptr.cast::<u8>(), len).to_vec()
+}
+
+unsafe fn value_at<T: Copy>(data: *mut c_void, row: usize) -> T {
+    data.cast::<T>().add(row).read_unaligned()
+}
+
+/// Reads an integer stored with the given physical type, as used by the
+/// indexes of an ENUM and the values of a DECIMAL.
+unsafe fn integer_at(data: *mut c_void, type_id: ffi::duckdb_type, row: usize) -> i128 {
+    match type_id {
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UTINYINT => value_at::<u8>(data, row) as i128,
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_USMALLINT => value_at::<u16>(data, row) as i128,
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UINTEGER => value_at::<u32>(data, row) as i128,
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_SMALLINT => value_at::<i16>(data, row) as i128,
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_INTEGER => value_at::<i32>(data, row) as i128,
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_BIGINT => value_at::<i64>(data, row) as i128,
+        _ => value_at::<i128>(data, row),
+    }
+}
+
+/// Reads the first `count` rows of a flat vector as the values of the
+/// ClickHouse type `clickhouse_type` maps it to. Timestamps and times keep
+/// DuckDB's unit, which is the precision of that type.
+unsafe fn read_vector(vector: ffi::duckdb_vector, count: usize) -> Vec<ColumnData> {
+    let logical_type = LogicalType(ffi::duckdb_vector_get_column_type(vector));
+    let type_id = ffi::duckdb_get_type_id(logical_type.0);
+    let validity = ffi::duckdb_vector_get_validity(vector);
+    let is_valid =
+        |row: usize| validity.is_null() || ffi::duckdb_validity_row_is_valid(validity, row as u64);
+    let data = ffi::duckdb_vector_get_data(vector);
+
+    let mut values: Vec<ColumnData> = match type_id {
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_LIST | ffi::DUCKDB_TYPE_DUCKDB_TYPE_MAP => {
+            let size = ffi::duckdb_list_vector_get_size(vector) as usize;
+            let children = read_vector(ffi::duckdb_list_vector_get_child(vector), size);
+            (0..count)
+                .map(|row| {
+                    if !is_valid(row) {
+                        return ColumnData::Null;
+                    }
+                    let entry = value_at::<ffi::duckdb_list_entry>(data, row);
+                    let start = entry.offset as usize;
+                    ColumnData::Array(children[start..start + entry.length as usize].to_vec())
+                })
+                .collect()
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_ARRAY => {
+            let size = ffi::duckdb_array_type_array_size(logical_type.0) as usize;
+            let children = read_vector(ffi::duckdb_array_vector_get_child(vector), count * size);
+            (0..count)
+                .map(|row| ColumnData::Array(children[row * size..(row + 1) * size].to_vec()))
+                .collect()
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_STRUCT => {
+            let fields = ffi::duckdb_struct_type_child_count(logical_type.0);
+            let mut rows: Vec<Vec<ColumnData>> = vec![Vec::new(); count];
+            for idx in 0..fields {
+                let child = read_vector(ffi::duckdb_struct_vector_get_child(vector, idx), count);
+                for (row, value) in rows.iter_mut().zip(child) {
+                    row.push(value);
+                }
+            }
+            rows.into_iter().map(ColumnData::Tuple).collect()
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_ENUM => {
+            let internal_type = ffi::duckdb_enum_internal_type(logical_type.0);
+            let dictionary: Vec<String> = (0..ffi::duckdb_enum_dictionary_size(logical_type.0))
+                .map(|idx| {
+                    take_string(ffi::duckdb_enum_dictionary_value(
+                        logical_type.0,
+                        idx as u64,
+                    ))
+                })
+                .collect();
+            (0..count)
+                .map(|row| {
+                    let index = integer_at(data, internal_type, row) as usize;
+                    ColumnData::String(dictionary.get(index).cloned().unwrap_or_default())
+                })
+                .collect()
+        }
+        _ => (0..count)
+            .map(|row| {
+                if is_valid(row) {
+                    read_scalar(data, logical_type.0, type_id, row)
+                } else {
+                    ColumnData::Null
+                }
+            })
+            .collect(),
+    };
+
+    for (row, value) in values.iter_mut().enumerate() {
+        if !is_valid(row) {
+            *value = ColumnData::Null;
+        }
+    }
+    values
+}
+
+unsafe fn read_scalar(
+    data: *mut c_void,
+    logical_type: ffi::duckdb_logical_type,
+    type_id: ffi::duckdb_type,
+    row: usize,
+) -> ColumnData {
+    match type_id {
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_BOOLEAN => ColumnData::Bool(value_at::<u8>(data, row) != 0),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TINYINT => ColumnData::Int8(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_SMALLINT => ColumnData::Int16(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_INTEGER => ColumnData::Int32(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_BIGINT => ColumnData::Int64(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UTINYINT => ColumnData::UInt8(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_USMALLINT => ColumnData::UInt16(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UINTEGER => ColumnData::UInt32(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UBIGINT => ColumnData::UInt64(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_FLOAT => ColumnData::Float32(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_DOUBLE => ColumnData::Float64(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_VARCHAR => {
+            ColumnData::String(String::from_utf8_lossy(&string_at(data, row)).into_owned())
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_BLOB => ColumnData::Blob(string_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_DATE => ColumnData::Date(value_at(data, row)),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIME | ffi::DUCKDB_TYPE_DUCKDB_TYPE_TIME_NS => {
+            ColumnData::Time(value_at(data, row))
+        }
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_DECIMAL => ColumnData::Decimal(integer_at(
+            data,
+            ffi::duckdb_decimal_internal_type(logical_type),
+            row,
+        )),
+        ffi::DUCKDB_TYPE_DUCKDB_TYPE_UUID => ColumnData::Uuid(value_at(data, row)),
+        // The TIMESTAMP types, all stored as an i64 count of their unit.
+        _ => ColumnData::Timestamp(value_at(data, row)),
+    }
+}
+
+/// The format a copy function writes, kept as its extra info.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CopyFormat {
+    Native,
+    RowBinary,
+}
+
+impl CopyFormat {
+    fn name(self) -> &'static str {
+        match self {
+            CopyFormat::Native => "clickhouse_native",
+            CopyFormat::RowBinary => "clickhouse_rowbinary",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            CopyFormat::Native => "ClickHouse Native",
+            CopyFormat::RowBinary => "ClickHouse RowBinary",
+        }
+    }
+}
+
+/// The header written before RowBinary rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RowBinaryHeader {
+    None,
+    Names,
+    NamesAndTypes,
+}
+
+impl RowBinaryHeader {
+    /// Parses a `HEADER` option, which may also be given as a boolean.
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "none" | "false" => Some(RowBinaryHeader::None),
+            "names" => Some(RowBinaryHeader::Names),
+            "names_and_types" | "true" => Some(RowBinaryHeader::NamesAndTypes),
+            _ => None,
+        }
+    }
+}
+
+struct CopyOptions {
+    rows_per_block: usize,
+    column_names: Option<Vec<String>>,
+    compression: Option<BlockCompression>,
+    file_compression: String,
+    header: RowBinaryHeader,
+}
+
+/// The strings of an option given once or as a list.
+unsafe fn option_strings(value: ffi::duckdb_value) -> Vec<String> {
+    let value_type = ffi::duckdb_get_value_type(value);
+    if ffi::duckdb_get_type_id(value_type) != ffi::DUCKDB_TYPE_DUCKDB_TYPE_LIST {
+        return vec![take_string(ffi::duckdb_get_varchar(value))];
+    }
+    (0..ffi::duckdb_get_list_size(value))
+        .map(|idx| {
+            let item = Value(ffi::duckdb_get_list_child(value, idx));
+            take_string(ffi::duckdb_get_varchar(item.0))
+        })
+        .collect()
+}
+
+/// Reads the options of `COPY ... TO`, which DuckDB hands over as a STRUCT
+/// with one field per option.
+unsafe fn read_copy_options(
+    options: ffi::duckdb_value,
+    format: CopyFormat,
+) -> Result<CopyOptions, String> {
+    let mut copy_options = CopyOptions {
+        rows_per_block: DEFAULT_ROWS_PER_BLOCK,
+        column_names: None,
+        compression: None,
+        file_compression: "auto".to_string(),
+        header: RowBinaryHeader::NamesAndTypes,
+    };
+    if options.is_null() || ffi::duckdb_is_null_value(options) {
+        return Ok(copy_options);
+    }
+    let options_type = ffi::duckdb_get_value_type(options);
+    if ffi::duckdb_get_type_id(options_type) != ffi::DUCKDB_TYPE_DUCKDB_TYPE_STRUCT {
+        return Ok(copy_options);
+    }
+
+    for idx in 0..ffi::duckdb_struct_type_child_count(options_type) {
+        let name = take_string(ffi::duckdb_struct_type_child_name(options_type, idx));
+        let value = Value(ffi::duckdb_get_struct_child(options, idx));
+        match name.to_lowercase().as_str() {
+            "rows_per_block" if format == CopyFormat::Native => {
+                let rows = ffi::duckdb_get_int64(value.0);
+                if rows <= 0 {
+                    return Err("ROWS_PER_BLOCK must be a positive number of rows".to_string());
+                }
+                copy_options.rows_per_block = rows as usize;
+            }
+            "column_names" => copy_options.column_names = Some(option_strings(value.0)),
+            "compression" => {
+                let name = take_string(ffi::duckdb_get_varchar(value.0));
+                copy_options.compression =
+                    Some(BlockCompression::parse(&name).ok_or_else(|| {
+                        format!(
+                        "Unknown COMPRESSION {}, expected lz4, zstd or none (see FILE_COMPRESSION \
+                         for whole-file compression)",
+                        name
+                    )
+                    })?);
+            }
+            "file_compression" => {
+                let name = take_string(ffi::duckdb_get_varchar(value.0));
+                if FileCompression::parse(&name, "").is_none() {
+                    return Err(format!(
+                        "Unknown FILE_COMPRESSION {}, expected auto, zstd, gzip or none",
+                        name
+                    ));
+                }
+                copy_options.file_compression = name;
+            }
+            "header" if format == CopyFormat::RowBinary => {
+                let name = take_string(ffi::duckdb_get_varchar(value.0));
+                copy_options.header = RowBinaryHeader::parse(&name).ok_or_else(|| {
+                    format!(
+                        "Unknown HEADER {}, expected none, names or names_and_types",
+                        name
+                    )
+                })?;
+            }
+            _ => return Err(format!("Unknown option {} for {}", name, format.name())),
+        }
+    }
+    Ok(copy_options)
+}
+
+struct CopyBindData {
+    format: CopyFormat,
+    columns: Vec<(String, ColumnType)>,
+    rows_per_block: usize,
+    compression: Option<BlockCompression>,
+    file_compression: String,
+    header: RowBinaryHeader,
+}
+
+struct CopyState {
+    /// Taken when the copy is finalized.
+    out: Option<CompressedWriter<FileWriter>>,
+    /// Values of the rows not written yet, per column.
+    pending: Vec<Vec<ColumnData>>,
+    blocks: usize,
+}
+
+impl CopyState {
+    /// Writes the pending rows, as one Native block or as RowBinary rows.
+    fn write_block(&mut self, bind_data: &CopyBindData) -> Result<(), Box<dyn Error>> {
+        let out = self
+            .out
+            .as_mut()
+            .ok_or_else(|| format!("{} output already closed", bind_data.format.name()))?;
+        match bind_data.format {
+            CopyFormat::Native => {
+                writer::write_native_block(out, &bind_data.columns, &self.pending)?
+            }
+            CopyFormat::RowBinary => {
+                writer::write_rowbinary_rows(out, &bind_data.columns, &self.pending)?
+            }
+        }
+        self.pending.iter_mut().for_each(Vec::clear);
+        self.blocks += 1;
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn drop_boxed<T>(ptr: *mut c_void) {
+    drop(Box::from_raw(ptr.cast::<T>()));
+}
+
+fn error_message(error: impl ToString) -> CString {
+    CString::new(error.to_string()).unwrap_or_else(|_| c"clickhouse_native copy failed".to_owned())
+}
+
+unsafe fn copy_bind(info: ffi::duckdb_copy_function_bind_info) -> Result<CopyBindData, String> {
+    let format = *ffi::duckdb_copy_function_bind_get_extra_info(info).cast::<CopyFormat>();
+    let options = Value(ffi::duckdb_copy_function_bind_get_options(info));
+    let options = read_copy_options(options.0, format)?;
+
+    let count = ffi::duckdb_copy_function_bind_get_column_count(info) as usize;
+    let names = match options.column_names {
+        Some(names) if names.len() != count => {
+            return Err(format!(
+                "COLUMN_NAMES has {} names but the query returns {} columns",
+                names.len(),
+                count
+            ))
+        }
+        Some(names) => names,
+        // The copy API does not pass column names on; these are the names
+        // ClickHouse itself gives unnamed columns.
+        None => (1..=count).map(|idx| format!("c{}", idx)).collect(),
+    };
+
+    let columns = names
+        .into_iter()
+        .enumerate()
+        .map(|(idx, name)| {
+            let logical_type = LogicalType(ffi::duckdb_copy_function_bind_get_column_type(
+                info, idx as u64,
+            ));
+            let column_type = clickhouse_type(logical_type.0).map_err(|type_name| {
+                format!(
+                    "Cannot write column {} of type {} as {}",
+                    name,
+                    type_name,
+                    format.title()
+                )
+            })?;
+            Ok((name, column_type))
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(CopyBindData {
+        format,
+        columns,
+        rows_per_block: options.rows_per_block,
+        compression: options.compression,
+        file_compression: options.file_compression,
+        header: options.header,
+    })
+}
+
+unsafe extern "C" fn native_copy_bind(info: ffi::duckdb_copy_function_bind_info) {
+    match copy_bind(info) {
+        Ok(bind_data) => ffi::duckdb_copy_function_bind_set_bind_data(
+            info,
+            Box::into_raw(Box::new(bind_data)).cast(),
+            Some(drop_boxed::<CopyBindData>),
+        ),
+        Err(error) => ffi::duckdb_copy_function_bind_set_error(info, error_message(error).as_ptr()),
+    }
+}
+
+unsafe extern "C" fn native_copy_global_init(info: ffi::duckdb_copy_function_global_init_info) {
+    let bind_data =
+        &*ffi::duckdb_copy_function_global_init_get_bind_data(info).cast::<CopyBindData>();
+    let path = CStr::from_ptr(ffi::duckdb_copy_function_global_init_get_file_path(info));
+    let path = path.to_string_lossy();
+    let file_compression =
+        FileCompression::parse(&bind_data.file_compression, &path).unwrap_or(FileCompression::None);
+    let out = FileWriter::create(&path, file_compression).and_then(|file| {
+        let mut out = CompressedWriter::new(file, bind_data.compression);
+        if bind_data.format == CopyFormat::RowBinary && bind_data.header != RowBinaryHeader::None {
+            writer::write_rowbinary_header(
+                &mut out,
+                &bind_data.columns,
+                bind_data.header == RowBinaryHeader::NamesAndTypes,
+            )?;
+        }
+        Ok(out)
+    });
+    match out {
+        Ok(out) => {
+            let state = Mutex::new(CopyState {
+                out: Some(out),
+                pending: vec![Vec::new(); bind_data.columns.len()],
+                blocks: 0,
+            });
+            ffi::duckdb_copy_function_global_init_set_global_state(
+                info,
+                Box::into_raw(Box::new(state)).cast(),
+                Some(drop_boxed::<Mutex<CopyState>>),
+            );
+        }
+        Err(error) => ffi::duckdb_copy_function_global_init_set_error(
+            info,
+            error_message(format!("Cannot create {}: {}", path, error)).as_ptr(),
+        ),
+    }
+}
+
+unsafe fn copy_sink(
+    info: ffi::duckdb_copy_function_sink_info,
+    input: ffi::duckdb_data_chunk,
+) -> Result<(), Box<dyn Error>> {
+    let bind_data = &*ffi::duckdb_copy_function_sink_get_bind_data(info).cast::<CopyBindData>();
+    let state = &*ffi::duckdb_copy_function_sink_get_global_state(info).cast::<Mutex<CopyState>>();
+    let rows = ffi::duckdb_data_chunk_get_size(input) as usize;
+    let chunk: Vec<Vec<ColumnData>> = (0..bind_data.columns.len())
+        .map(|idx| read_vector(ffi::duckdb_data_chunk_get_vector(input, idx as u64), rows))
+        .collect();
+
+    let mut state = state
+        .lock()
+        .map_err(|_| format!("{} writer poisoned", bind_data.format.name()))?;
+    for (pending, values) in state.pending.iter_mut().zip(chunk) {
+        pending.extend(values);
+    }
+    // RowBinary has no blocks, so rows are written as they come.
+    if bind_data.format == CopyFormat::RowBinary {
+        return state.write_block(bind_data);
+    }
+    while state.pending.first().map_or(0, Vec::len) >= bind_data.rows_per_block {
+        let rest: Vec<Vec<ColumnData>> = state
+            .pending
+            .iter_mut()
+            .map(|values| values.split_off(bind_data.rows_per_block))
+            .collect();
+        state.write_block(bind_data)?;
+        state.pending = rest;
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn native_copy_sink(
+    info: ffi::duckdb_copy_function_sink_info,
+    input: ffi::duckdb_data_chunk,
+) {
+    if let Err(error) = copy_sink(info, input) {
+        ffi::duckdb_copy_function_sink_set_error(info, error_message(error).as_ptr());
+    }
+}
+
+unsafe fn copy_finalize(
+    info: ffi::duckdb_copy_function_finalize_info,
+) -> Result<(), Box<dyn Error>> {
+    let bind_data = &*ffi::duckdb_copy_function_finalize_get_bind_data(info).cast::<CopyBindData>();
+    let state =
+        &*ffi::duckdb_copy_function_finalize_get_global_state(info).cast::<Mutex<CopyState>>();
+    let mut state = state
+        .lock()
+        .map_err(|_| format!("{} writer poisoned", bind_data.format.name()))?;
+    // An empty result is still written as a Native block, so the file keeps
+    // the column names and types.
+    if (bind_data.format == CopyFormat::Native && state.blocks == 0)
+        || state
+            .pending
+            .first()
+            .is_some_and(|values| !values.is_empty())
+    {
+        state.write_block(bind_data)?;
+    }
+    if let Some(out) = state.out.take() {
+        out.finish()?.finish()?;
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn native_copy_finalize(info: ffi::duckdb_copy_function_finalize_info) {
+    if let Err(error) = copy_finalize(info) {
+        ffi::duckdb_copy_function_finalize_set_error(info, error_message(error).as_ptr());
+    }
+}
+
+/// Registers `COPY ... TO ... (FORMAT clickhouse_native)` and
+/// `(FORMAT clickhouse_rowbinary)`. Copy functions are registered on a raw
+/// connection, which the `Connection` wrapper does not expose.
+///
+/// # Safety
+///
+/// `db` must be the database the extension is being loaded into.
+pub(crate) unsafe fn register_copy_functions(
+    db: ffi::duckdb_database,
+) -> Result<(), Box<dyn Error>> {
+    let mut con: ffi::duckdb_connection = ptr::null_mut();
+    if ffi::duckdb_connect(db, &mut con) != ffi::duckdb_state_DuckDBSuccess {
+        return Err("Cannot connect to register the ClickHouse copy functions".into());
+    }
+
+    let mut result = Ok(());
+    for format in [CopyFormat::Native, CopyFormat::RowBinary] {
+        let name = CString::new(format.name())?;
+        let mut function = ffi::duckdb_create_copy_function();
+        ffi::duckdb_copy_function_set_name(function, name.as_ptr());
+        ffi::duckdb_copy_function_set_extra_info(
+            function,
+            Box::into_raw(Box::new(format)).cast(),
+            Some(drop_boxed::<CopyFormat>),
+        );
+        ffi::duckdb_copy_function_set_bind(function, Some(native_copy_bind));
+        ffi::duckdb_copy_function_set_global_init(function, Some(native_copy_global_init));
+        ffi::duckdb_copy_function_set_sink(function, Some(native_copy_sink));
+        ffi::duckdb_copy_function_set_finalize(function, Some(native_copy_finalize));
+        let registered = ffi::duckdb_register_copy_function(con, function);
+        ffi::duckdb_destroy_copy_function(&mut function);
+
+        if registered != ffi::duckdb_state_DuckDBSuccess {
+            result = Err(format!("Failed to register the {} copy function", format.name()).into());
+            break;
+        }
+    }
+    ffi::duckdb_disconnect(&mut con);
+    result
+}
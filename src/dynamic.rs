@@ -0,0 +1,674 @@
+use super::{
+    binary_types, geo, low_cardinality, parse_column_type, read_array_offsets, read_bytes,
+    read_column_data, read_row_value as read_value, read_string, read_var_u64, resolve_column,
+    ColumnData, ColumnType, DynamicMode, JsonMode, JsonType, NativeOptions,
+};
+use byteorder::{LittleEndian, ReadBytesExt};
+use chrono::DateTime;
+use serde_json::{Map, Number, Value};
+use std::io::{self, Read};
+
+/// Discriminator of a NULL `Variant` row.
+const NULL_DISCRIMINATOR: u8 = 255;
+
+/// Variant that holds the values of a `Dynamic` column beyond its typed
+/// variants, each encoded as a binary type followed by the value.
+const SHARED_VARIANT: &str = "SharedVariant";
+
+/// Discriminators mode written in a `Variant` prefix. Only the basic mode
+/// (one discriminator per row) is used outside MergeTree parts.
+const DISCRIMINATORS_BASIC: u64 = 0;
+
+/// Structure versions written in a `Dynamic` prefix.
+const DYNAMIC_V1: u64 = 1;
+const DYNAMIC_V2: u64 = 2;
+
+/// Serialization versions written in a `JSON` prefix.
+const OBJECT_V1: u64 = 0;
+const OBJECT_STRING: u64 = 1;
+const OBJECT_V2: u64 = 2;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the serialization prefix that precedes the data of a column in each
+/// block and returns the type the block is laid out with: the variants of a
+/// `Dynamic` column and the dynamic paths of a `JSON` column are only known
+/// from it. Prefixes of nested types come first, in element order.
+pub(crate) fn read_prefix(
+    reader: &mut impl Read,
+    column_type: &ColumnType,
+    binary_types: bool,
+) -> io::Result<ColumnType> {
+    Ok(match column_type {
+        ColumnType::Nullable(inner) => {
+            ColumnType::Nullable(Box::new(read_prefix(reader, inner, binary_types)?))
+        }
+        ColumnType::Array(inner) => {
+            ColumnType::Array(Box::new(read_prefix(reader, inner, binary_types)?))
+        }
+        ColumnType::LowCardinality(_) => {
+            low_cardinality::read_prefix(reader)?;
+            column_type.clone()
+        }
+        ColumnType::Map(key, value) => {
+            let key = read_prefix(reader, key, binary_types)?;
+            let value = read_prefix(reader, value, binary_types)?;
+            ColumnType::Map(Box::new(key), Box::new(value))
+        }
+        ColumnType::Tuple(elements) => {
+            ColumnType::Tuple(read_prefixes(reader, elements, binary_types)?)
+        }
+        ColumnType::Variant(members) => {
+            ColumnType::Variant(read_variant_prefix(reader, members, binary_types)?)
+        }
+        ColumnType::Dynamic(_) => ColumnType::Dynamic(read_dynamic_prefix(reader, binary_types)?),
+        ColumnType::Json(json) => ColumnType::Json(read_json_prefix(reader, json, binary_types)?),
+        other => other.clone(),
+    })
+}
+
+fn read_prefixes(
+    reader: &mut impl Read,
+    elements: &[(String, ColumnType)],
+    binary_types: bool,
+) -> io::Result<Vec<(String, ColumnType)>> {
+    elements
+        .iter()
+        .map(|(name, element_type)| {
+            Ok((
+                name.clone(),
+                read_prefix(reader, element_type, binary_types)?,
+            ))
+        })
+        .collect()
+}
+
+fn read_variant_prefix(
+    reader: &mut impl Read,
+    members: &[(String, ColumnType)],
+    binary_types: bool,
+) -> io::Result<Vec<(String, ColumnType)>> {
+    let mode = reader.read_u64::<LittleEndian>()?;
+    if mode != DISCRIMINATORS_BASIC {
+        return Err(invalid(format!(
+            "Unsupported Variant discriminators mode {}",
+            mode
+        )));
+    }
+    read_prefixes(reader, members, binary_types)
+}
+
+/// Reads the structure of a `Dynamic` column in this block and returns the
+/// members of the `Variant` it is stored as, shared variant included.
+fn read_dynamic_prefix(
+    reader: &mut impl Read,
+    binary_types: bool,
+) -> io::Result<Vec<(String, ColumnType)>> {
+    match reader.read_u64::<LittleEndian>()? {
+        DYNAMIC_V1 => {
+            let _max_dynamic_types = read_var_u64(reader)?;
+        }
+        DYNAMIC_V2 => {}
+        version => {
+            return Err(invalid(format!(
+                "Unsupported Dynamic serialization version {}",
+                version
+            )))
+        }
+    }
+
+    let count = read_var_u64(reader)?;
+    let mut members = Vec::with_capacity(count as usize + 1);
+    for _ in 0..count {
+        let name = if binary_types {
+            binary_types::read_binary_type(reader)?
+        } else {
+            read_string(reader)?
+        };
+        let member_type = parse_column_type(&name).0;
+        members.push((name, member_type));
+    }
+    members.push((SHARED_VARIANT.to_string(), ColumnType::String));
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+
+    read_variant_prefix(reader, &members, binary_types)
+}
+
+fn read_json_prefix(
+    reader: &mut impl Read,
+    json: &JsonType,
+    binary_types: bool,
+) -> io::Result<JsonType> {
+    match reader.read_u64::<LittleEndian>()? {
+        OBJECT_STRING => {
+            return Ok(JsonType {
+                as_string: true,
+                ..json.clone()
+            })
+        }
+        OBJECT_V1 => {
+            let _max_dynamic_paths = read_var_u64(reader)?;
+        }
+        OBJECT_V2 => {}
+        version => {
+            return Err(invalid(format!(
+                "Unsupported JSON serialization version {}",
+                version
+            )))
+        }
+    }
+
+    let count = read_var_u64(reader)?;
+    let paths = (0..count)
+        .map(|_| read_string(reader))
+        .collect::<io::Result<Vec<_>>>()?;
+    let typed_paths = read_prefixes(reader, &json.typed_paths, binary_types)?;
+    let dynamic_paths = paths
+        .into_iter()
+        .map(|path| {
+            let members = read_dynamic_prefix(reader, binary_types)?;
+            Ok((path, ColumnType::Dynamic(members)))
+        })
+        .collect::<io::Result<_>>()?;
+
+    Ok(JsonType {
+        typed_paths,
+        dynamic_paths,
+        as_string: false,
+    })
+}
+
+/// Decodes one value of the shared variant: its binary-encoded type followed
+/// by the value in per-row form.
+fn read_shared_value(bytes: &[u8]) -> io::Result<ColumnData> {
+    let mut cursor = bytes;
+    let name = binary_types::read_binary_type(&mut cursor)?;
+    if name == "Nothing" {
+        return Ok(ColumnData::Null);
+    }
+    let value = read_value(&mut cursor, &parse_column_type(&name).0)?;
+    Ok(ColumnData::Dynamic(name, Box::new(value)))
+}
+
+/// Reads one discriminator per row, then the values of each member for the
+/// rows that hold it, members in order. Returns the member index and value
+/// of every row, `None` for NULL rows.
+fn read_variant_rows(
+    reader: &mut impl Read,
+    members: &[(String, ColumnType)],
+    rows: u64,
+) -> io::Result<Vec<Option<(usize, ColumnData)>>> {
+    let mut discriminators = vec![0u8; rows as usize];
+    reader.read_exact(&mut discriminators)?;
+
+    let mut member_values = Vec::with_capacity(members.len());
+    for (idx, (name, member_type)) in members.iter().enumerate() {
+        let count = discriminators
+            .iter()
+            .filter(|&&d| d as usize == idx)
+            .count() as u64;
+        let values = if name == SHARED_VARIANT {
+            (0..count)
+                .map(|_| read_shared_value(&read_bytes(reader)?))
+                .collect::<io::Result<Vec<_>>>()?
+        } else {
+            read_column_data(reader, member_type, count)?
+        };
+        member_values.push(values.into_iter());
+    }
+
+    discriminators
+        .into_iter()
+        .map(|discriminator| {
+            if discriminator == NULL_DISCRIMINATOR {
+                return Ok(None);
+            }
+            let idx = discriminator as usize;
+            let value = member_values
+                .get_mut(idx)
+                .and_then(|values| values.next())
+                .ok_or_else(|| invalid(format!("Invalid Variant discriminator {}", idx)))?;
+            Ok(Some((idx, value)))
+        })
+        .collect()
+}
+
+pub(crate) fn read_variant_data(
+    reader: &mut impl Read,
+    members: &[(String, ColumnType)],
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    Ok(read_variant_rows(reader, members, rows)?
+        .into_iter()
+        .map(|row| match row {
+            Some((idx, value)) => ColumnData::Variant(idx as u8, Box::new(value)),
+            None => ColumnData::Null,
+        })
+        .collect())
+}
+
+/// Reads a `Dynamic` column laid out with the members of its block prefix.
+/// Each value keeps the name of its type, since members differ per block.
+pub(crate) fn read_dynamic_data(
+    reader: &mut impl Read,
+    members: &[(String, ColumnType)],
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    Ok(read_variant_rows(reader, members, rows)?
+        .into_iter()
+        .map(|row| match row {
+            Some((idx, value)) if members[idx].0 != SHARED_VARIANT => {
+                ColumnData::Dynamic(members[idx].0.clone(), Box::new(value))
+            }
+            Some((_, value)) => value,
+            None => ColumnData::Null,
+        })
+        .collect())
+}
+
+/// Reads a `JSON` column: typed paths, then dynamic paths (as `Dynamic`),
+/// then the shared data holding the remaining paths of each row as a
+/// `Map(String, String)` of binary-encoded values.
+pub(crate) fn read_json_data(
+    reader: &mut impl Read,
+    json: &JsonType,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    if json.as_string {
+        return (0..rows)
+            .map(|_| Ok(ColumnData::String(read_string(reader)?)))
+            .collect();
+    }
+
+    let mut objects: Vec<Vec<(String, ColumnData)>> = vec![Vec::new(); rows as usize];
+    for (path, path_type) in json.typed_paths.iter().chain(&json.dynamic_paths) {
+        let values = read_column_data(reader, path_type, rows)?;
+        let dynamic = matches!(path_type, ColumnType::Dynamic(_));
+        for (object, value) in objects.iter_mut().zip(values) {
+            // A NULL dynamic path is a path the row does not have.
+            if !(dynamic && matches!(value, ColumnData::Null)) {
+                object.push((path.clone(), value));
+            }
+        }
+    }
+
+    let offsets = read_array_offsets(reader, rows)?;
+    let total = offsets.last().copied().unwrap_or(0);
+    let paths = (0..total)
+        .map(|_| read_string(reader))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut values = (0..total)
+        .map(|_| read_shared_value(&read_bytes(reader)?))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter();
+    let mut paths = paths.into_iter();
+    let mut start = 0;
+    for (object, end) in objects.iter_mut().zip(offsets) {
+        for _ in start..end {
+            if let (Some(path), Some(value)) = (paths.next(), values.next()) {
+                object.push((path, value));
+            }
+        }
+        start = end;
+    }
+
+    Ok(objects.into_iter().map(ColumnData::Object).collect())
+}
+
+/// Reads a `Variant`, `Dynamic` or `JSON` value in per-row form.
+pub(crate) fn read_row_value(
+    reader: &mut impl Read,
+    column_type: &ColumnType,
+) -> io::Result<ColumnData> {
+    Ok(match column_type {
+        ColumnType::Variant(members) => {
+            let discriminator = reader.read_u8()?;
+            if discriminator == NULL_DISCRIMINATOR {
+                return Ok(ColumnData::Null);
+            }
+            let (_, member_type) = members.get(discriminator as usize).ok_or_else(|| {
+                invalid(format!("Invalid Variant discriminator {}", discriminator))
+            })?;
+            ColumnData::Variant(discriminator, Box::new(read_value(reader, member_type)?))
+        }
+        ColumnType::Dynamic(_) => {
+            let name = binary_types::read_binary_type(reader)?;
+            if name == "Nothing" {
+                return Ok(ColumnData::Null);
+            }
+            let value = read_value(reader, &parse_column_type(&name).0)?;
+            ColumnData::Dynamic(name, Box::new(value))
+        }
+        ColumnType::Json(json) => {
+            let count = read_var_u64(reader)?;
+            let mut object = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let path = read_string(reader)?;
+                let value = match json.typed_paths.iter().find(|(typed, _)| *typed == path) {
+                    Some((_, path_type)) => read_value(reader, path_type)?,
+                    None => read_row_value(reader, &ColumnType::Dynamic(Vec::new()))?,
+                };
+                object.push((path, value));
+            }
+            ColumnData::Object(object)
+        }
+        other => read_value(reader, other)?,
+    })
+}
+
+/// Exposes a fully read `Dynamic` column as a `Variant` of every type found
+/// in it, or as a STRUCT of the type name and the value as text.
+pub(crate) fn resolve_dynamic(
+    column_type: &mut ColumnType,
+    values: Vec<&mut ColumnData>,
+    options: &NativeOptions,
+) -> io::Result<()> {
+    match options.dynamic_as {
+        DynamicMode::Union => resolve_dynamic_union(column_type, values, options),
+        DynamicMode::Varchar => {
+            for value in values {
+                if let ColumnData::Dynamic(name, inner) = value {
+                    let text = value_text(&parse_column_type(name).0, inner);
+                    *value = ColumnData::Tuple(vec![
+                        ColumnData::String(std::mem::take(name)),
+                        ColumnData::String(text),
+                    ]);
+                }
+            }
+            *column_type = ColumnType::Tuple(vec![
+                ("type".to_string(), ColumnType::String),
+                ("value".to_string(), ColumnType::String),
+            ]);
+            Ok(())
+        }
+    }
+}
+
+/// Exposes a fully read `JSON` column as JSON text or as a `Tuple` of its
+/// typed paths.
+pub(crate) fn resolve_json(
+    column_type: &mut ColumnType,
+    values: Vec<&mut ColumnData>,
+    options: &NativeOptions,
+) -> io::Result<()> {
+    let ColumnType::Json(json) = column_type else {
+        return Ok(());
+    };
+    match options.json_as {
+        JsonMode::Json => {
+            for value in values {
+                if let ColumnData::Object(paths) = value {
+                    let text = Value::Object(json_object(json, paths)).to_string();
+                    *value = ColumnData::String(text);
+                }
+            }
+            Ok(())
+        }
+        JsonMode::Struct => {
+            if json.typed_paths.is_empty() {
+                return Err(invalid(
+                    "JSON column has no typed paths to expose as a STRUCT".to_string(),
+                ));
+            }
+            let typed_paths = std::mem::take(&mut json.typed_paths);
+            let mut values = values;
+            for value in values.iter_mut() {
+                let fields = match &mut **value {
+                    ColumnData::Object(paths) => typed_paths
+                        .iter()
+                        .map(|(typed, _)| {
+                            paths
+                                .iter_mut()
+                                .find(|(path, _)| path == typed)
+                                .map(|(_, value)| std::mem::replace(value, ColumnData::Null))
+                                .unwrap_or(ColumnData::Null)
+                        })
+                        .collect(),
+                    ColumnData::String(_) => {
+                        return Err(invalid(
+                            "JSON written as strings can only be read with json_as := 'json'"
+                                .to_string(),
+                        ))
+                    }
+                    _ => continue,
+                };
+                **value = ColumnData::Tuple(fields);
+            }
+            *column_type = ColumnType::Tuple(typed_paths);
+            resolve_column(column_type, values, options)
+        }
+    }
+}
+
+/// Replaces a `Dynamic` column by a `Variant` of the types its values have,
+/// sorted by name. A column without any value becomes a NULL VARCHAR.
+fn resolve_dynamic_union(
+    column_type: &mut ColumnType,
+    values: Vec<&mut ColumnData>,
+    options: &NativeOptions,
+) -> io::Result<()> {
+    let mut names: Vec<String> = values
+        .iter()
+        .filter_map(|value| match &**value {
+            ColumnData::Dynamic(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+
+    if names.is_empty() {
+        *column_type = ColumnType::Nullable(Box::new(ColumnType::String));
+        return Ok(());
+    }
+    if names.len() > NULL_DISCRIMINATOR as usize {
+        return Err(invalid(format!(
+            "Dynamic column has {} types, more than a UNION can hold",
+            names.len()
+        )));
+    }
+
+    let mut members: Vec<(String, ColumnType)> = names
+        .into_iter()
+        .map(|name| {
+            let member_type = parse_column_type(&name).0;
+            (name, member_type)
+        })
+        .collect();
+    let mut values = values;
+    for value in values.iter_mut() {
+        if let ColumnData::Dynamic(name, inner) = &mut **value {
+            let idx = members
+                .iter()
+                .position(|(member, _)| member == name)
+                .unwrap_or(0);
+            let inner = std::mem::replace(inner.as_mut(), ColumnData::Null);
+            **value = ColumnData::Variant(idx as u8, Box::new(inner));
+        }
+    }
+    for (idx, (_, member_type)) in members.iter_mut().enumerate() {
+        let children = values
+            .iter_mut()
+            .filter_map(|value| match &mut **value {
+                ColumnData::Variant(member, inner) if *member as usize == idx => {
+                    Some(inner.as_mut())
+                }
+                _ => None,
+            })
+            .collect();
+        resolve_column(member_type, children, options)?;
+    }
+    *column_type = ColumnType::Variant(members);
+    Ok(())
+}
+
+fn json_object(json: &JsonType, paths: &[(String, ColumnData)]) -> Map<String, Value> {
+    let mut object = Map::new();
+    for (path, value) in paths {
+        let path_type = json
+            .typed_paths
+            .iter()
+            .find(|(typed, _)| typed == path)
+            .map(|(_, path_type)| path_type)
+            .unwrap_or(&ColumnType::String);
+        insert_path(&mut object, path, json_value(path_type, value));
+    }
+    object
+}
+
+/// Inserts a value at a dotted path, creating the intermediate objects.
+fn insert_path(object: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        Some((head, rest)) => {
+            let entry = object
+                .entry(head)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            if let Value::Object(child) = entry {
+                insert_path(child, rest, value);
+            }
+        }
+        None => {
+            object.insert(path.to_string(), value);
+        }
+    }
+}
+
+fn format_timestamp(ticks: i64, nanos_per_tick: i64, digits: u8) -> String {
+    let nanos = ticks as i128 * nanos_per_tick as i128;
+    let seconds = nanos.div_euclid(1_000_000_000) as i64;
+    let fraction = nanos.rem_euclid(1_000_000_000) as u32;
+    let Some(timestamp) = DateTime::from_timestamp(seconds, fraction) else {
+        return ticks.to_string();
+    };
+    let base = timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+    if digits == 0 {
+        base
+    } else {
+        let scaled = fraction / 10u32.pow(9 - digits as u32);
+        format!("{}.{:0width$}", base, scaled, width = digits as usize)
+    }
+}
+
+fn format_uuid(value: i128) -> String {
+    let hex = format!("{:032x}", (value as u128) ^ (1u128 << 127));
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn float_value(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+/// Renders a decoded value as JSON, the way ClickHouse prints it inside a
+/// JSON document.
+fn json_value(column_type: &ColumnType, value: &ColumnData) -> Value {
+    match (column_type, value) {
+        (_, ColumnData::Null) => Value::Null,
+        (_, ColumnData::Dynamic(name, inner)) => json_value(&parse_column_type(name).0, inner),
+        (ColumnType::Nullable(inner), value) => json_value(inner, value),
+        (ColumnType::Array(inner), ColumnData::Array(items)) => {
+            Value::Array(items.iter().map(|item| json_value(inner, item)).collect())
+        }
+        (ColumnType::Map(key, value_type), ColumnData::Array(entries)) => Value::Object(
+            entries
+                .iter()
+                .filter_map(|entry| match entry {
+                    ColumnData::Tuple(pair) if pair.len() == 2 => {
+                        Some((value_text(key, &pair[0]), json_value(value_type, &pair[1])))
+                    }
+                    _ => None,
+                })
+                .collect(),
+        ),
+        (ColumnType::Tuple(elements), ColumnData::Tuple(items)) => {
+            let unnamed = elements
+                .iter()
+                .enumerate()
+                .all(|(idx, (name, _))| *name == (idx + 1).to_string());
+            if unnamed {
+                Value::Array(
+                    elements
+                        .iter()
+                        .zip(items)
+                        .map(|((_, element_type), item)| json_value(element_type, item))
+                        .collect(),
+                )
+            } else {
+                Value::Object(
+                    elements
+                        .iter()
+                        .zip(items)
+                        .map(|((name, element_type), item)| {
+                            (name.clone(), json_value(element_type, item))
+                        })
+                        .collect(),
+                )
+            }
+        }
+        (ColumnType::Variant(members), ColumnData::Variant(idx, inner)) => {
+            match members.get(*idx as usize) {
+                Some((_, member_type)) => json_value(member_type, inner),
+                None => Value::Null,
+            }
+        }
+        (ColumnType::Geo(kind), value) => json_value(&geo::storage_type(*kind), value),
+        (ColumnType::Json(json), ColumnData::Object(paths)) => {
+            Value::Object(json_object(json, paths))
+        }
+        (ColumnType::Json(_), ColumnData::String(text)) => {
+            serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.clone()))
+        }
+        (ColumnType::Decimal(_, scale), ColumnData::Decimal(v)) => {
+            float_value(*v as f64 / 10f64.powi(*scale as i32))
+        }
+        (ColumnType::DateTime64(precision, tz), ColumnData::Timestamp(ticks)) => {
+            let nanos_per_tick = if *precision > 6 && tz.is_none() {
+                1
+            } else {
+                1_000
+            };
+            Value::String(format_timestamp(*ticks, nanos_per_tick, *precision))
+        }
+        (_, ColumnData::Timestamp(micros)) => Value::String(format_timestamp(*micros, 1_000, 0)),
+        (_, ColumnData::Date(days)) => Value::String(
+            DateTime::from_timestamp(*days as i64 * 86_400, 0)
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| days.to_string()),
+        ),
+        (_, ColumnData::Uuid(v)) => Value::String(format_uuid(*v)),
+        (_, ColumnData::String(s) | ColumnData::Enum8(s) | ColumnData::Enum16(s)) => {
+            Value::String(s.clone())
+        }
+        (_, ColumnData::UInt8(v)) => Value::from(*v),
+        (_, ColumnData::UInt16(v)) => Value::from(*v),
+        (_, ColumnData::UInt32(v)) => Value::from(*v),
+        (_, ColumnData::UInt64(v)) => Value::from(*v),
+        (_, ColumnData::Int(v) | ColumnData::Int32(v)) => Value::from(*v),
+        (_, ColumnData::Int8(v)) => Value::from(*v),
+        (_, ColumnData::Int16(v)) => Value::from(*v),
+        (_, ColumnData::Int64(v)) => Value::from(*v),
+        (_, ColumnData::Float32(v)) => float_value(*v as f64),
+        (_, ColumnData::Float64(v)) => float_value(*v),
+        (_, ColumnData::Bool(v)) => Value::Bool(*v),
+        _ => Value::Null,
+    }
+}
+
+/// Renders a value as text: strings as they are, anything else as JSON.
+fn value_text(column_type: &ColumnType, value: &ColumnData) -> String {
+    match json_value(column_type, value) {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
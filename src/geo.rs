@@ -0,0 +1,134 @@
+use super::{ColumnData, ColumnType, GeoKind, GeoMode, NativeOptions};
+use std::io;
+
+const WKB_POINT: u32 = 1;
+const WKB_LINESTRING: u32 = 2;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTILINESTRING: u32 = 5;
+const WKB_MULTIPOLYGON: u32 = 6;
+
+/// The tuples and arrays of Float64 a geo type is stored as. Points become a
+/// STRUCT with `x` and `y` fields.
+pub(crate) fn storage_type(kind: GeoKind) -> ColumnType {
+    let point = ColumnType::Tuple(vec![
+        ("x".to_string(), ColumnType::Float64),
+        ("y".to_string(), ColumnType::Float64),
+    ]);
+    let array = |inner| ColumnType::Array(Box::new(inner));
+    match kind {
+        GeoKind::Point => point,
+        GeoKind::Ring | GeoKind::LineString => array(point),
+        GeoKind::MultiLineString | GeoKind::Polygon => array(array(point)),
+        GeoKind::MultiPolygon => array(array(array(point))),
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, geometry_type: u32) {
+    out.push(1);
+    out.extend_from_slice(&geometry_type.to_le_bytes());
+}
+
+fn write_count(out: &mut Vec<u8>, count: usize) {
+    out.extend_from_slice(&(count as u32).to_le_bytes());
+}
+
+fn items(value: &ColumnData) -> &[ColumnData] {
+    match value {
+        ColumnData::Array(items) => items,
+        _ => &[],
+    }
+}
+
+fn write_point(out: &mut Vec<u8>, value: &ColumnData) {
+    let (x, y) = match value {
+        ColumnData::Tuple(coordinates) => match coordinates.as_slice() {
+            [ColumnData::Float64(x), ColumnData::Float64(y)] => (*x, *y),
+            _ => (f64::NAN, f64::NAN),
+        },
+        _ => (f64::NAN, f64::NAN),
+    };
+    out.extend_from_slice(&x.to_le_bytes());
+    out.extend_from_slice(&y.to_le_bytes());
+}
+
+fn write_points(out: &mut Vec<u8>, value: &ColumnData) {
+    let points = items(value);
+    write_count(out, points.len());
+    for point in points {
+        write_point(out, point);
+    }
+}
+
+fn write_rings(out: &mut Vec<u8>, value: &ColumnData) {
+    let rings = items(value);
+    write_count(out, rings.len());
+    for ring in rings {
+        write_points(out, ring);
+    }
+}
+
+/// Encodes a geo value as little-endian WKB. A `Ring` is written as a
+/// polygon with a single ring, as ClickHouse's `wkt` does.
+pub(crate) fn to_wkb(kind: GeoKind, value: &ColumnData) -> Vec<u8> {
+    let mut out = Vec::new();
+    match kind {
+        GeoKind::Point => {
+            write_header(&mut out, WKB_POINT);
+            write_point(&mut out, value);
+        }
+        GeoKind::LineString => {
+            write_header(&mut out, WKB_LINESTRING);
+            write_points(&mut out, value);
+        }
+        GeoKind::Ring => {
+            write_header(&mut out, WKB_POLYGON);
+            write_count(&mut out, 1);
+            write_points(&mut out, value);
+        }
+        GeoKind::Polygon => {
+            write_header(&mut out, WKB_POLYGON);
+            write_rings(&mut out, value);
+        }
+        GeoKind::MultiLineString => {
+            write_header(&mut out, WKB_MULTILINESTRING);
+            let lines = items(value);
+            write_count(&mut out, lines.len());
+            for line in lines {
+                write_header(&mut out, WKB_LINESTRING);
+                write_points(&mut out, line);
+            }
+        }
+        GeoKind::MultiPolygon => {
+            write_header(&mut out, WKB_MULTIPOLYGON);
+            let polygons = items(value);
+            write_count(&mut out, polygons.len());
+            for polygon in polygons {
+                write_header(&mut out, WKB_POLYGON);
+                write_rings(&mut out, polygon);
+            }
+        }
+    }
+    out
+}
+
+/// Exposes a fully read geo column as nested STRUCT/LIST values or as WKB.
+pub(crate) fn resolve_geo(
+    column_type: &mut ColumnType,
+    values: Vec<&mut ColumnData>,
+    options: &NativeOptions,
+) -> io::Result<()> {
+    let ColumnType::Geo(kind) = *column_type else {
+        return Ok(());
+    };
+    match options.geo_as {
+        GeoMode::Struct => *column_type = storage_type(kind),
+        GeoMode::Wkb => {
+            for value in values {
+                if !matches!(value, ColumnData::Null) {
+                    *value = ColumnData::Blob(to_wkb(kind, value));
+                }
+            }
+        }
+    }
+    Ok(())
+}
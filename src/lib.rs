@@ -1,50 +1,196 @@
 use byteorder::{LittleEndian, ReadBytesExt};
 use duckdb::{
-    core::{DataChunkHandle, Inserter, LogicalTypeHandle, LogicalTypeId},
+    core::{
+        DataChunkHandle, FlatVector, Inserter, ListVector, LogicalTypeHandle, LogicalTypeId,
+        StructVector,
+    },
     vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
     Connection, Result,
 };
-use duckdb_loadable_macros::duckdb_entrypoint_c_api;
 use libduckdb_sys as ffi;
 use std::{
     error::Error,
+    ffi::CString,
     fs::File,
-    io::{self, BufReader, Read, Seek},
+    io::{self, BufRead, BufReader, Read},
 };
 
+mod aggregate;
+mod binary_types;
+mod blob;
 mod clickhouse_scan;
+mod coerce;
+mod compression;
+mod copy_to;
+mod dynamic;
+mod geo;
+mod log_table;
+mod low_cardinality;
+mod metadata;
+mod nested;
+mod part;
+mod pruning;
+mod replacement_scan;
+mod rowbinary;
+mod table_dir;
+mod table_metadata;
+mod writer;
+
+/// Leading bytes of a whole-file zstd frame and of a gzip member.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Set on the last group size of a sparse offsets stream.
+const END_OF_GRANULE_FLAG: u64 = 1 << 62;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum ColumnType {
     String,
+    FixedString(usize),
     UInt8,
+    UInt16,
+    UInt32,
     UInt64,
     Int,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    Bool,
+    Date,
+    Date32,
+    DateTime(Option<String>),
+    DateTime64(u8, Option<String>),
+    Decimal(u8, u8),
+    Time,
+    Time64(u8),
+    Interval(IntervalKind),
+    Uuid,
     Enum8(EnumType),
+    Enum16(EnumType),
+    Nullable(Box<ColumnType>),
+    LowCardinality(Box<ColumnType>),
+    Array(Box<ColumnType>),
+    Tuple(Vec<(String, ColumnType)>),
+    Map(Box<ColumnType>, Box<ColumnType>),
+    Variant(Vec<(String, ColumnType)>),
+    Dynamic(Vec<(String, ColumnType)>),
+    Json(JsonType),
+    Geo(GeoKind),
+    AggregateFunction(AggregateType),
+    /// A column cast to a DuckDB type by `columns` or `types_as`.
+    Cast(coerce::CastType),
     Unsupported(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum ColumnData {
     String(String),
     UInt8(u8),
+    UInt16(u16),
+    UInt32(u32),
     UInt64(u64),
     Int(i32),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    Bool(bool),
+    Date(i32),
+    Timestamp(i64),
+    Time(i64),
+    Interval(ffi::duckdb_interval),
+    Decimal(i128),
+    /// An integer cast to a DuckDB integer type, written in the width of
+    /// that type.
+    HugeInt(i128),
+    Uuid(i128),
     Enum8(String),
+    Enum16(String),
+    Array(Vec<ColumnData>),
+    Tuple(Vec<ColumnData>),
+    Variant(u8, Box<ColumnData>),
+    Dynamic(String, Box<ColumnData>),
+    Object(Vec<(String, ColumnData)>),
+    Blob(Vec<u8>),
+    /// Raw state of an aggregate function and its finalized value.
+    Aggregate(Vec<u8>, Box<ColumnData>),
+    Null,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct EnumValue {
     name: String,
-    value: i8,
+    value: i16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct EnumType {
     values: Vec<EnumValue>,
 }
 
+/// Typed paths of a `JSON` column. While a block is read it also holds the
+/// dynamic paths listed in the block's prefix.
+#[derive(Debug, Clone, Default)]
+struct JsonType {
+    typed_paths: Vec<(String, ColumnType)>,
+    dynamic_paths: Vec<(String, ColumnType)>,
+    /// Written as JSON strings (`output_format_native_write_json_as_string`).
+    as_string: bool,
+}
+
+/// Unit of an `Interval*` type, whose values are an Int64 count of units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IntervalKind {
+    Nanosecond,
+    Microsecond,
+    Millisecond,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// Function name and argument types of an `AggregateFunction` column.
+#[derive(Debug, Clone)]
+struct AggregateType {
+    function: String,
+    arguments: Vec<ColumnType>,
+}
+
+/// ClickHouse geo types, stored as tuples and arrays of Float64.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GeoKind {
+    Point,
+    Ring,
+    LineString,
+    MultiLineString,
+    Polygon,
+    MultiPolygon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SerializationKind {
+    Default,
+    Sparse,
+}
+
+/// Serialization kind of a column, with one entry per element for tuples.
+#[derive(Debug, Clone, PartialEq)]
+struct SerializationInfo {
+    kind: SerializationKind,
+    elements: Vec<SerializationInfo>,
+}
+
 #[derive(Debug)]
 struct Column {
     name: String,
@@ -52,27 +198,340 @@ struct Column {
     data: Vec<ColumnData>,
 }
 
+#[derive(Debug, Clone, Default)]
+struct NativeOptions {
+    /// Whether column types are written with ClickHouse's binary type
+    /// encoding. Detected from the first column header when unset.
+    binary_types: Option<bool>,
+    dynamic_as: DynamicMode,
+    json_as: JsonMode,
+    geo_as: GeoMode,
+    /// Regroup the flattened `n.a`, `n.b` columns of a `Nested` into one
+    /// LIST of STRUCT column.
+    group_nested: bool,
+    aggregate_as: AggregateMode,
+}
+
+impl NativeOptions {
+    /// Reads the scan options shared by the Native table functions.
+    fn from_bind(bind: &BindInfo) -> Result<Self, Box<dyn Error>> {
+        Ok(NativeOptions {
+            binary_types: bind.get_named_parameter("binary_types").map(|v| v.to_bool()),
+            dynamic_as: match bind.get_named_parameter("dynamic_as").map(|v| v.to_string()) {
+                None => DynamicMode::Union,
+                Some(mode) => match mode.to_lowercase().as_str() {
+                    "union" => DynamicMode::Union,
+                    "varchar" => DynamicMode::Varchar,
+                    _ => return Err(format!("Unknown dynamic_as mode '{}'", mode).into()),
+                },
+            },
+            json_as: match bind.get_named_parameter("json_as").map(|v| v.to_string()) {
+                None => JsonMode::Json,
+                Some(mode) => match mode.to_lowercase().as_str() {
+                    "json" => JsonMode::Json,
+                    "struct" => JsonMode::Struct,
+                    _ => return Err(format!("Unknown json_as mode '{}'", mode).into()),
+                },
+            },
+            geo_as: match bind.get_named_parameter("geo_as").map(|v| v.to_string()) {
+                None => GeoMode::Struct,
+                Some(mode) => match mode.to_lowercase().as_str() {
+                    "struct" => GeoMode::Struct,
+                    "wkb" => GeoMode::Wkb,
+                    _ => return Err(format!("Unknown geo_as mode '{}'", mode).into()),
+                },
+            },
+            group_nested: bind
+                .get_named_parameter("group_nested")
+                .is_some_and(|v| v.to_bool()),
+            aggregate_as: match bind.get_named_parameter("aggregate_as").map(|v| v.to_string()) {
+                None => AggregateMode::Value,
+                Some(mode) => match mode.to_lowercase().as_str() {
+                    "value" => AggregateMode::Value,
+                    "state" => AggregateMode::State,
+                    _ => return Err(format!("Unknown aggregate_as mode '{}'", mode).into()),
+                },
+            },
+        })
+    }
+
+    fn named_parameters() -> Vec<(String, LogicalTypeHandle)> {
+        vec![
+            (
+                "binary_types".to_string(),
+                LogicalTypeHandle::from(LogicalTypeId::Boolean),
+            ),
+            (
+                "dynamic_as".to_string(),
+                LogicalTypeHandle::from(LogicalTypeId::Varchar),
+            ),
+            (
+                "json_as".to_string(),
+                LogicalTypeHandle::from(LogicalTypeId::Varchar),
+            ),
+            (
+                "geo_as".to_string(),
+                LogicalTypeHandle::from(LogicalTypeId::Varchar),
+            ),
+            (
+                "group_nested".to_string(),
+                LogicalTypeHandle::from(LogicalTypeId::Boolean),
+            ),
+            (
+                "aggregate_as".to_string(),
+                LogicalTypeHandle::from(LogicalTypeId::Varchar),
+            ),
+        ]
+    }
+}
+
+/// How `Dynamic` columns are exposed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum DynamicMode {
+    /// A UNION of every type found in the file.
+    #[default]
+    Union,
+    /// A STRUCT of the ClickHouse type name and the value as text.
+    Varchar,
+}
+
+/// How `JSON` columns are exposed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum JsonMode {
+    /// DuckDB's JSON type, re-serialised from the paths of each row.
+    #[default]
+    Json,
+    /// A STRUCT of the typed paths declared in the column type.
+    Struct,
+}
+
+/// How `AggregateFunction` columns are exposed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum AggregateMode {
+    /// The finalized value for states the reader can decode, the raw state
+    /// as a BLOB otherwise.
+    #[default]
+    Value,
+    /// The raw serialized state as a BLOB.
+    State,
+}
+
+/// How geo columns are exposed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum GeoMode {
+    /// Nested STRUCT(x, y) points in LISTs.
+    #[default]
+    Struct,
+    /// WKB in a BLOB, for `ST_GeomFromWKB`.
+    Wkb,
+}
+
 #[derive(Debug)]
 struct ClickHouseBindData {
     filepath: String,
+    options: NativeOptions,
+    overrides: coerce::TypeOverrides,
+    /// Set when the file is a pipe or a device, opened once in `bind`.
+    streamed: Option<StreamedInput>,
 }
 
-#[derive(Debug)]
-struct ClickHouseInitData {
+/// The blocks of a stream after the one it was bound from, decoded as the
+/// scan reaches them.
+trait ReadBlock: Send {
+    /// Returns the columns of the next block, or `None` at the end.
+    fn read_block(&mut self) -> io::Result<Option<Vec<Column>>>;
+}
+
+/// An input that cannot be opened a second time, such as `/dev/stdin` or a
+/// named pipe. `bind` decodes its first block for the schema and keeps the
+/// open reader, which the scan continues from.
+struct StreamedInput(std::sync::Mutex<Option<ScanState>>);
+
+impl std::fmt::Debug for StreamedInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StreamedInput")
+    }
+}
+
+impl StreamedInput {
+    /// Whether `path` is read as a stream rather than opened again in `init`.
+    fn is_stream(path: &str) -> bool {
+        !std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file())
+    }
+
+    fn new(first: Vec<Column>, rest: Box<dyn ReadBlock>) -> Self {
+        StreamedInput(std::sync::Mutex::new(Some(ScanState {
+            columns: first,
+            current_row: 0,
+            rest: Some(rest),
+        })))
+    }
+
+    fn take(&self, path: &str) -> io::Result<ClickHouseInitData> {
+        let state = self
+            .0
+            .lock()
+            .ok()
+            .and_then(|mut input| input.take())
+            .ok_or_else(|| {
+                io::Error::other(format!("{} is a stream and was already scanned", path))
+            })?;
+        Ok(ClickHouseInitData(std::sync::Mutex::new(state)))
+    }
+}
+
+/// Checks that a block read after the one a stream was bound from has the
+/// same columns, since the scan cannot change its schema.
+fn check_block_types(bound: &[Column], block: &[Column], block_index: usize) -> io::Result<()> {
+    let same = bound.len() == block.len()
+        && bound.iter().zip(block).all(|(a, b)| {
+            a.name == b.name && format!("{:?}", a.type_) == format!("{:?}", b.type_)
+        });
+    if same {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Block {} of the stream has other column types than the first block, \
+                 which the query was bound with",
+                block_index + 1
+            ),
+        ))
+    }
+}
+
+struct ScanState {
     columns: Vec<Column>,
-    current_row: std::sync::atomic::AtomicUsize,
-    total_rows: usize,
-    done: std::sync::atomic::AtomicBool,
+    current_row: usize,
+    /// The rest of a streamed input, read once `columns` are scanned.
+    rest: Option<Box<dyn ReadBlock>>,
 }
 
-fn read_string(reader: &mut impl Read) -> io::Result<String> {
+struct ClickHouseInitData(std::sync::Mutex<ScanState>);
+
+impl ClickHouseInitData {
+    fn new(columns: Vec<Column>) -> Self {
+        ClickHouseInitData(std::sync::Mutex::new(ScanState {
+            columns,
+            current_row: 0,
+            rest: None,
+        }))
+    }
+
+    /// Writes the next rows to `output`, reading the next block of a
+    /// streamed input once the current one is scanned.
+    fn scan(&self, output: &mut DataChunkHandle) -> io::Result<()> {
+        let mut state = self
+            .0
+            .lock()
+            .map_err(|_| io::Error::other("A previous scan of the file failed"))?;
+        let state = &mut *state;
+
+        loop {
+            let total_rows = state.columns.first().map_or(0, |column| column.data.len());
+            if state.current_row < total_rows {
+                break;
+            }
+            let next = match state.rest.as_mut() {
+                Some(rest) => rest.read_block()?,
+                None => None,
+            };
+            match next {
+                Some(columns) => {
+                    state.columns = columns;
+                    state.current_row = 0;
+                }
+                None => {
+                    state.rest = None;
+                    output.set_len(0);
+                    return Ok(());
+                }
+            }
+        }
+
+        let current_row = state.current_row;
+        let batch_size = 1024.min(state.columns[0].data.len() - current_row);
+
+        for (col_idx, column) in state.columns.iter().enumerate() {
+            let values: Vec<&ColumnData> = column.data[current_row..current_row + batch_size]
+                .iter()
+                .collect();
+            write_column(output, col_idx, &column.type_, &values);
+        }
+
+        state.current_row += batch_size;
+        output.set_len(batch_size);
+        Ok(())
+    }
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
     let len = read_var_u64(reader)? as usize;
     let mut buffer = vec![0; len];
     reader.read_exact(&mut buffer)?;
-    Ok(String::from_utf8_lossy(&buffer)
-        .replace('\0', "")
-        .replace('\u{FFFD}', "")
-        .to_string())
+    Ok(buffer)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let buffer = read_bytes(reader)?;
+    Ok(String::from_utf8_lossy(&buffer).replace(['\0', '\u{FFFD}'], ""))
+}
+
+/// Splits type parameters on top-level commas, leaving commas inside nested
+/// parentheses and quoted names alone.
+fn split_type_params(params: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for c in params.chars() {
+        if quoted {
+            current.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '\'' {
+                quoted = false;
+            }
+            continue;
+        }
+        match c {
+            '\'' => {
+                quoted = true;
+                current.push(c);
+            }
+            '(' => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' => {
+                depth -= 1;
+                current.push(c);
+            }
+            ',' if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .trim()
+        .trim_matches('\'')
+        .replace("\\'", "'")
+        .replace("\\\\", "\\")
 }
 
 fn parse_enum_values(params: &str) -> Option<EnumType> {
@@ -83,16 +542,16 @@ fn parse_enum_values(params: &str) -> Option<EnumType> {
     }
 
     let mut values = Vec::new();
-    for pair in inner.split(',') {
-        let parts: Vec<&str> = pair.split('=').collect();
-        if parts.len() != 2 {
+    for pair in split_type_params(inner) {
+        let Some((name, value)) = pair.rsplit_once('=') else {
             continue;
-        }
-
-        let name = parts[0].trim().trim_matches('\'').to_string();
+        };
 
-        if let Ok(value) = parts[1].trim().parse::<i8>() {
-            values.push(EnumValue { name, value });
+        if let Ok(value) = value.trim().parse::<i16>() {
+            values.push(EnumValue {
+                name: unquote(name),
+                value,
+            });
         }
     }
 
@@ -103,7 +562,47 @@ fn parse_enum_values(params: &str) -> Option<EnumType> {
     }
 }
 
+/// Splits a tuple element such as `a String` or `` `a b` String `` into its
+/// name and type. Unnamed elements are numbered from 1, like ClickHouse does.
+fn parse_tuple_element(element: &str, position: usize) -> (String, ColumnType) {
+    let element = element.trim();
+    if let Some(rest) = element.strip_prefix('`') {
+        if let Some((name, type_str)) = rest.split_once('`') {
+            return (name.to_string(), parse_column_type(type_str.trim()).0);
+        }
+    }
+
+    let type_start = element.find('(').unwrap_or(element.len());
+    match element[..type_start].find(' ') {
+        Some(idx) => (
+            element[..idx].to_string(),
+            parse_column_type(element[idx..].trim()).0,
+        ),
+        None => (position.to_string(), parse_column_type(element).0),
+    }
+}
+
+/// Collects the typed paths of a `JSON(...)` type, skipping settings such as
+/// `max_dynamic_paths=N` and `SKIP` clauses. Paths are sorted, which is the
+/// order their data is written in.
+fn parse_json_type(params: &[String]) -> JsonType {
+    let mut typed_paths: Vec<(String, ColumnType)> = params
+        .iter()
+        .filter(|param| {
+            let head = param.split(' ').next().unwrap_or_default();
+            head != "SKIP" && !head.contains('=')
+        })
+        .map(|param| parse_tuple_element(param, 0))
+        .collect();
+    typed_paths.sort_by(|a, b| a.0.cmp(&b.0));
+    JsonType {
+        typed_paths,
+        ..JsonType::default()
+    }
+}
+
 fn parse_column_type(type_str: &str) -> (ColumnType, Option<String>) {
+    let type_str = type_str.trim();
     let params_start = type_str.find('(');
     let base_type = match params_start {
         Some(idx) => &type_str[..idx],
@@ -118,33 +617,312 @@ fn parse_column_type(type_str: &str) -> (ColumnType, Option<String>) {
         }
     });
 
-    let column_type = match base_type {
-        "String" => ColumnType::String,
-        "UInt8" => ColumnType::UInt8,
-        "UInt64" => ColumnType::UInt64,
-        "Int" => ColumnType::Int,
-        "Enum8" => {
+    let args = params
+        .as_deref()
+        .and_then(|p| p.strip_prefix('(')?.strip_suffix(')'))
+        .map(split_type_params)
+        .unwrap_or_default();
+
+    let column_type = match (base_type, args.as_slice()) {
+        ("String", _) => ColumnType::String,
+        ("FixedString", [size]) => match size.parse() {
+            Ok(size) => ColumnType::FixedString(size),
+            Err(_) => ColumnType::Unsupported(type_str.to_string()),
+        },
+        ("UInt8", _) => ColumnType::UInt8,
+        ("UInt16", _) => ColumnType::UInt16,
+        ("UInt32", _) => ColumnType::UInt32,
+        ("UInt64", _) => ColumnType::UInt64,
+        ("Int", _) => ColumnType::Int,
+        ("Int8", _) => ColumnType::Int8,
+        ("Int16", _) => ColumnType::Int16,
+        ("Int32", _) => ColumnType::Int32,
+        ("Int64", _) => ColumnType::Int64,
+        ("Float32", _) => ColumnType::Float32,
+        ("Float64", _) => ColumnType::Float64,
+        ("Bool", _) => ColumnType::Bool,
+        ("Date", _) => ColumnType::Date,
+        ("Date32", _) => ColumnType::Date32,
+        ("DateTime", []) => ColumnType::DateTime(None),
+        ("DateTime", [tz]) => ColumnType::DateTime(Some(unquote(tz))),
+        ("DateTime64", [precision, rest @ ..]) => match precision.parse() {
+            Ok(precision) if precision <= 9 => {
+                ColumnType::DateTime64(precision, rest.first().map(|tz| unquote(tz)))
+            }
+            _ => ColumnType::Unsupported(type_str.to_string()),
+        },
+        ("Decimal", [precision, scale]) => match (precision.parse(), scale.parse()) {
+            (Ok(precision), Ok(scale)) if scale <= precision && precision <= 76 => {
+                ColumnType::Decimal(precision, scale)
+            }
+            _ => ColumnType::Unsupported(type_str.to_string()),
+        },
+        ("Decimal32" | "Decimal64" | "Decimal128" | "Decimal256", [scale]) => {
+            let precision = match base_type {
+                "Decimal32" => 9,
+                "Decimal64" => 18,
+                "Decimal128" => 38,
+                _ => 76,
+            };
+            match scale.parse() {
+                Ok(scale) if scale <= precision => ColumnType::Decimal(precision, scale),
+                _ => ColumnType::Unsupported(type_str.to_string()),
+            }
+        }
+        ("Time", []) => ColumnType::Time,
+        ("Time64", [precision]) => match precision.parse() {
+            Ok(precision) if precision <= 9 => ColumnType::Time64(precision),
+            _ => ColumnType::Unsupported(type_str.to_string()),
+        },
+        ("IntervalNanosecond", _) => ColumnType::Interval(IntervalKind::Nanosecond),
+        ("IntervalMicrosecond", _) => ColumnType::Interval(IntervalKind::Microsecond),
+        ("IntervalMillisecond", _) => ColumnType::Interval(IntervalKind::Millisecond),
+        ("IntervalSecond", _) => ColumnType::Interval(IntervalKind::Second),
+        ("IntervalMinute", _) => ColumnType::Interval(IntervalKind::Minute),
+        ("IntervalHour", _) => ColumnType::Interval(IntervalKind::Hour),
+        ("IntervalDay", _) => ColumnType::Interval(IntervalKind::Day),
+        ("IntervalWeek", _) => ColumnType::Interval(IntervalKind::Week),
+        ("IntervalMonth", _) => ColumnType::Interval(IntervalKind::Month),
+        ("IntervalQuarter", _) => ColumnType::Interval(IntervalKind::Quarter),
+        ("IntervalYear", _) => ColumnType::Interval(IntervalKind::Year),
+        ("UUID", _) => ColumnType::Uuid,
+        ("Enum8" | "Enum16", _) => {
             if let Some(ref p) = params {
                 if let Some(enum_type) = parse_enum_values(p) {
-                    ColumnType::Enum8(enum_type)
+                    if base_type == "Enum8" {
+                        ColumnType::Enum8(enum_type)
+                    } else {
+                        ColumnType::Enum16(enum_type)
+                    }
                 } else {
-                    ColumnType::Unsupported("Invalid Enum8".to_string())
+                    ColumnType::Unsupported(format!("Invalid {}", base_type))
                 }
             } else {
-                ColumnType::Unsupported("Invalid Enum8".to_string())
+                ColumnType::Unsupported(format!("Invalid {}", base_type))
             }
         }
-        other => ColumnType::Unsupported(other.to_string()),
+        ("Nullable", [inner]) => ColumnType::Nullable(Box::new(parse_column_type(inner).0)),
+        ("LowCardinality", [inner]) => {
+            ColumnType::LowCardinality(Box::new(parse_column_type(inner).0))
+        }
+        ("Array", [inner]) => ColumnType::Array(Box::new(parse_column_type(inner).0)),
+        ("Nested", elements) if !elements.is_empty() => {
+            ColumnType::Array(Box::new(ColumnType::Tuple(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, element)| parse_tuple_element(element, idx + 1))
+                    .collect(),
+            )))
+        }
+        ("SimpleAggregateFunction", [_, inner]) => parse_column_type(inner).0,
+        ("AggregateFunction", args) if !args.is_empty() => {
+            ColumnType::AggregateFunction(aggregate::parse_aggregate_function(args))
+        }
+        ("Tuple", elements) if !elements.is_empty() => ColumnType::Tuple(
+            elements
+                .iter()
+                .enumerate()
+                .map(|(idx, element)| parse_tuple_element(element, idx + 1))
+                .collect(),
+        ),
+        ("Map", [key, value]) => ColumnType::Map(
+            Box::new(parse_column_type(key).0),
+            Box::new(parse_column_type(value).0),
+        ),
+        ("Variant", members) if !members.is_empty() => {
+            let mut members: Vec<(String, ColumnType)> = members
+                .iter()
+                .map(|member| (member.clone(), parse_column_type(member).0))
+                .collect();
+            // Discriminators follow the members sorted by type name.
+            members.sort_by(|a, b| a.0.cmp(&b.0));
+            ColumnType::Variant(members)
+        }
+        ("Dynamic", _) => ColumnType::Dynamic(Vec::new()),
+        ("JSON", params) => ColumnType::Json(parse_json_type(params)),
+        ("Point", _) => ColumnType::Geo(GeoKind::Point),
+        ("Ring", _) => ColumnType::Geo(GeoKind::Ring),
+        ("LineString", _) => ColumnType::Geo(GeoKind::LineString),
+        ("MultiLineString", _) => ColumnType::Geo(GeoKind::MultiLineString),
+        ("Polygon", _) => ColumnType::Geo(GeoKind::Polygon),
+        ("MultiPolygon", _) => ColumnType::Geo(GeoKind::MultiPolygon),
+        (other, _) => ColumnType::Unsupported(other.to_string()),
     };
 
     (column_type, params)
 }
 
+fn enum_name(enum_type: &EnumType, value: i16) -> String {
+    enum_type
+        .values
+        .iter()
+        .find(|ev| ev.value == value)
+        .map(|ev| ev.name.clone())
+        .unwrap_or_else(|| format!("Unknown({})", value))
+}
+
+fn default_value(column_type: &ColumnType) -> ColumnData {
+    match column_type {
+        ColumnType::String | ColumnType::FixedString(_) => ColumnData::String(String::new()),
+        ColumnType::UInt8 => ColumnData::UInt8(0),
+        ColumnType::UInt16 => ColumnData::UInt16(0),
+        ColumnType::UInt32 => ColumnData::UInt32(0),
+        ColumnType::UInt64 => ColumnData::UInt64(0),
+        ColumnType::Int => ColumnData::Int(0),
+        ColumnType::Int8 => ColumnData::Int8(0),
+        ColumnType::Int16 => ColumnData::Int16(0),
+        ColumnType::Int32 => ColumnData::Int32(0),
+        ColumnType::Int64 => ColumnData::Int64(0),
+        ColumnType::Float32 => ColumnData::Float32(0.0),
+        ColumnType::Float64 => ColumnData::Float64(0.0),
+        ColumnType::Bool => ColumnData::Bool(false),
+        ColumnType::Date | ColumnType::Date32 => ColumnData::Date(0),
+        ColumnType::DateTime(_) | ColumnType::DateTime64(..) => ColumnData::Timestamp(0),
+        ColumnType::Decimal(precision, _) if *precision > 38 => ColumnData::Float64(0.0),
+        ColumnType::Decimal(..) => ColumnData::Decimal(0),
+        ColumnType::Time | ColumnType::Time64(_) => ColumnData::Time(0),
+        ColumnType::Interval(kind) => ColumnData::Interval(interval(*kind, 0)),
+        ColumnType::Uuid => ColumnData::Uuid(uuid_from_parts(0, 0)),
+        ColumnType::Enum8(enum_type) => ColumnData::Enum8(enum_name(enum_type, 0)),
+        ColumnType::Enum16(enum_type) => ColumnData::Enum16(enum_name(enum_type, 0)),
+        ColumnType::Nullable(_) => ColumnData::Null,
+        ColumnType::LowCardinality(inner) => default_value(inner),
+        ColumnType::Array(_) | ColumnType::Map(..) => ColumnData::Array(Vec::new()),
+        ColumnType::Tuple(elements) => {
+            ColumnData::Tuple(elements.iter().map(|(_, t)| default_value(t)).collect())
+        }
+        ColumnType::Variant(_) | ColumnType::Dynamic(_) => ColumnData::Null,
+        ColumnType::Json(_) => ColumnData::Object(Vec::new()),
+        ColumnType::Geo(kind) => default_value(&geo::storage_type(*kind)),
+        ColumnType::AggregateFunction(_) | ColumnType::Cast(_) => ColumnData::Null,
+        ColumnType::Unsupported(type_name) => {
+            ColumnData::String(format!("<unsupported:{}>", type_name))
+        }
+    }
+}
+
+/// ClickHouse stores a UUID as its high and low halves, each little-endian.
+/// DuckDB stores it as a hugeint with the top bit flipped so that it sorts
+/// like the textual form.
+fn uuid_from_parts(high: u64, low: u64) -> i128 {
+    ((((high as u128) << 64) | low as u128) ^ (1u128 << 127)) as i128
+}
+
+/// Converts a DateTime64 tick count to the unit of the DuckDB timestamp it
+/// is mapped to: microseconds, or nanoseconds above microsecond precision
+/// unless the column has a time zone.
+fn scale_datetime64(value: i64, precision: u8, tz: &Option<String>) -> i64 {
+    let target = if precision > 6 && tz.is_none() { 9 } else { 6 };
+    if precision <= target {
+        value * 10i64.pow((target - precision) as u32)
+    } else {
+        value.div_euclid(10i64.pow((precision - target) as u32))
+    }
+}
+
+/// Converts a Time64 tick count to the unit of the DuckDB time it is mapped
+/// to: microseconds, or nanoseconds above microsecond precision.
+fn scale_time64(value: i64, precision: u8) -> i64 {
+    let target = if precision > 6 { 9 } else { 6 };
+    value * 10i64.pow((target - precision) as u32)
+}
+
+/// Converts a count of interval units to a DuckDB interval, keeping months
+/// and days apart from the fixed-length units like DuckDB does.
+fn interval(kind: IntervalKind, count: i64) -> ffi::duckdb_interval {
+    let (months, days, micros) = match kind {
+        IntervalKind::Nanosecond => (0, 0, count / 1_000),
+        IntervalKind::Microsecond => (0, 0, count),
+        IntervalKind::Millisecond => (0, 0, count * 1_000),
+        IntervalKind::Second => (0, 0, count * 1_000_000),
+        IntervalKind::Minute => (0, 0, count * 60_000_000),
+        IntervalKind::Hour => (0, 0, count * 3_600_000_000),
+        IntervalKind::Day => (0, count, 0),
+        IntervalKind::Week => (0, count * 7, 0),
+        IntervalKind::Month => (count, 0, 0),
+        IntervalKind::Quarter => (count * 3, 0, 0),
+        IntervalKind::Year => (count * 12, 0, 0),
+    };
+    ffi::duckdb_interval {
+        months: months as i32,
+        days: days as i32,
+        micros,
+    }
+}
+
+fn read_array_offsets(reader: &mut impl Read, rows: u64) -> io::Result<Vec<u64>> {
+    let mut offsets = Vec::with_capacity(rows as usize);
+    for _ in 0..rows {
+        offsets.push(reader.read_u64::<LittleEndian>()?);
+    }
+    Ok(offsets)
+}
+
+fn split_by_offsets(offsets: &[u64], values: Vec<ColumnData>) -> Vec<ColumnData> {
+    let mut values = values.into_iter();
+    let mut start = 0;
+    offsets
+        .iter()
+        .map(|&end| {
+            let items = values.by_ref().take((end - start) as usize).collect();
+            start = end;
+            ColumnData::Array(items)
+        })
+        .collect()
+}
+
 fn read_column_data(
     reader: &mut impl Read,
     column_type: &ColumnType,
     rows: u64,
 ) -> io::Result<Vec<ColumnData>> {
+    match column_type {
+        ColumnType::Nullable(inner) => {
+            let mut null_map = vec![0u8; rows as usize];
+            reader.read_exact(&mut null_map)?;
+            let values = read_column_data(reader, inner, rows)?;
+            return Ok(null_map
+                .into_iter()
+                .zip(values)
+                .map(|(is_null, value)| if is_null != 0 { ColumnData::Null } else { value })
+                .collect());
+        }
+        ColumnType::LowCardinality(inner) => {
+            return low_cardinality::read_low_cardinality_data(reader, inner, rows)
+        }
+        ColumnType::Array(inner) => {
+            let offsets = read_array_offsets(reader, rows)?;
+            let total = offsets.last().copied().unwrap_or(0);
+            let values = read_column_data(reader, inner, total)?;
+            return Ok(split_by_offsets(&offsets, values));
+        }
+        ColumnType::Map(key, value) => {
+            let offsets = read_array_offsets(reader, rows)?;
+            let total = offsets.last().copied().unwrap_or(0);
+            let keys = read_column_data(reader, key, total)?;
+            let values = read_column_data(reader, value, total)?;
+            let entries = keys
+                .into_iter()
+                .zip(values)
+                .map(|(k, v)| ColumnData::Tuple(vec![k, v]))
+                .collect();
+            return Ok(split_by_offsets(&offsets, entries));
+        }
+        ColumnType::Tuple(elements) => {
+            let default_info = SerializationInfo::default_for(column_type);
+            return read_tuple_data(reader, elements, &default_info.elements, rows);
+        }
+        ColumnType::Variant(members) => return dynamic::read_variant_data(reader, members, rows),
+        ColumnType::Dynamic(types) => return dynamic::read_dynamic_data(reader, types, rows),
+        ColumnType::Json(json) => return dynamic::read_json_data(reader, json, rows),
+        ColumnType::Geo(kind) => return read_column_data(reader, &geo::storage_type(*kind), rows),
+        ColumnType::AggregateFunction(aggregate) => {
+            return aggregate::read_aggregate_data(reader, aggregate, rows)
+        }
+        _ => {}
+    }
+
     let mut data = Vec::with_capacity(rows as usize);
     for _ in 0..rows {
         let value = match column_type {
@@ -153,27 +931,227 @@ fn read_column_data(
                 ColumnData::UInt64(val)
             }
             ColumnType::String => ColumnData::String(read_string(reader)?),
+            ColumnType::FixedString(size) => {
+                let mut buffer = vec![0; *size];
+                reader.read_exact(&mut buffer)?;
+                ColumnData::String(
+                    String::from_utf8_lossy(&buffer).replace(['\0', '\u{FFFD}'], ""),
+                )
+            }
             ColumnType::UInt8 => ColumnData::UInt8(reader.read_u8()?),
+            ColumnType::UInt16 => ColumnData::UInt16(reader.read_u16::<LittleEndian>()?),
+            ColumnType::UInt32 => ColumnData::UInt32(reader.read_u32::<LittleEndian>()?),
+            ColumnType::Int8 => ColumnData::Int8(reader.read_i8()?),
+            ColumnType::Int16 => ColumnData::Int16(reader.read_i16::<LittleEndian>()?),
+            ColumnType::Int32 => ColumnData::Int32(reader.read_i32::<LittleEndian>()?),
+            ColumnType::Int64 => ColumnData::Int64(reader.read_i64::<LittleEndian>()?),
+            ColumnType::Float32 => ColumnData::Float32(reader.read_f32::<LittleEndian>()?),
+            ColumnType::Float64 => ColumnData::Float64(reader.read_f64::<LittleEndian>()?),
+            ColumnType::Bool => ColumnData::Bool(reader.read_u8()? != 0),
+            ColumnType::Date => ColumnData::Date(reader.read_u16::<LittleEndian>()? as i32),
+            ColumnType::Date32 => ColumnData::Date(reader.read_i32::<LittleEndian>()?),
+            ColumnType::DateTime(_) => {
+                ColumnData::Timestamp(reader.read_u32::<LittleEndian>()? as i64 * 1_000_000)
+            }
+            ColumnType::DateTime64(precision, tz) => {
+                let ticks = reader.read_i64::<LittleEndian>()?;
+                ColumnData::Timestamp(scale_datetime64(ticks, *precision, tz))
+            }
+            ColumnType::Decimal(precision, scale) => match precision {
+                0..=9 => ColumnData::Decimal(reader.read_i32::<LittleEndian>()? as i128),
+                10..=18 => ColumnData::Decimal(reader.read_i64::<LittleEndian>()? as i128),
+                19..=38 => ColumnData::Decimal(reader.read_i128::<LittleEndian>()?),
+                _ => {
+                    // Decimal256 does not fit DuckDB's DECIMAL; keep it as a double.
+                    let mut limbs = [0u64; 4];
+                    reader.read_u64_into::<LittleEndian>(&mut limbs)?;
+                    let negative = limbs[3] >> 63 == 1;
+                    if negative {
+                        let mut carry = true;
+                        for limb in limbs.iter_mut() {
+                            let (value, overflow) = (!*limb).overflowing_add(carry as u64);
+                            *limb = value;
+                            carry = overflow;
+                        }
+                    }
+                    let magnitude = limbs
+                        .iter()
+                        .rev()
+                        .fold(0f64, |acc, &limb| acc * 18446744073709551616.0 + limb as f64);
+                    let value = magnitude / 10f64.powi(*scale as i32);
+                    ColumnData::Float64(if negative { -value } else { value })
+                }
+            },
+            ColumnType::Time => {
+                ColumnData::Time(reader.read_i32::<LittleEndian>()? as i64 * 1_000_000)
+            }
+            ColumnType::Time64(precision) => {
+                let ticks = reader.read_i64::<LittleEndian>()?;
+                ColumnData::Time(scale_time64(ticks, *precision))
+            }
+            ColumnType::Interval(kind) => {
+                ColumnData::Interval(interval(*kind, reader.read_i64::<LittleEndian>()?))
+            }
+            ColumnType::Uuid => {
+                let high = reader.read_u64::<LittleEndian>()?;
+                let low = reader.read_u64::<LittleEndian>()?;
+                ColumnData::Uuid(uuid_from_parts(high, low))
+            }
             ColumnType::Enum8(enum_type) => {
-                let val = reader.read_u8()?;
-                let enum_str = enum_type
-                    .values
-                    .iter()
-                    .find(|ev| ev.value == val as i8)
-                    .map(|ev| ev.name.clone())
-                    .unwrap_or_else(|| format!("Unknown({})", val));
-                ColumnData::Enum8(enum_str)
+                let val = reader.read_i8()?;
+                ColumnData::Enum8(enum_name(enum_type, val as i16))
+            }
+            ColumnType::Enum16(enum_type) => {
+                let val = reader.read_i16::<LittleEndian>()?;
+                ColumnData::Enum16(enum_name(enum_type, val))
             }
             ColumnType::Int => ColumnData::Int(reader.read_i32::<LittleEndian>()?),
             ColumnType::Unsupported(type_name) => {
                 ColumnData::String(format!("<unsupported:{}>", type_name))
             }
+            ColumnType::Nullable(_)
+            | ColumnType::LowCardinality(_)
+            | ColumnType::Array(_)
+            | ColumnType::Map(..)
+            | ColumnType::Tuple(_)
+            | ColumnType::Variant(_)
+            | ColumnType::Dynamic(_)
+            | ColumnType::Json(_)
+            | ColumnType::Geo(_)
+            | ColumnType::AggregateFunction(_)
+            | ColumnType::Cast(_) => unreachable!(),
         };
         data.push(value);
     }
     Ok(data)
 }
 
+/// Reads a single value in its per-row form (as in RowBinary), where
+/// containers carry their own sizes and null flags instead of the separate
+/// streams of a Native column.
+fn read_row_value(reader: &mut impl Read, column_type: &ColumnType) -> io::Result<ColumnData> {
+    Ok(match column_type {
+        ColumnType::Nullable(inner) => {
+            if reader.read_u8()? != 0 {
+                ColumnData::Null
+            } else {
+                read_row_value(reader, inner)?
+            }
+        }
+        ColumnType::LowCardinality(inner) => read_row_value(reader, inner)?,
+        ColumnType::Array(inner) => {
+            let len = read_var_u64(reader)?;
+            ColumnData::Array(
+                (0..len)
+                    .map(|_| read_row_value(reader, inner))
+                    .collect::<io::Result<_>>()?,
+            )
+        }
+        ColumnType::Map(key, value) => {
+            let len = read_var_u64(reader)?;
+            let mut entries = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                let k = read_row_value(reader, key)?;
+                let v = read_row_value(reader, value)?;
+                entries.push(ColumnData::Tuple(vec![k, v]));
+            }
+            ColumnData::Array(entries)
+        }
+        ColumnType::Tuple(elements) => ColumnData::Tuple(
+            elements
+                .iter()
+                .map(|(_, element_type)| read_row_value(reader, element_type))
+                .collect::<io::Result<_>>()?,
+        ),
+        ColumnType::Variant(_) | ColumnType::Dynamic(_) | ColumnType::Json(_) => {
+            dynamic::read_row_value(reader, column_type)?
+        }
+        ColumnType::Geo(kind) => read_row_value(reader, &geo::storage_type(*kind))?,
+        _ => read_column_data(reader, column_type, 1)?
+            .pop()
+            .unwrap_or(ColumnData::Null),
+    })
+}
+
+fn read_tuple_data(
+    reader: &mut impl Read,
+    elements: &[(String, ColumnType)],
+    infos: &[SerializationInfo],
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    let mut rows_data: Vec<Vec<ColumnData>> = (0..rows)
+        .map(|_| Vec::with_capacity(elements.len()))
+        .collect();
+    for ((_, element_type), info) in elements.iter().zip(infos) {
+        let values = read_column_values(reader, element_type, info, rows)?;
+        for (row, value) in rows_data.iter_mut().zip(values) {
+            row.push(value);
+        }
+    }
+    Ok(rows_data.into_iter().map(ColumnData::Tuple).collect())
+}
+
+/// Reads the positions of the non-default rows of a sparse column. Each
+/// group size counts the defaults preceding the next value; the last one
+/// carries `END_OF_GRANULE_FLAG` and counts the trailing defaults.
+fn read_sparse_offsets(reader: &mut impl Read, rows: u64) -> io::Result<Vec<u64>> {
+    let mut offsets = Vec::new();
+    let mut position = 0u64;
+
+    loop {
+        let group_size = read_var_u64(reader)?;
+        if group_size & END_OF_GRANULE_FLAG != 0 {
+            position += group_size & !END_OF_GRANULE_FLAG;
+            break;
+        }
+        position += group_size;
+        offsets.push(position);
+        position += 1;
+    }
+
+    if position != rows {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Sparse offsets cover {} rows, expected {}", position, rows),
+        ));
+    }
+
+    Ok(offsets)
+}
+
+fn read_sparse_column_data(
+    reader: &mut impl Read,
+    column_type: &ColumnType,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    let offsets = read_sparse_offsets(reader, rows)?;
+    let values = read_column_data(reader, column_type, offsets.len() as u64)?;
+
+    let mut data = vec![default_value(column_type); rows as usize];
+    for (offset, value) in offsets.into_iter().zip(values) {
+        data[offset as usize] = value;
+    }
+    Ok(data)
+}
+
+fn read_column_values(
+    reader: &mut impl Read,
+    column_type: &ColumnType,
+    info: &SerializationInfo,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    if rows == 0 {
+        return Ok(Vec::new());
+    }
+    match (info.kind, column_type) {
+        (SerializationKind::Sparse, _) => read_sparse_column_data(reader, column_type, rows),
+        (SerializationKind::Default, ColumnType::Tuple(elements)) => {
+            read_tuple_data(reader, elements, &info.elements, rows)
+        }
+        (SerializationKind::Default, _) => read_column_data(reader, column_type, rows),
+    }
+}
+
 fn read_var_u64(reader: &mut impl Read) -> io::Result<u64> {
     let mut x = 0u64;
     let mut shift = 0;
@@ -193,50 +1171,718 @@ fn read_var_u64(reader: &mut impl Read) -> io::Result<u64> {
     ))
 }
 
-fn read_native_format(reader: &mut BufReader<File>) -> io::Result<Vec<Column>> {
-    let num_columns = read_var_u64(reader)?;
-    let mut columns = Vec::new();
-    let num_rows = read_var_u64(reader)?;
-
-    for _ in 0..num_columns {
-        let name = read_string(reader)?;
-        let type_str = read_string(reader)?;
-        let (column_type, _type_params) = parse_column_type(&type_str);
-        let data = read_column_data(reader, &column_type, num_rows)?;
-        columns.push(Column {
-            name,
-            type_: column_type,
-            data,
-        });
-    }
+/// Files written with a client protocol revision (for example over HTTP with
+/// `client_protocol_version`) prefix every block with a `BlockInfo` record:
+/// field 1 (`is_overflows`), field 2 (`bucket_num`, usually -1) and a zero
+/// terminator. A plain Native block never has a zero byte at offset 7, since
+/// that is within the first column's type name, which starts at offset 6.
+fn has_block_info(reader: &mut impl BufRead) -> io::Result<bool> {
+    let buf = reader.fill_buf()?;
+    Ok(buf.len() >= 8 && buf[0] == 1 && buf[1] <= 1 && buf[2] == 2 && buf[7] == 0)
+}
 
+fn read_block_info(reader: &mut impl Read) -> io::Result<()> {
     loop {
-        let _pos = reader.stream_position()?;
-        let block_columns = match read_var_u64(reader) {
-            Ok(cols) => cols,
-            Err(_) => break,
-        };
+        match read_var_u64(reader)? {
+            0 => return Ok(()),
+            1 => {
+                reader.read_u8()?;
+            }
+            2 => {
+                reader.read_i32::<LittleEndian>()?;
+            }
+            3 => {
+                for _ in 0..read_var_u64(reader)? {
+                    reader.read_i32::<LittleEndian>()?;
+                }
+            }
+            field => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown block info field {}", field),
+                ))
+            }
+        }
+    }
+}
 
-        let block_rows = read_var_u64(reader)?;
+impl SerializationInfo {
+    fn default_for(column_type: &ColumnType) -> Self {
+        let elements = match column_type {
+            ColumnType::Tuple(elements) => elements
+                .iter()
+                .map(|(_, element_type)| SerializationInfo::default_for(element_type))
+                .collect(),
+            _ => Vec::new(),
+        };
+        SerializationInfo {
+            kind: SerializationKind::Default,
+            elements,
+        }
+    }
 
-        if block_rows == 0 {
-            break;
+    /// Reads the serialization kinds written after the `has_custom` flag:
+    /// one byte for the column, then the kinds of tuple elements in order.
+    fn read_kinds(reader: &mut impl Read, column_type: &ColumnType) -> io::Result<Self> {
+        if let ColumnType::Geo(GeoKind::Point) = column_type {
+            return SerializationInfo::read_kinds(reader, &geo::storage_type(GeoKind::Point));
         }
+        let kind = match reader.read_u8()? {
+            0 => SerializationKind::Default,
+            1 => SerializationKind::Sparse,
+            kind => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unsupported serialization kind {}", kind),
+                ))
+            }
+        };
+        let elements = match column_type {
+            ColumnType::Tuple(elements) => elements
+                .iter()
+                .map(|(_, element_type)| SerializationInfo::read_kinds(reader, element_type))
+                .collect::<io::Result<_>>()?,
+            _ => Vec::new(),
+        };
+        Ok(SerializationInfo { kind, elements })
+    }
+}
 
-        for _ in 0..block_columns {
-            let _ = read_string(reader)?;
-            let _ = read_string(reader)?;
+fn read_serialization_info(
+    reader: &mut impl Read,
+    column_type: &ColumnType,
+) -> io::Result<SerializationInfo> {
+    if reader.read_u8()? == 0 {
+        return Ok(SerializationInfo::default_for(column_type));
+    }
+    SerializationInfo::read_kinds(reader, column_type)
+}
+
+/// A type name is a length-prefixed identifier starting with an uppercase
+/// letter. A binary type starts with a one-byte type code, which as a length
+/// is either too short for a type name or followed by column data.
+fn looks_like_type_name(buf: &[u8]) -> bool {
+    let mut cursor = buf;
+    let len = match read_var_u64(&mut cursor) {
+        Ok(len) => len as usize,
+        Err(_) => return false,
+    };
+    if len < 4 || cursor.len() < len {
+        return false;
+    }
+    let name = &cursor[..len];
+    name[0].is_ascii_uppercase()
+        && name.iter().all(|b| b.is_ascii_graphic() || *b == b' ')
+        && name.iter().filter(|&&b| b == b'(').count() == name.iter().filter(|&&b| b == b')').count()
+}
+
+/// Opens a Native file, decompressing it on the fly when the whole file is
+/// a zstd or gzip stream (as written by `INTO OUTFILE ... COMPRESSION`) and
+/// when it is made of ClickHouse's checksummed compressed blocks.
+fn open_native_file(path: &str) -> io::Result<Box<dyn BufRead + Send>> {
+    native_reader(BufReader::with_capacity(64 * 1024, File::open(path)?))
+}
+
+/// Wraps a Native stream in the decompression its leading bytes call for,
+/// like `open_native_file` does for files.
+fn native_reader(
+    mut reader: impl BufRead + Send + 'static,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let magic = reader.fill_buf()?;
+    let reader: Box<dyn BufRead + Send> = if magic.starts_with(&ZSTD_MAGIC) {
+        let decoder = zstd::Decoder::with_buffer(reader)?;
+        Box::new(BufReader::with_capacity(64 * 1024, decoder))
+    } else if magic.starts_with(&GZIP_MAGIC) {
+        let decoder = flate2::bufread::MultiGzDecoder::new(reader);
+        Box::new(BufReader::with_capacity(64 * 1024, decoder))
+    } else {
+        Box::new(reader)
+    };
+    compression::decompress_blocks(reader)
+}
+
+/// Reads the column and row counts of the next block, after its `BlockInfo`
+/// when the file has one. Returns `None` at the end of the stream, and an
+/// error for a block cut short.
+fn read_block_header(
+    reader: &mut impl BufRead,
+    with_block_info: bool,
+) -> io::Result<Option<(u64, u64)>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    if with_block_info {
+        read_block_info(reader)?;
+    }
+    let block_columns = read_var_u64(reader)?;
+    let block_rows = read_var_u64(reader)?;
+    Ok(Some((block_columns, block_rows)))
+}
+
+/// Reads a column's name and type string. Whether types are binary encoded
+/// is detected on the first column when `binary_types` is unset, and
+/// remembered for the rest of the file.
+fn read_column_header(
+    reader: &mut impl BufRead,
+    binary_types: &mut Option<bool>,
+) -> io::Result<(String, String)> {
+    let name = read_string(reader)?;
+    let binary = match *binary_types {
+        Some(binary) => binary,
+        None => {
+            let binary = !looks_like_type_name(reader.fill_buf()?);
+            *binary_types = Some(binary);
+            binary
         }
+    };
+    let type_str = if binary {
+        binary_types::read_binary_type(reader)?
+    } else {
+        read_string(reader)?
+    };
+    Ok((name, type_str))
+}
+
+/// Reads the values a block holds for one column, following its header.
+fn read_column_block(
+    reader: &mut impl BufRead,
+    column_type: &ColumnType,
+    with_block_info: bool,
+    binary: bool,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    let info = if with_block_info {
+        read_serialization_info(reader, column_type)?
+    } else {
+        SerializationInfo::default_for(column_type)
+    };
+    if rows == 0 {
+        return Ok(Vec::new());
+    }
+    let block_type = dynamic::read_prefix(reader, column_type, binary)?;
+    read_column_values(reader, &block_type, &info, rows)
+}
+
+fn read_native_format(
+    reader: &mut impl BufRead,
+    options: &NativeOptions,
+) -> io::Result<Vec<Column>> {
+    let with_block_info = has_block_info(reader)?;
+    let mut binary_types = options.binary_types;
+    let mut columns: Vec<Column> = Vec::new();
+
+    if read_native_block(reader, with_block_info, &mut binary_types, &mut columns, true)? {
+        while read_native_block(reader, with_block_info, &mut binary_types, &mut columns, false)? {}
+    }
+
+    resolve_columns(columns, options)
+}
+
+/// Appends the next block of a Native stream to `columns`, which the first
+/// block creates with its column types. Returns `false` at the end of the
+/// stream, or at an empty block after the first.
+fn read_native_block(
+    reader: &mut impl BufRead,
+    with_block_info: bool,
+    binary_types: &mut Option<bool>,
+    columns: &mut Vec<Column>,
+    first_block: bool,
+) -> io::Result<bool> {
+    let Some((block_columns, block_rows)) = read_block_header(reader, with_block_info)? else {
+        return Ok(false);
+    };
+    if !first_block && block_rows == 0 {
+        return Ok(false);
+    }
 
-        for col in &mut columns {
-            let mut new_data = read_column_data(reader, &col.type_, block_rows)?;
-            col.data.append(&mut new_data);
+    for idx in 0..block_columns as usize {
+        let (name, type_str) = read_column_header(reader, binary_types)?;
+
+        if first_block {
+            let (column_type, _type_params) = parse_column_type(&type_str);
+            columns.push(Column {
+                name,
+                type_: column_type,
+                data: Vec::new(),
+            });
         }
+
+        let column = columns.get_mut(idx).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Block has more columns than the header ({})", block_columns),
+            )
+        })?;
+        let mut new_data = read_column_block(
+            reader,
+            &column.type_,
+            with_block_info,
+            binary_types.unwrap_or_default(),
+            block_rows,
+        )?;
+        column.data.append(&mut new_data);
+    }
+
+    Ok(true)
+}
+
+/// Resolves the columns read from a file and groups their Nested columns when
+/// asked to.
+fn resolve_columns(mut columns: Vec<Column>, options: &NativeOptions) -> io::Result<Vec<Column>> {
+    for column in &mut columns {
+        resolve_column(&mut column.type_, column.data.iter_mut().collect(), options)?;
+    }
+    if options.group_nested {
+        columns = nested::group_nested_columns(columns)?;
     }
 
     Ok(columns)
 }
 
+/// The names and types of `columns`, for the blocks of a stream to be read
+/// into.
+fn without_values(columns: &[Column]) -> Vec<Column> {
+    columns
+        .iter()
+        .map(|column| Column {
+            name: column.name.clone(),
+            type_: column.type_.clone(),
+            data: Vec::new(),
+        })
+        .collect()
+}
+
+/// A Native stream after its first block, read a block at a time.
+struct NativeStream {
+    reader: Box<dyn BufRead + Send>,
+    with_block_info: bool,
+    binary_types: Option<bool>,
+    /// The columns of the first block, without values, as read from the file.
+    header: Vec<Column>,
+    /// The columns of the first block as bound.
+    bound: Vec<Column>,
+    options: NativeOptions,
+    overrides: coerce::TypeOverrides,
+    blocks: usize,
+}
+
+impl NativeStream {
+    /// Reads the first block of `reader`, returning its columns for the
+    /// schema and the stream to read the others from.
+    fn open(
+        mut reader: Box<dyn BufRead + Send>,
+        options: &NativeOptions,
+        overrides: &coerce::TypeOverrides,
+    ) -> io::Result<(Vec<Column>, Self)> {
+        let with_block_info = has_block_info(&mut reader)?;
+        let mut binary_types = options.binary_types;
+        let mut columns = Vec::new();
+        read_native_block(&mut reader, with_block_info, &mut binary_types, &mut columns, true)?;
+
+        let header = without_values(&columns);
+        let mut columns = resolve_columns(columns, options)?;
+        overrides.apply(&mut columns)?;
+        let bound = without_values(&columns);
+
+        let stream = NativeStream {
+            reader,
+            with_block_info,
+            binary_types,
+            header,
+            bound,
+            options: options.clone(),
+            overrides: overrides.clone(),
+            blocks: 1,
+        };
+        Ok((columns, stream))
+    }
+}
+
+impl ReadBlock for NativeStream {
+    fn read_block(&mut self) -> io::Result<Option<Vec<Column>>> {
+        let mut columns = without_values(&self.header);
+        let read = read_native_block(
+            &mut self.reader,
+            self.with_block_info,
+            &mut self.binary_types,
+            &mut columns,
+            false,
+        )?;
+        if !read {
+            return Ok(None);
+        }
+
+        let mut columns = resolve_columns(columns, &self.options)?;
+        self.overrides.apply(&mut columns)?;
+        check_block_types(&self.bound, &columns, self.blocks)?;
+        self.blocks += 1;
+        Ok(Some(columns))
+    }
+}
+
+fn take_children<'a>(
+    values: Vec<&'a mut ColumnData>,
+    mut child: impl FnMut(&'a mut ColumnData) -> Vec<&'a mut ColumnData>,
+) -> Vec<&'a mut ColumnData> {
+    values.into_iter().flat_map(&mut child).collect()
+}
+
+/// Once the whole file is read, turns the columns whose DuckDB shape depends
+/// on the data or on the scan options into that shape, at any nesting depth.
+fn resolve_column(
+    column_type: &mut ColumnType,
+    values: Vec<&mut ColumnData>,
+    options: &NativeOptions,
+) -> io::Result<()> {
+    match column_type {
+        ColumnType::Nullable(inner) => resolve_column(inner, values, options),
+        ColumnType::LowCardinality(inner) => {
+            // Values were looked up in the dictionary while reading, so the
+            // column is its key type from here on.
+            resolve_column(inner, values, options)?;
+            *column_type = (**inner).clone();
+            Ok(())
+        }
+        ColumnType::Array(inner) => {
+            let children = take_children(values, |value| match value {
+                ColumnData::Array(items) => items.iter_mut().collect(),
+                _ => Vec::new(),
+            });
+            resolve_column(inner, children, options)
+        }
+        ColumnType::Map(_, value_type) => {
+            let children = take_children(values, |value| match value {
+                ColumnData::Array(entries) => entries
+                    .iter_mut()
+                    .filter_map(|entry| match entry {
+                        ColumnData::Tuple(pair) => pair.get_mut(1),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            });
+            resolve_column(value_type, children, options)
+        }
+        ColumnType::Tuple(elements) => {
+            let mut values = values;
+            for (idx, (_, element_type)) in elements.iter_mut().enumerate() {
+                let children = values
+                    .iter_mut()
+                    .filter_map(|value| match &mut **value {
+                        ColumnData::Tuple(items) => items.get_mut(idx),
+                        _ => None,
+                    })
+                    .collect();
+                resolve_column(element_type, children, options)?;
+            }
+            Ok(())
+        }
+        ColumnType::Variant(members) => {
+            let mut values = values;
+            for (idx, (_, member_type)) in members.iter_mut().enumerate() {
+                let children = values
+                    .iter_mut()
+                    .filter_map(|value| match &mut **value {
+                        ColumnData::Variant(member, inner) if *member as usize == idx => {
+                            Some(inner.as_mut())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                resolve_column(member_type, children, options)?;
+            }
+            Ok(())
+        }
+        ColumnType::Dynamic(_) => dynamic::resolve_dynamic(column_type, values, options),
+        ColumnType::Json(_) => dynamic::resolve_json(column_type, values, options),
+        ColumnType::Geo(_) => geo::resolve_geo(column_type, values, options),
+        ColumnType::AggregateFunction(_) => {
+            aggregate::resolve_aggregate(column_type, values, options)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn logical_type(column_type: &ColumnType) -> LogicalTypeHandle {
+    let type_id = match column_type {
+        ColumnType::String
+        | ColumnType::FixedString(_)
+        | ColumnType::Enum8(_)
+        | ColumnType::Enum16(_)
+        | ColumnType::Unsupported(_) => LogicalTypeId::Varchar,
+        ColumnType::Int | ColumnType::Int32 => LogicalTypeId::Integer,
+        ColumnType::UInt8 => LogicalTypeId::UTinyint,
+        ColumnType::UInt64 => LogicalTypeId::UBigint,
+        ColumnType::UInt16 => LogicalTypeId::USmallint,
+        ColumnType::UInt32 => LogicalTypeId::UInteger,
+        ColumnType::Int8 => LogicalTypeId::Tinyint,
+        ColumnType::Int16 => LogicalTypeId::Smallint,
+        ColumnType::Int64 => LogicalTypeId::Bigint,
+        ColumnType::Float32 => LogicalTypeId::Float,
+        ColumnType::Float64 => LogicalTypeId::Double,
+        ColumnType::Bool => LogicalTypeId::Boolean,
+        ColumnType::Date | ColumnType::Date32 => LogicalTypeId::Date,
+        ColumnType::DateTime(None) => LogicalTypeId::Timestamp,
+        ColumnType::DateTime64(precision, None) if *precision > 6 => LogicalTypeId::TimestampNs,
+        ColumnType::DateTime64(_, None) => LogicalTypeId::Timestamp,
+        ColumnType::DateTime(Some(_)) | ColumnType::DateTime64(_, Some(_)) => {
+            LogicalTypeId::TimestampTZ
+        }
+        ColumnType::Decimal(precision, scale) if *precision <= 38 => {
+            return LogicalTypeHandle::decimal(*precision, *scale)
+        }
+        ColumnType::Decimal(..) => LogicalTypeId::Double,
+        // TIME_NS is available in every DuckDB from MINIMUM_DUCKDB_VERSION.
+        ColumnType::Time64(precision) if *precision > 6 => LogicalTypeId::TimeNs,
+        ColumnType::Time | ColumnType::Time64(_) => LogicalTypeId::Time,
+        ColumnType::Interval(_) => LogicalTypeId::Interval,
+        ColumnType::Uuid => LogicalTypeId::Uuid,
+        ColumnType::Nullable(inner) | ColumnType::LowCardinality(inner) => {
+            return logical_type(inner)
+        }
+        ColumnType::Array(inner) => return LogicalTypeHandle::list(&logical_type(inner)),
+        ColumnType::Map(key, value) => {
+            return LogicalTypeHandle::map(&logical_type(key), &logical_type(value))
+        }
+        ColumnType::Tuple(elements) => {
+            let fields: Vec<(&str, LogicalTypeHandle)> = elements
+                .iter()
+                .map(|(name, element_type)| (name.as_str(), logical_type(element_type)))
+                .collect();
+            return LogicalTypeHandle::struct_type(&fields);
+        }
+        ColumnType::Variant(members) => {
+            let fields: Vec<(&str, LogicalTypeHandle)> = members
+                .iter()
+                .map(|(name, member_type)| (name.as_str(), logical_type(member_type)))
+                .collect();
+            return LogicalTypeHandle::union_type(&fields);
+        }
+        ColumnType::Json(_) => {
+            let mut handle = LogicalTypeHandle::from(LogicalTypeId::Varchar);
+            handle.set_alias("JSON");
+            return handle;
+        }
+        ColumnType::Dynamic(_) => LogicalTypeId::Varchar,
+        ColumnType::Geo(_) | ColumnType::AggregateFunction(_) => LogicalTypeId::Blob,
+        ColumnType::Cast(target) => target.type_id(),
+    };
+    LogicalTypeHandle::from(type_id)
+}
+
+/// Declares the columns a reader returns along with their exact row count,
+/// which DuckDB's optimizer uses to order joins.
+fn bind_columns(bind: &BindInfo, columns: &[Column]) {
+    let rows = columns.first().map_or(0, |column| column.data.len());
+    bind_schema(bind, columns, Some((rows as u64, true)));
+}
+
+/// Binds columns whose values are only read in `init`, with the row count
+/// of the scan when it is known up front and whether it is exact.
+fn bind_schema(bind: &BindInfo, columns: &[Column], rows: Option<(u64, bool)>) {
+    for column in columns {
+        bind.add_result_column(&column.name, logical_type(&column.type_));
+    }
+    if let Some((rows, exact)) = rows {
+        bind.set_cardinality(rows, exact);
+    }
+}
+
+fn set_value<T>(vector: &mut FlatVector, row: usize, value: T) {
+    unsafe { vector.as_mut_slice::<T>()[row] = value };
+}
+
+fn write_flat_vector(vector: &mut FlatVector, column_type: &ColumnType, values: &[&ColumnData]) {
+    for (row, value) in values.iter().enumerate() {
+        match (value, column_type) {
+            (ColumnData::Null, _) => vector.set_null(row),
+            (value, ColumnType::Cast(target)) => coerce::set_cast_value(vector, row, *target, value),
+            (ColumnData::String(s), _) | (ColumnData::Enum8(s), _) | (ColumnData::Enum16(s), _) => {
+                vector.insert(row, s.as_str())
+            }
+            (ColumnData::UInt8(v), _) => set_value(vector, row, *v),
+            (ColumnData::UInt64(v), _) => set_value(vector, row, *v),
+            (ColumnData::Int(v), _) | (ColumnData::Int32(v), _) => set_value(vector, row, *v),
+            (ColumnData::UInt16(v), _) => set_value(vector, row, *v),
+            (ColumnData::UInt32(v), _) => set_value(vector, row, *v),
+            (ColumnData::Int8(v), _) => set_value(vector, row, *v),
+            (ColumnData::Int16(v), _) => set_value(vector, row, *v),
+            (ColumnData::Int64(v), _) => set_value(vector, row, *v),
+            (ColumnData::Float32(v), _) => set_value(vector, row, *v),
+            (ColumnData::Float64(v), _) => set_value(vector, row, *v),
+            (ColumnData::Bool(v), _) => set_value(vector, row, *v),
+            (ColumnData::Date(v), _) => set_value(vector, row, *v),
+            (ColumnData::Timestamp(v), _) => set_value(vector, row, *v),
+            (ColumnData::Time(v), _) => set_value(vector, row, *v),
+            (ColumnData::Interval(v), _) => set_value(vector, row, *v),
+            (ColumnData::Uuid(v), _) => set_value(vector, row, *v),
+            (ColumnData::Blob(bytes), _) => vector.insert(row, bytes.as_slice()),
+            (ColumnData::Decimal(v), ColumnType::Decimal(precision, _)) => match precision {
+                0..=4 => set_value(vector, row, *v as i16),
+                5..=9 => set_value(vector, row, *v as i32),
+                10..=18 => set_value(vector, row, *v as i64),
+                _ => set_value(vector, row, *v),
+            },
+            _ => vector.set_null(row),
+        }
+    }
+}
+
+fn write_list_vector(vector: &mut ListVector, element_type: &ColumnType, values: &[&ColumnData]) {
+    let mut children = Vec::new();
+    for (row, value) in values.iter().enumerate() {
+        match value {
+            ColumnData::Array(items) => {
+                vector.set_entry(row, children.len(), items.len());
+                children.extend(items.iter());
+            }
+            _ => vector.set_null(row),
+        }
+    }
+
+    match element_type {
+        ColumnType::Nullable(inner) => write_list_children(vector, inner, &children),
+        _ => write_list_children(vector, element_type, &children),
+    }
+    vector.set_len(children.len());
+}
+
+fn write_list_children(vector: &ListVector, element_type: &ColumnType, children: &[&ColumnData]) {
+    match element_type {
+        ColumnType::Array(inner) => {
+            vector.try_reserve(children.len()).ok();
+            write_list_vector(&mut vector.list_child(), inner, children)
+        }
+        ColumnType::Map(key, value) => {
+            vector.try_reserve(children.len()).ok();
+            write_list_vector(&mut vector.list_child(), &map_entry_type(key, value), children)
+        }
+        ColumnType::Tuple(elements) => {
+            write_struct_vector(&mut vector.struct_child(children.len()), elements, children)
+        }
+        ColumnType::Variant(members) => {
+            write_union_vector(&mut vector.struct_child(children.len()), members, children)
+        }
+        _ => write_flat_vector(&mut vector.child(children.len()), element_type, children),
+    }
+}
+
+fn map_entry_type(key: &ColumnType, value: &ColumnType) -> ColumnType {
+    ColumnType::Tuple(vec![
+        ("key".to_string(), key.clone()),
+        ("value".to_string(), value.clone()),
+    ])
+}
+
+fn write_struct_vector(
+    vector: &mut StructVector,
+    elements: &[(String, ColumnType)],
+    values: &[&ColumnData],
+) {
+    for (idx, (_, element_type)) in elements.iter().enumerate() {
+        let children: Vec<&ColumnData> = values
+            .iter()
+            .map(|value| match value {
+                ColumnData::Tuple(items) => items.get(idx).unwrap_or(&ColumnData::Null),
+                _ => &ColumnData::Null,
+            })
+            .collect();
+        write_struct_child(vector, idx, element_type, &children);
+    }
+    for (row, value) in values.iter().enumerate() {
+        if matches!(value, ColumnData::Null) {
+            vector.set_null(row);
+        }
+    }
+}
+
+fn write_struct_child(
+    vector: &StructVector,
+    idx: usize,
+    element_type: &ColumnType,
+    children: &[&ColumnData],
+) {
+    let element_type = match element_type {
+        ColumnType::Nullable(inner) => inner,
+        other => other,
+    };
+    match element_type {
+        ColumnType::Array(inner) => {
+            write_list_vector(&mut vector.list_vector_child(idx), inner, children)
+        }
+        ColumnType::Map(key, value) => write_list_vector(
+            &mut vector.list_vector_child(idx),
+            &map_entry_type(key, value),
+            children,
+        ),
+        ColumnType::Tuple(inner) => {
+            write_struct_vector(&mut vector.struct_vector_child(idx), inner, children)
+        }
+        ColumnType::Variant(members) => {
+            write_union_vector(&mut vector.struct_vector_child(idx), members, children)
+        }
+        _ => write_flat_vector(&mut vector.child(idx, children.len()), element_type, children),
+    }
+}
+
+/// A UNION vector is a STRUCT whose first child holds the member tag of each
+/// row, followed by one child per member.
+fn write_union_vector(
+    vector: &mut StructVector,
+    members: &[(String, ColumnType)],
+    values: &[&ColumnData],
+) {
+    let mut tags = vector.child(0, values.len());
+    for (row, value) in values.iter().enumerate() {
+        let tag = match value {
+            ColumnData::Variant(idx, _) => *idx,
+            _ => 0,
+        };
+        set_value(&mut tags, row, tag);
+    }
+    for (idx, (_, member_type)) in members.iter().enumerate() {
+        let children: Vec<&ColumnData> = values
+            .iter()
+            .map(|value| match value {
+                ColumnData::Variant(member, inner) if *member as usize == idx => inner.as_ref(),
+                _ => &ColumnData::Null,
+            })
+            .collect();
+        write_struct_child(vector, idx + 1, member_type, &children);
+    }
+    // DuckDB reads the tag of a NULL union as NULL too, so both are set.
+    for (row, value) in values.iter().enumerate() {
+        if !matches!(value, ColumnData::Variant(..)) {
+            vector.child(0, values.len()).set_null(row);
+            vector.set_null(row);
+        }
+    }
+}
+
+fn write_column(
+    output: &mut DataChunkHandle,
+    col_idx: usize,
+    column_type: &ColumnType,
+    values: &[&ColumnData],
+) {
+    match column_type {
+        ColumnType::Nullable(inner) => write_column(output, col_idx, inner, values),
+        ColumnType::Array(inner) => write_list_vector(&mut output.list_vector(col_idx), inner, values),
+        ColumnType::Map(key, value) => write_list_vector(
+            &mut output.list_vector(col_idx),
+            &map_entry_type(key, value),
+            values,
+        ),
+        ColumnType::Tuple(elements) => {
+            write_struct_vector(&mut output.struct_vector(col_idx), elements, values)
+        }
+        ColumnType::Variant(members) => {
+            write_union_vector(&mut output.struct_vector(col_idx), members, values)
+        }
+        _ => write_flat_vector(&mut output.flat_vector(col_idx), column_type, values),
+    }
+}
+
 struct ClickHouseVTab;
 
 impl VTab for ClickHouseVTab {
@@ -245,129 +1891,116 @@ impl VTab for ClickHouseVTab {
 
     fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
         let filepath = bind.get_parameter(0).to_string();
+        let options = NativeOptions::from_bind(bind)?;
+        let overrides = coerce::TypeOverrides::from_bind(bind)?;
 
-        let file = File::open(&filepath)?;
-        let mut reader = BufReader::with_capacity(64 * 1024, file);
-        let columns = read_native_format(&mut reader)?;
-
-        for column in &columns {
-            let logical_type = match &column.type_ {
-                ColumnType::String => LogicalTypeId::Varchar,
-                ColumnType::UInt8 => LogicalTypeId::Integer,
-                ColumnType::UInt64 => LogicalTypeId::Integer,
-                ColumnType::Int => LogicalTypeId::Integer,
-                ColumnType::Enum8(_) => LogicalTypeId::Varchar,
-                ColumnType::Unsupported(_) => LogicalTypeId::Varchar,
-            };
-            bind.add_result_column(&column.name, LogicalTypeHandle::from(logical_type));
+        let reader = open_native_file(&filepath)?;
+        let mut streamed = None;
+        if StreamedInput::is_stream(&filepath) {
+            let (columns, rest) = NativeStream::open(reader, &options, &overrides)?;
+            bind_schema(bind, &columns, None);
+            streamed = Some(StreamedInput::new(columns, Box::new(rest)));
+        } else {
+            let mut reader = reader;
+            let mut columns = read_native_format(&mut reader, &options)?;
+            overrides.apply(&mut columns)?;
+            bind_columns(bind, &columns);
         }
 
-        Ok(ClickHouseBindData { filepath })
+        Ok(ClickHouseBindData {
+            filepath,
+            options,
+            overrides,
+            streamed,
+        })
     }
 
     fn init(info: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
         let bind_data = info.get_bind_data::<ClickHouseBindData>();
-        let filepath = unsafe { &(*bind_data).filepath };
-        let file = File::open(filepath)?;
-        let mut reader = BufReader::with_capacity(64 * 1024, file);
-
-        let columns = read_native_format(&mut reader)?;
-        let total_rows = if columns.is_empty() {
-            0
-        } else {
-            columns[0].data.len()
-        };
+        let bind_data = unsafe { &*bind_data };
+        if let Some(streamed) = &bind_data.streamed {
+            return Ok(streamed.take(&bind_data.filepath)?);
+        }
+        let mut reader = open_native_file(&bind_data.filepath)?;
 
-        Ok(ClickHouseInitData {
-            columns,
-            current_row: std::sync::atomic::AtomicUsize::new(0),
-            total_rows,
-            done: std::sync::atomic::AtomicBool::new(false),
-        })
+        let mut columns = read_native_format(&mut reader, &bind_data.options)?;
+        bind_data.overrides.apply(&mut columns)?;
+        Ok(ClickHouseInitData::new(columns))
     }
 
     fn func(func: &TableFunctionInfo<Self>, output: &mut DataChunkHandle) -> Result<(), Box<dyn Error>> {
-        let init_data = func.get_init_data();
-        let current_row = init_data.current_row.load(std::sync::atomic::Ordering::Relaxed);
-
-        if current_row >= init_data.total_rows || init_data.done.load(std::sync::atomic::Ordering::Relaxed) {
-            output.set_len(0);
-            init_data.done.store(true, std::sync::atomic::Ordering::Relaxed);
-            return Ok(());
-        }
-
-        let batch_size = 1024.min(init_data.total_rows - current_row);
-
-        for col_idx in 0..init_data.columns.len() {
-            let column = &init_data.columns[col_idx];
-            let mut vector = output.flat_vector(col_idx);
-
-            match &column.type_ {
-                ColumnType::String | ColumnType::Unsupported(_) => {
-                    for row in 0..batch_size {
-                        let data_idx = current_row + row;
-                        match &column.data[data_idx] {
-                            ColumnData::String(s) => {
-                                let cleaned = s.replace('\0', "").replace('\u{FFFD}', "");
-                                vector.insert(row, cleaned.as_str())
-                            }
-                            _ => vector.insert(row, "<invalid>"),
-                        }
-                    }
-                }
-                ColumnType::UInt8 => {
-                    let slice = vector.as_mut_slice::<i32>();
-                    for row in 0..batch_size {
-                        let data_idx = current_row + row;
-                        if let ColumnData::UInt8(v) = column.data[data_idx] {
-                            slice[row] = v as i32;
-                        }
-                    }
-                }
-                ColumnType::Enum8(_) => {
-                    for row in 0..batch_size {
-                        let data_idx = current_row + row;
-                        if let ColumnData::Enum8(ref s) = column.data[data_idx] {
-                            vector.insert(row, s.as_str());
-                        }
-                    }
-                }
-
-                ColumnType::UInt64 => {
-                    let slice = vector.as_mut_slice::<i32>();
-                    for row in 0..batch_size {
-                        let data_idx = current_row + row;
-                        if let ColumnData::UInt64(v) = column.data[data_idx] {
-                            slice[row] = v as i32;
-                        }
-                    }
-                }
-                ColumnType::Int => {
-                    let slice = vector.as_mut_slice::<i32>();
-                    for row in 0..batch_size {
-                        let data_idx = current_row + row;
-                        if let ColumnData::Int(v) = column.data[data_idx] {
-                            slice[row] = v;
-                        }
-                    }
-                }
-            }
-        }
-        
-        init_data.current_row.fetch_add(batch_size, std::sync::atomic::Ordering::Relaxed);
-        output.set_len(batch_size);
-        
+        func.get_init_data().scan(output)?;
         Ok(())
     }
 
     fn parameters() -> Option<Vec<LogicalTypeHandle>> {
         Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
     }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        let mut parameters = NativeOptions::named_parameters();
+        // STRUCTs of any shape, so declared as ANY.
+        parameters.push(("columns".to_string(), LogicalTypeHandle::from(LogicalTypeId::Any)));
+        parameters.push(("types_as".to_string(), LogicalTypeHandle::from(LogicalTypeId::Any)));
+        Some(parameters)
+    }
 }
 
-#[duckdb_entrypoint_c_api()]
+/// C API version requested from DuckDB, overridable at build time like the
+/// `duckdb_entrypoint_c_api` macro allows. Copy functions are only in the C
+/// API from v1.5.6, and older versions leave their function pointers unset.
+const MINIMUM_DUCKDB_VERSION: &str = match option_env!("DUCKDB_EXTENSION_MIN_DUCKDB_VERSION") {
+    Some(version) => version,
+    None => "v1.5.6",
+};
+
+/// # Safety
+///
+/// Called by DuckDB when the extension is loaded.
 pub unsafe fn extension_entrypoint(con: Connection) -> Result<(), Box<dyn Error>> {
     con.register_table_function::<ClickHouseVTab>("clickhouse_native")?;
     clickhouse_scan::register_clickhouse_scan(&con)?;
+    metadata::register_metadata_functions(&con)?;
+    rowbinary::register_rowbinary(&con)?;
+    blob::register_blob_function(&con)?;
+    part::register_part_function(&con)?;
+    table_dir::register_table_dir_function(&con)?;
+    log_table::register_log_function(&con)?;
+    table_metadata::register_table_metadata_function(&con)?;
     Ok(())
 }
+
+unsafe fn extension_init(
+    info: ffi::duckdb_extension_info,
+    access: *const ffi::duckdb_extension_access,
+) -> Result<bool, Box<dyn Error>> {
+    if !ffi::duckdb_rs_extension_api_init(info, access, MINIMUM_DUCKDB_VERSION)? {
+        return Ok(false);
+    }
+    let db: ffi::duckdb_database = *(*access).get_database.unwrap()(info);
+    extension_entrypoint(Connection::open_from_raw(db.cast())?)?;
+    replacement_scan::register_replacement_scan(db);
+    copy_to::register_copy_functions(db)?;
+    Ok(true)
+}
+
+/// # Safety
+///
+/// Entrypoint called by DuckDB. Written out instead of generated with
+/// `duckdb_entrypoint_c_api`, which only hands over a connection, because the
+/// replacement scan is registered on the database itself.
+#[no_mangle]
+pub unsafe extern "C" fn chsql_native_init_c_api(
+    info: ffi::duckdb_extension_info,
+    access: *const ffi::duckdb_extension_access,
+) -> bool {
+    match extension_init(info, access) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            let message = CString::new(error.to_string())
+                .unwrap_or_else(|_| c"chsql_native failed to load".to_owned());
+            (*access).set_error.unwrap()(info, message.as_ptr());
+            false
+        }
+    }
+}
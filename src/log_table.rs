@@ -0,0 +1,360 @@
+use super::{
+    bind_schema, coerce,
+    compression::read_frames,
+    parse_column_type,
+    part::{
+        read_wide_part, resolve_part_columns, schema_columns, stream_names, supported,
+        unescape_file_name, PartDir,
+    },
+    pruning::PartFilter,
+    read_column_data, read_native_format, read_string, read_var_u64,
+    table_metadata::metadata_schema,
+    ClickHouseInitData, Column, ColumnType, NativeOptions,
+};
+use byteorder::{LittleEndian, ReadBytesExt};
+use duckdb::{
+    core::{DataChunkHandle, LogicalTypeHandle, LogicalTypeId},
+    vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
+    Connection, Result,
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Cursor},
+    path::Path,
+};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Checks the files of a table against `sizes.json`, where Log-family
+/// engines record the size of every file after each insert. Files that
+/// differ were left by a failed insert, which ClickHouse refuses as well.
+fn check_sizes(dir: &Path) -> io::Result<()> {
+    let Ok(text) = std::fs::read_to_string(dir.join("sizes.json")) else {
+        return Ok(());
+    };
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|err| invalid(format!("Invalid sizes.json: {}", err)))?;
+    let files = json["yandex"].as_object().cloned().unwrap_or_default();
+    for (name, file) in files {
+        let name = unescape_file_name(&name);
+        let recorded = file["size"].as_str().unwrap_or_default();
+        let size = std::fs::metadata(dir.join(&name))?.len();
+        if recorded.parse::<u64>().ok() != Some(size) {
+            return Err(invalid(format!(
+                "{} is {} bytes, sizes.json records {}, the table is broken",
+                name, size, recorded
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The columns of a Log or TinyLog table, which only its metadata records,
+/// from a `schema` STRUCT of ClickHouse types.
+fn parse_schema(text: &str) -> Result<Vec<(String, ColumnType)>, Box<dyn Error>> {
+    let fields = coerce::struct_fields(text)
+        .filter(|fields| !fields.is_empty())
+        .ok_or("schema must be a STRUCT such as {'id': 'UInt64'}")?;
+    Ok(fields
+        .into_iter()
+        .map(|(name, type_str)| (name, parse_column_type(&type_str).0))
+        .collect())
+}
+
+/// The size of the values of a column stored at a fixed width.
+fn value_width(column_type: &ColumnType) -> Option<u64> {
+    Some(match column_type {
+        ColumnType::UInt8 | ColumnType::Int8 | ColumnType::Bool | ColumnType::Enum8(_) => 1,
+        ColumnType::UInt16 | ColumnType::Int16 | ColumnType::Date | ColumnType::Enum16(_) => 2,
+        ColumnType::UInt32
+        | ColumnType::Int32
+        | ColumnType::Float32
+        | ColumnType::Date32
+        | ColumnType::DateTime(_) => 4,
+        ColumnType::UInt64
+        | ColumnType::Int64
+        | ColumnType::Float64
+        | ColumnType::DateTime64(..) => 8,
+        ColumnType::Uuid => 16,
+        ColumnType::FixedString(size) if *size > 0 => *size as u64,
+        _ => return None,
+    })
+}
+
+/// The row count of a Log or TinyLog table, from the streams of a column
+/// whose rows can be counted without knowing the others. A null map, the
+/// sizes of arrays or the values of a fixed-width column are counted from
+/// the headers of their compressed blocks, other values are read.
+fn count_rows(dir: &PartDir, schema: &[(String, ColumnType)]) -> io::Result<u64> {
+    let mut read_values = None;
+    for (name, column_type) in schema {
+        let (stream, nested_table) = stream_names(name);
+        let (file, width) = match column_type {
+            ColumnType::Nullable(_) => (format!("{}.null.bin", stream), 1),
+            ColumnType::Array(_) | ColumnType::Map(_, _) => {
+                let table = nested_table.unwrap_or(stream);
+                (format!("{}.size0.bin", table), 8)
+            }
+            ColumnType::Tuple(_) => continue,
+            _ => match value_width(column_type) {
+                Some(width) => (format!("{}.bin", stream), width),
+                None => {
+                    read_values.get_or_insert((format!("{}.bin", stream), column_type));
+                    continue;
+                }
+            },
+        };
+        if !dir.exists(&file) {
+            // Nothing was ever inserted.
+            return Ok(0);
+        }
+        return Ok(dir.read_compressed(&file)?.decompressed_len() / width);
+    }
+
+    let (file, column_type) = read_values
+        .ok_or_else(|| invalid("Cannot count the rows of a table of tuples only".to_string()))?;
+    if !dir.exists(&file) {
+        return Ok(0);
+    }
+    let data = dir.read_decompressed(&file)?;
+    let mut reader = Cursor::new(&data);
+    let mut rows = 0;
+    while (reader.position() as usize) < data.len() {
+        read_column_data(&mut reader, column_type, 1)?;
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+/// Reads a Log or TinyLog table. Every insert appends to the file of each
+/// column stream, so without stream prefixes the files read as the streams
+/// of one Wide part.
+fn read_log_streams(dir: &Path, schema: &[(String, ColumnType)]) -> io::Result<Vec<Column>> {
+    check_log_schema(schema)?;
+    let dir = PartDir::new(dir);
+    let rows = count_rows(&dir, schema)?;
+    let data = read_wide_part(&dir, schema, rows, &PartFilter::default())?;
+    Ok(schema
+        .iter()
+        .cloned()
+        .zip(data)
+        .map(|((name, type_), data)| Column { name, type_, data })
+        .collect())
+}
+
+fn check_log_schema(schema: &[(String, ColumnType)]) -> io::Result<()> {
+    if let Some((name, _)) = schema
+        .iter()
+        .find(|(_, column_type)| !supported(column_type) || has_low_cardinality(column_type))
+    {
+        return Err(invalid(format!(
+            "Cannot read column {} from a Log table, its type is not supported",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// LowCardinality streams start a dictionary with every insert and block,
+/// which Log tables do not record.
+fn has_low_cardinality(column_type: &ColumnType) -> bool {
+    match column_type {
+        ColumnType::LowCardinality(_) => true,
+        ColumnType::Nullable(inner) | ColumnType::Array(inner) => has_low_cardinality(inner),
+        ColumnType::Map(key, value) => has_low_cardinality(key) || has_low_cardinality(value),
+        ColumnType::Tuple(elements) => elements.iter().any(|(_, t)| has_low_cardinality(t)),
+        _ => false,
+    }
+}
+
+/// A TinyLog, Log or StripeLog table directory whose files match
+/// `sizes.json`.
+fn open_log_table(path: &str) -> Result<&Path, Box<dyn Error>> {
+    let dir = Path::new(path);
+    if !dir.is_dir() {
+        return Err(format!("{} is not a table directory", path).into());
+    }
+    check_sizes(dir)?;
+    Ok(dir)
+}
+
+/// The columns of a StripeLog table's first block and the rows of all of
+/// them, from `index.mrk`, which lists the columns of every block with
+/// their positions in `data.bin`. It is compressed like `data.bin`, except
+/// in tables written by some older versions.
+fn read_stripe_index(dir: &Path) -> io::Result<(Vec<(String, String)>, u64)> {
+    let path = dir.join("index.mrk");
+    let index = match read_frames(&mut File::open(&path)?, &path)? {
+        Some(_) => PartDir::new(dir).read_decompressed("index.mrk")?,
+        None => std::fs::read(&path)?,
+    };
+    let mut reader = Cursor::new(&index);
+    let mut columns = None;
+    let mut rows = 0;
+    while (reader.position() as usize) < index.len() {
+        let count = read_var_u64(&mut reader)?;
+        rows += read_var_u64(&mut reader)?;
+        let mut block = Vec::new();
+        for _ in 0..count {
+            let name = read_string(&mut reader)?;
+            let type_str = read_string(&mut reader)?;
+            // The position of the column in data.bin.
+            reader.read_u64::<LittleEndian>()?;
+            reader.read_u64::<LittleEndian>()?;
+            block.push((name, type_str));
+        }
+        columns.get_or_insert(block);
+    }
+    Ok((columns.unwrap_or_default(), rows))
+}
+
+/// Whether the DuckDB type of a column is only known from its values.
+fn depends_on_values(column_type: &ColumnType) -> bool {
+    match column_type {
+        ColumnType::Dynamic(_) | ColumnType::Json(_) => true,
+        ColumnType::Nullable(inner)
+        | ColumnType::LowCardinality(inner)
+        | ColumnType::Array(inner) => depends_on_values(inner),
+        ColumnType::Map(key, value) => depends_on_values(key) || depends_on_values(value),
+        ColumnType::Tuple(elements) | ColumnType::Variant(elements) => {
+            elements.iter().any(|(_, t)| depends_on_values(t))
+        }
+        _ => false,
+    }
+}
+
+/// Reads a TinyLog, Log or StripeLog table directory, told apart by their
+/// index files.
+fn read_log_table(
+    path: &str,
+    schema: Option<&[(String, ColumnType)]>,
+    options: &NativeOptions,
+) -> Result<Vec<Column>, Box<dyn Error>> {
+    let dir = open_log_table(path)?;
+    if dir.join("index.mrk").exists() {
+        // StripeLog: the Native blocks of every insert in one file.
+        let data = PartDir::new(dir).read_decompressed("data.bin")?;
+        if data.is_empty() {
+            return Err(format!("{} holds no blocks to read the columns from", path).into());
+        }
+        return Ok(read_native_format(&mut Cursor::new(data), options)?);
+    }
+    let schema = schema.ok_or(
+        "Log and TinyLog tables do not store their columns, pass them with schema or metadata",
+    )?;
+    let columns = read_log_streams(dir, schema)?;
+    Ok(resolve_part_columns(columns, options)?)
+}
+
+/// The columns a scan of a Log-family table returns and its row count,
+/// found without reading its values, except for StripeLog columns whose
+/// type depends on them.
+fn log_table_schema(
+    path: &str,
+    schema: Option<&[(String, ColumnType)]>,
+    options: &NativeOptions,
+) -> Result<(Vec<Column>, u64), Box<dyn Error>> {
+    let dir = open_log_table(path)?;
+    if dir.join("index.mrk").exists() {
+        let (columns, rows) = read_stripe_index(dir)?;
+        if columns.is_empty() {
+            return Err(format!("{} holds no blocks to read the columns from", path).into());
+        }
+        let schema: Vec<(String, ColumnType)> = columns
+            .into_iter()
+            .map(|(name, type_str)| (name, parse_column_type(&type_str).0))
+            .collect();
+        if schema.iter().any(|(_, t)| depends_on_values(t)) {
+            let columns = read_log_table(path, None, options)?;
+            let rows = columns.first().map_or(0, |column| column.data.len());
+            return Ok((columns, rows as u64));
+        }
+        return Ok((schema_columns(&schema, options)?, rows));
+    }
+    let schema = schema.ok_or(
+        "Log and TinyLog tables do not store their columns, pass them with schema or metadata",
+    )?;
+    check_log_schema(schema)?;
+    let rows = count_rows(&PartDir::new(dir), schema)?;
+    Ok((schema_columns(schema, options)?, rows))
+}
+
+#[derive(Debug)]
+struct LogBindData {
+    path: String,
+    schema: Option<Vec<(String, ColumnType)>>,
+    options: NativeOptions,
+}
+
+struct ClickHouseLogVTab;
+
+impl VTab for ClickHouseLogVTab {
+    type InitData = ClickHouseInitData;
+    type BindData = LogBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let path = bind.get_parameter(0).to_string();
+        let schema = match (bind.get_named_parameter("schema"), metadata_schema(bind)?) {
+            (Some(_), Some(_)) => return Err("Pass either schema or metadata, not both".into()),
+            (Some(value), None) => Some(parse_schema(&value.to_string())?),
+            (None, metadata) => metadata,
+        };
+        let options = NativeOptions::from_bind(bind)?;
+
+        // The values are only read in init.
+        let (columns, rows) = log_table_schema(&path, schema.as_deref(), &options)?;
+        bind_schema(bind, &columns, Some((rows, true)));
+
+        Ok(LogBindData {
+            path,
+            schema,
+            options,
+        })
+    }
+
+    fn init(info: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        let bind_data = info.get_bind_data::<LogBindData>();
+        let bind_data = unsafe { &*bind_data };
+
+        let columns = read_log_table(
+            &bind_data.path,
+            bind_data.schema.as_deref(),
+            &bind_data.options,
+        )?;
+        Ok(ClickHouseInitData::new(columns))
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        func.get_init_data().scan(output)?;
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        let mut parameters = NativeOptions::named_parameters();
+        // A STRUCT of any shape, so declared as ANY.
+        parameters.push((
+            "schema".to_string(),
+            LogicalTypeHandle::from(LogicalTypeId::Any),
+        ));
+        parameters.push((
+            "metadata".to_string(),
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        ));
+        Some(parameters)
+    }
+}
+
+pub fn register_log_function(con: &Connection) -> Result<(), Box<dyn Error>> {
+    con.register_table_function::<ClickHouseLogVTab>("clickhouse_log")?;
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use super::{default_value, read_column_data, writer, ColumnData, ColumnType};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+/// `SharedDictionariesWithAdditionalKeys`, the keys serialization ClickHouse
+/// writes in the prefix of a LowCardinality column.
+const KEYS_VERSION: u64 = 1;
+/// Set in an index header when the keys are in a dictionary shared by the
+/// whole part. Native streams carry the keys with each index chunk instead.
+const NEED_GLOBAL_DICTIONARY: u64 = 1 << 8;
+/// Set in an index header when the keys used by the chunk follow it.
+const HAS_ADDITIONAL_KEYS: u64 = 1 << 9;
+/// Set in an index header when the shared dictionary is replaced by the next
+/// one in the dictionary stream.
+const NEED_UPDATE_DICTIONARY: u64 = 1 << 10;
+/// Low bits of an index header: the width of the indexes, 0 to 3 for
+/// UInt8 to UInt64.
+const INDEX_TYPE_MASK: u64 = 0xFF;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The type the keys are serialized as and whether index 0 stands for NULL.
+fn key_type(inner: &ColumnType) -> (&ColumnType, bool) {
+    match inner {
+        ColumnType::Nullable(key_type) => (key_type, true),
+        key_type => (key_type, false),
+    }
+}
+
+pub(crate) fn read_prefix(reader: &mut impl Read) -> io::Result<()> {
+    let version = reader.read_u64::<LittleEndian>()?;
+    if version != KEYS_VERSION {
+        return Err(invalid(format!(
+            "Unsupported LowCardinality keys version {}",
+            version
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the index chunks of a LowCardinality column until `rows` values are
+/// read, looking each index up in the keys carried by its chunk.
+pub(crate) fn read_low_cardinality_data(
+    reader: &mut impl Read,
+    inner: &ColumnType,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    read_shared_low_cardinality_data(reader, None, inner, rows)
+}
+
+/// Reads the index chunks of a LowCardinality column of a MergeTree part.
+/// Besides the keys of each chunk, indexes may point into a dictionary shared
+/// by the chunks that follow it, read from the `keys` stream whenever a
+/// chunk asks for a new one.
+pub(crate) fn read_shared_low_cardinality_data(
+    reader: &mut impl Read,
+    mut keys: Option<&mut dyn Read>,
+    inner: &ColumnType,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    let (key_type, nullable) = key_type(inner);
+    let mut data = Vec::with_capacity(rows as usize);
+    let mut dictionary: Option<Vec<ColumnData>> = None;
+
+    while (data.len() as u64) < rows {
+        let header = reader.read_u64::<LittleEndian>()?;
+        let global = header & NEED_GLOBAL_DICTIONARY != 0;
+        if keys.is_none() && (global || header & HAS_ADDITIONAL_KEYS == 0) {
+            return Err(invalid(
+                "LowCardinality columns with a global dictionary are not supported".to_string(),
+            ));
+        }
+        let width = match header & INDEX_TYPE_MASK {
+            index_type @ 0..=3 => 1usize << index_type,
+            index_type => {
+                return Err(invalid(format!(
+                    "Unknown LowCardinality index type {}",
+                    index_type
+                )))
+            }
+        };
+
+        if let (true, Some(keys)) = (global, keys.as_mut()) {
+            if dictionary.is_none() || header & NEED_UPDATE_DICTIONARY != 0 {
+                let num_keys = keys.read_u64::<LittleEndian>()?;
+                dictionary = Some(read_column_data(keys, key_type, num_keys)?);
+            }
+        }
+        let shared: &[ColumnData] = match &dictionary {
+            Some(dictionary) if global => dictionary,
+            _ => &[],
+        };
+        let additional = if header & HAS_ADDITIONAL_KEYS != 0 {
+            let num_keys = reader.read_u64::<LittleEndian>()?;
+            read_column_data(reader, key_type, num_keys)?
+        } else {
+            Vec::new()
+        };
+
+        let num_indexes = reader.read_u64::<LittleEndian>()?;
+        for _ in 0..num_indexes {
+            let mut index = [0u8; 8];
+            reader.read_exact(&mut index[..width])?;
+            let index = u64::from_le_bytes(index) as usize;
+            if nullable && index == 0 {
+                data.push(ColumnData::Null);
+                continue;
+            }
+            // Keys of the chunk are numbered after those of the dictionary.
+            let key = match index.checked_sub(shared.len()) {
+                None => shared.get(index),
+                Some(index) => additional.get(index),
+            };
+            let key = key.ok_or_else(|| {
+                invalid(format!(
+                    "LowCardinality index {} out of {} keys",
+                    index,
+                    shared.len() + additional.len()
+                ))
+            })?;
+            data.push(key.clone());
+        }
+    }
+
+    Ok(data)
+}
+
+pub(crate) fn write_prefix(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&KEYS_VERSION.to_le_bytes())
+}
+
+/// Writes a LowCardinality column as a single index chunk carrying its own
+/// keys, in order of first appearance. For a nullable dictionary, key 0 is
+/// the placeholder for NULL.
+pub(crate) fn write_low_cardinality_data(
+    out: &mut impl Write,
+    inner: &ColumnType,
+    values: &[&ColumnData],
+) -> io::Result<()> {
+    let (key_type, nullable) = key_type(inner);
+    let default = default_value(key_type);
+    let mut keys: Vec<&ColumnData> = Vec::new();
+    if nullable {
+        keys.push(&default);
+    }
+    let mut positions = HashMap::new();
+    let mut indexes = Vec::with_capacity(values.len());
+    for &value in values {
+        let value = match value {
+            ColumnData::Null if nullable => {
+                indexes.push(0);
+                continue;
+            }
+            ColumnData::Null => &default,
+            value => value,
+        };
+        let index = *positions.entry(format!("{:?}", value)).or_insert_with(|| {
+            keys.push(value);
+            keys.len() as u64 - 1
+        });
+        indexes.push(index);
+    }
+
+    let index_type: u64 = match keys.len() {
+        0..=0xFF => 0,
+        0x100..=0xFFFF => 1,
+        0x1_0000..=0xFFFF_FFFF => 2,
+        _ => 3,
+    };
+    out.write_all(&(index_type | HAS_ADDITIONAL_KEYS).to_le_bytes())?;
+    out.write_all(&(keys.len() as u64).to_le_bytes())?;
+    writer::write_column_data(out, key_type, &keys)?;
+    out.write_all(&(indexes.len() as u64).to_le_bytes())?;
+    for index in indexes {
+        out.write_all(&index.to_le_bytes()[..1 << index_type])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,511 @@
+use super::{
+    compression::{read_frames, Frame},
+    has_block_info, logical_type, open_native_file, parse_column_type, read_block_header,
+    read_column_block, read_column_header, resolve_column, set_value, write_column, ColumnData,
+    ColumnType, NativeOptions, GZIP_MAGIC, ZSTD_MAGIC,
+};
+use duckdb::{
+    core::{DataChunkHandle, Inserter, LogicalTypeHandle, LogicalTypeId},
+    vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
+    Connection, Result,
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufRead, Read},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// One column of a Native file as `clickhouse_native_schema` lists it.
+#[derive(Debug)]
+struct SchemaRow {
+    name: String,
+    clickhouse_type: String,
+    duckdb_type: String,
+    supported: bool,
+}
+
+pub(crate) fn contains_unsupported(column_type: &ColumnType) -> bool {
+    match column_type {
+        ColumnType::Unsupported(_) => true,
+        ColumnType::Nullable(inner)
+        | ColumnType::LowCardinality(inner)
+        | ColumnType::Array(inner) => contains_unsupported(inner),
+        ColumnType::Map(key, value) => contains_unsupported(key) || contains_unsupported(value),
+        ColumnType::Tuple(elements) | ColumnType::Variant(elements) => elements
+            .iter()
+            .any(|(_, element_type)| contains_unsupported(element_type)),
+        _ => false,
+    }
+}
+
+/// Quotes a STRUCT or UNION field name the way DuckDB prints it.
+fn field_name(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+fn fields_name(handle: &LogicalTypeHandle) -> String {
+    (0..handle.num_children())
+        .map(|idx| {
+            format!(
+                "{} {}",
+                field_name(&handle.child_name(idx)),
+                type_name(&handle.child(idx))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Renders a logical type as DuckDB's SQL name for it.
+pub(crate) fn type_name(handle: &LogicalTypeHandle) -> String {
+    if let Some(alias) = handle.get_alias() {
+        return alias;
+    }
+    let name = match handle.id() {
+        LogicalTypeId::Boolean => "BOOLEAN",
+        LogicalTypeId::Tinyint => "TINYINT",
+        LogicalTypeId::Smallint => "SMALLINT",
+        LogicalTypeId::Integer => "INTEGER",
+        LogicalTypeId::Bigint => "BIGINT",
+        LogicalTypeId::UTinyint => "UTINYINT",
+        LogicalTypeId::USmallint => "USMALLINT",
+        LogicalTypeId::UInteger => "UINTEGER",
+        LogicalTypeId::UBigint => "UBIGINT",
+        LogicalTypeId::Hugeint => "HUGEINT",
+        LogicalTypeId::Float => "FLOAT",
+        LogicalTypeId::Double => "DOUBLE",
+        LogicalTypeId::Date => "DATE",
+        LogicalTypeId::Time => "TIME",
+        LogicalTypeId::TimeNs => "TIME_NS",
+        LogicalTypeId::Timestamp => "TIMESTAMP",
+        LogicalTypeId::TimestampNs => "TIMESTAMP_NS",
+        LogicalTypeId::TimestampTZ => "TIMESTAMP WITH TIME ZONE",
+        LogicalTypeId::Interval => "INTERVAL",
+        LogicalTypeId::Uuid => "UUID",
+        LogicalTypeId::Varchar => "VARCHAR",
+        LogicalTypeId::Blob => "BLOB",
+        LogicalTypeId::Decimal => {
+            return format!(
+                "DECIMAL({},{})",
+                handle.decimal_width(),
+                handle.decimal_scale()
+            )
+        }
+        LogicalTypeId::List => return format!("{}[]", type_name(&handle.child(0))),
+        LogicalTypeId::Map => {
+            return format!(
+                "MAP({}, {})",
+                type_name(&handle.child(0)),
+                type_name(&handle.child(1))
+            )
+        }
+        LogicalTypeId::Struct => return format!("STRUCT({})", fields_name(handle)),
+        LogicalTypeId::Union => return format!("UNION({})", fields_name(handle)),
+        other => return format!("{:?}", other).to_uppercase(),
+    };
+    name.to_string()
+}
+
+/// Lists the columns of the first block. Its values are read, since column
+/// headers are interleaved with the data, and resolved so the DuckDB type is
+/// the one `clickhouse_native` would give the column (for `Dynamic`, from
+/// the types found in that block). A column whose values cannot be read
+/// ends the listing: the headers after it cannot be located.
+fn read_schema(reader: &mut impl BufRead, options: &NativeOptions) -> io::Result<Vec<SchemaRow>> {
+    let with_block_info = has_block_info(reader)?;
+    let mut binary_types = options.binary_types;
+    let Some((block_columns, block_rows)) = read_block_header(reader, with_block_info)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut rows = Vec::with_capacity(block_columns as usize);
+    for _ in 0..block_columns {
+        let (name, clickhouse_type) = read_column_header(reader, &mut binary_types)?;
+        let (mut column_type, _type_params) = parse_column_type(&clickhouse_type);
+        let mut supported = !contains_unsupported(&column_type);
+        let readable = supported || block_rows == 0;
+        if readable {
+            match read_column_block(
+                reader,
+                &column_type,
+                with_block_info,
+                binary_types.unwrap_or_default(),
+                block_rows,
+            ) {
+                Ok(mut data) => {
+                    resolve_column(&mut column_type, data.iter_mut().collect(), options)?
+                }
+                Err(_) => supported = false,
+            }
+        }
+        rows.push(SchemaRow {
+            name,
+            clickhouse_type,
+            duckdb_type: type_name(&logical_type(&column_type)),
+            supported,
+        });
+        if !supported && block_rows > 0 {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+#[derive(Debug)]
+struct SchemaBindData {
+    rows: Vec<SchemaRow>,
+}
+
+#[derive(Debug)]
+struct SchemaInitData {
+    current_row: AtomicUsize,
+}
+
+struct ClickHouseSchemaVTab;
+
+impl VTab for ClickHouseSchemaVTab {
+    type InitData = SchemaInitData;
+    type BindData = SchemaBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let filepath = bind.get_parameter(0).to_string();
+        let options = NativeOptions::from_bind(bind)?;
+
+        let mut reader = open_native_file(&filepath)?;
+        let rows = read_schema(&mut reader, &options)?;
+
+        bind.add_result_column(
+            "column_name",
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        );
+        bind.add_result_column(
+            "clickhouse_type",
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        );
+        bind.add_result_column(
+            "duckdb_type",
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        );
+        bind.add_result_column("supported", LogicalTypeHandle::from(LogicalTypeId::Boolean));
+
+        Ok(SchemaBindData { rows })
+    }
+
+    fn init(_: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        Ok(SchemaInitData {
+            current_row: AtomicUsize::new(0),
+        })
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        let rows = &func.get_bind_data().rows;
+        let current_row = func.get_init_data().current_row.load(Ordering::Relaxed);
+        let batch_size = 1024.min(rows.len() - current_row);
+
+        for (idx, row) in rows[current_row..current_row + batch_size]
+            .iter()
+            .enumerate()
+        {
+            output.flat_vector(0).insert(idx, row.name.as_str());
+            output
+                .flat_vector(1)
+                .insert(idx, row.clickhouse_type.as_str());
+            output.flat_vector(2).insert(idx, row.duckdb_type.as_str());
+            set_value(&mut output.flat_vector(3), idx, row.supported);
+        }
+
+        func.get_init_data()
+            .current_row
+            .fetch_add(batch_size, Ordering::Relaxed);
+        output.set_len(batch_size);
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        Some(NativeOptions::named_parameters())
+    }
+}
+
+/// Counts the bytes consumed from the wrapped reader, to locate blocks and
+/// size their columns.
+struct CountingReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// One block of a Native file as `clickhouse_native_blocks` lists it.
+/// Blocks of a file compressed whole have no place of their own in it.
+#[derive(Debug)]
+struct BlockRow {
+    offset: Option<u64>,
+    compressed_bytes: Option<u64>,
+    uncompressed_bytes: u64,
+    rows: u64,
+    column_bytes: Vec<(String, u64)>,
+}
+
+/// How a Native file is stored, which places its blocks in the file.
+enum FileLayout {
+    Plain,
+    /// ClickHouse's checksummed compressed blocks.
+    Frames(Vec<Frame>),
+    /// A zstd or gzip stream.
+    Whole,
+}
+
+impl FileLayout {
+    fn read(path: &str) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut magic = Vec::new();
+        (&mut file)
+            .take(ZSTD_MAGIC.len() as u64)
+            .read_to_end(&mut magic)?;
+        if magic.starts_with(&ZSTD_MAGIC) || magic.starts_with(&GZIP_MAGIC) {
+            return Ok(FileLayout::Whole);
+        }
+        Ok(match read_frames(&mut file, Path::new(path))? {
+            Some(frames) => FileLayout::Frames(frames),
+            None => FileLayout::Plain,
+        })
+    }
+
+    /// The offset and compressed size of the block in `start..end` of the
+    /// decompressed stream. A compressed block is counted in the Native
+    /// block its data starts in, which is then located at the compressed
+    /// block holding its first byte.
+    fn locate(&self, start: u64, end: u64) -> (Option<u64>, Option<u64>) {
+        let frames = match self {
+            FileLayout::Plain => return (Some(start), Some(end - start)),
+            FileLayout::Whole => return (None, None),
+            FileLayout::Frames(frames) => frames,
+        };
+        let mut offset = None;
+        let mut size = 0;
+        let mut position = 0;
+        for frame in frames {
+            if position <= start && start < position + frame.decompressed {
+                offset = Some(frame.offset);
+            }
+            if (start..end).contains(&position) {
+                size += frame.size;
+            }
+            position += frame.decompressed;
+        }
+        (offset, Some(size))
+    }
+}
+
+/// Walks the blocks of a file, decoding each column only to find where it
+/// ends. Sizes are counted on the decompressed stream, and `layout` places
+/// them in the file.
+fn read_blocks(
+    reader: impl BufRead,
+    options: &NativeOptions,
+    layout: &FileLayout,
+) -> io::Result<Vec<BlockRow>> {
+    let mut reader = CountingReader {
+        inner: reader,
+        position: 0,
+    };
+    let with_block_info = has_block_info(&mut reader)?;
+    let mut binary_types = options.binary_types;
+    let mut blocks: Vec<BlockRow> = Vec::new();
+
+    loop {
+        let offset = reader.position;
+        let Some((block_columns, block_rows)) = read_block_header(&mut reader, with_block_info)?
+        else {
+            break;
+        };
+        if !blocks.is_empty() && block_rows == 0 {
+            break;
+        }
+
+        let mut column_bytes = Vec::with_capacity(block_columns as usize);
+        for _ in 0..block_columns {
+            let (name, type_str) = read_column_header(&mut reader, &mut binary_types)?;
+            let (column_type, _type_params) = parse_column_type(&type_str);
+            if contains_unsupported(&column_type) && block_rows > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Cannot size column {} of unsupported type {}",
+                        name, type_str
+                    ),
+                ));
+            }
+            let start = reader.position;
+            read_column_block(
+                &mut reader,
+                &column_type,
+                with_block_info,
+                binary_types.unwrap_or_default(),
+                block_rows,
+            )?;
+            column_bytes.push((name, reader.position - start));
+        }
+
+        let (file_offset, compressed_bytes) = layout.locate(offset, reader.position);
+        blocks.push(BlockRow {
+            offset: file_offset,
+            compressed_bytes,
+            uncompressed_bytes: reader.position - offset,
+            rows: block_rows,
+            column_bytes,
+        });
+    }
+    Ok(blocks)
+}
+
+fn block_column_types() -> Vec<(&'static str, ColumnType)> {
+    vec![
+        ("block", ColumnType::Int64),
+        ("offset", ColumnType::Nullable(Box::new(ColumnType::Int64))),
+        (
+            "compressed_bytes",
+            ColumnType::Nullable(Box::new(ColumnType::Int64)),
+        ),
+        ("uncompressed_bytes", ColumnType::Int64),
+        ("rows", ColumnType::Int64),
+        (
+            "column_bytes",
+            ColumnType::Map(Box::new(ColumnType::String), Box::new(ColumnType::Int64)),
+        ),
+    ]
+}
+
+fn block_values(index: usize, block: &BlockRow) -> Vec<ColumnData> {
+    vec![
+        ColumnData::Int64(index as i64),
+        block
+            .offset
+            .map_or(ColumnData::Null, |offset| ColumnData::Int64(offset as i64)),
+        block
+            .compressed_bytes
+            .map_or(ColumnData::Null, |bytes| ColumnData::Int64(bytes as i64)),
+        ColumnData::Int64(block.uncompressed_bytes as i64),
+        ColumnData::Int64(block.rows as i64),
+        ColumnData::Array(
+            block
+                .column_bytes
+                .iter()
+                .map(|(name, bytes)| {
+                    ColumnData::Tuple(vec![
+                        ColumnData::String(name.clone()),
+                        ColumnData::Int64(*bytes as i64),
+                    ])
+                })
+                .collect(),
+        ),
+    ]
+}
+
+#[derive(Debug)]
+struct BlocksBindData {
+    blocks: Vec<BlockRow>,
+}
+
+#[derive(Debug)]
+struct BlocksInitData {
+    current_row: AtomicUsize,
+}
+
+struct ClickHouseBlocksVTab;
+
+impl VTab for ClickHouseBlocksVTab {
+    type InitData = BlocksInitData;
+    type BindData = BlocksBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let filepath = bind.get_parameter(0).to_string();
+        let options = NativeOptions::from_bind(bind)?;
+
+        let layout = FileLayout::read(&filepath)?;
+        let blocks = read_blocks(open_native_file(&filepath)?, &options, &layout)?;
+
+        for (name, column_type) in block_column_types() {
+            bind.add_result_column(name, logical_type(&column_type));
+        }
+
+        Ok(BlocksBindData { blocks })
+    }
+
+    fn init(_: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        Ok(BlocksInitData {
+            current_row: AtomicUsize::new(0),
+        })
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        let blocks = &func.get_bind_data().blocks;
+        let current_row = func.get_init_data().current_row.load(Ordering::Relaxed);
+        let batch_size = 1024.min(blocks.len() - current_row);
+
+        let rows: Vec<Vec<ColumnData>> = (current_row..current_row + batch_size)
+            .map(|index| block_values(index, &blocks[index]))
+            .collect();
+        for (col_idx, (_, column_type)) in block_column_types().iter().enumerate() {
+            let values: Vec<&ColumnData> = rows.iter().map(|row| &row[col_idx]).collect();
+            write_column(output, col_idx, column_type, &values);
+        }
+
+        func.get_init_data()
+            .current_row
+            .fetch_add(batch_size, Ordering::Relaxed);
+        output.set_len(batch_size);
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        Some(NativeOptions::named_parameters())
+    }
+}
+
+pub fn register_metadata_functions(con: &Connection) -> Result<(), Box<dyn Error>> {
+    con.register_table_function::<ClickHouseSchemaVTab>("clickhouse_native_schema")?;
+    con.register_table_function::<ClickHouseBlocksVTab>("clickhouse_native_blocks")?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use super::{Column, ColumnData, ColumnType};
+use std::io;
+
+/// Splits `n.a` into the group `n` and the field `a`.
+fn split_nested_name(name: &str) -> Option<(&str, &str)> {
+    name.split_once('.')
+        .filter(|(group, field)| !group.is_empty() && !field.is_empty())
+}
+
+/// Regroups the sibling `n.a Array(T1)`, `n.b Array(T2)` columns a flattened
+/// `Nested(a T1, b T2)` is written as into one `n Array(Tuple(a T1, b T2))`
+/// column, placed where the first sibling was. A prefix is only regrouped
+/// when every column sharing it is an Array; the siblings must then have
+/// arrays of the same length on every row, like ClickHouse enforces.
+pub(crate) fn group_nested_columns(columns: Vec<Column>) -> io::Result<Vec<Column>> {
+    let is_group = |group: &str| {
+        columns
+            .iter()
+            .filter(|column| split_nested_name(&column.name).map(|(g, _)| g) == Some(group))
+            .all(|column| matches!(column.type_, ColumnType::Array(_)))
+            && !columns.iter().any(|column| column.name == group)
+    };
+    let groups: Vec<String> = columns
+        .iter()
+        .filter_map(|column| split_nested_name(&column.name).map(|(group, _)| group))
+        .filter(|group| is_group(group))
+        .map(str::to_string)
+        .collect();
+
+    let mut grouped: Vec<Column> = Vec::with_capacity(columns.len());
+    for column in columns {
+        let group = split_nested_name(&column.name)
+            .map(|(group, field)| (group.to_string(), field.to_string()))
+            .filter(|(group, _)| groups.contains(group));
+        let Some((group, field)) = group else {
+            grouped.push(column);
+            continue;
+        };
+        let ColumnType::Array(field_type) = column.type_ else {
+            unreachable!()
+        };
+
+        match grouped.iter_mut().find(|c| c.name == group) {
+            None => grouped.push(Column {
+                name: group,
+                type_: ColumnType::Array(Box::new(ColumnType::Tuple(vec![(field, *field_type)]))),
+                data: column
+                    .data
+                    .into_iter()
+                    .map(|value| match value {
+                        ColumnData::Array(items) => ColumnData::Array(
+                            items
+                                .into_iter()
+                                .map(|item| ColumnData::Tuple(vec![item]))
+                                .collect(),
+                        ),
+                        other => other,
+                    })
+                    .collect(),
+            }),
+            Some(target) => {
+                if let ColumnType::Array(element) = &mut target.type_ {
+                    if let ColumnType::Tuple(fields) = element.as_mut() {
+                        fields.push((field.clone(), *field_type));
+                    }
+                }
+                for (row, (entries, value)) in target.data.iter_mut().zip(column.data).enumerate() {
+                    let (ColumnData::Array(entries), ColumnData::Array(items)) = (entries, value)
+                    else {
+                        continue;
+                    };
+                    if entries.len() != items.len() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Nested column {}: {}.{} has {} elements at row {}, its siblings have {}",
+                                group,
+                                group,
+                                field,
+                                items.len(),
+                                row,
+                                entries.len()
+                            ),
+                        ));
+                    }
+                    for (entry, item) in entries.iter_mut().zip(items) {
+                        if let ColumnData::Tuple(values) = entry {
+                            values.push(item);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(grouped)
+}
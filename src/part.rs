@@ -0,0 +1,1025 @@
+use super::{
+    bind_schema,
+    compression::{CompressedFile, ReadSeek},
+    default_value, geo, low_cardinality, nested, parse_column_type,
+    pruning::{self, PartFilter},
+    read_column_data, read_var_u64, resolve_column, split_by_offsets,
+    table_metadata::{metadata_schema, TableSchema},
+    ClickHouseInitData, Column, ColumnData, ColumnType, NativeOptions, END_OF_GRANULE_FLAG,
+};
+use byteorder::{LittleEndian, ReadBytesExt};
+use duckdb::{
+    core::{DataChunkHandle, LogicalTypeHandle, LogicalTypeId},
+    vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
+    Connection, Result,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    io::{self, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// ClickHouse's `escapeForFileName`: ASCII letters, digits and `_` are kept
+/// in the file names of a column's streams, other bytes become `%XX`.
+pub(crate) fn escape_file_name(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Reverses `escapeForFileName`.
+pub(crate) fn unescape_file_name(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escaped = (bytes[idx] == b'%')
+            .then(|| name.get(idx + 1..idx + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                unescaped.push(byte);
+                idx += 3;
+            }
+            None => {
+                unescaped.push(bytes[idx]);
+                idx += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// The files of a part: a directory, or a part's entries in a BACKUP,
+/// whose `.backup` metadata can store a file once for several parts and
+/// leaves empty files out.
+#[derive(Debug, Clone)]
+pub(crate) struct PartDir {
+    path: PathBuf,
+    /// For a part in a backup, the file each name is stored in, or `None`
+    /// for an empty file.
+    files: Option<HashMap<String, Option<PathBuf>>>,
+}
+
+impl PartDir {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        PartDir {
+            path: path.into(),
+            files: None,
+        }
+    }
+
+    pub(crate) fn in_backup(path: PathBuf, files: HashMap<String, Option<PathBuf>>) -> Self {
+        PartDir {
+            path,
+            files: Some(files),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn exists(&self, name: &str) -> bool {
+        match &self.files {
+            Some(files) => files.contains_key(name),
+            None => self.path.join(name).exists(),
+        }
+    }
+
+    /// The file `name` is stored in, or `None` for an empty file left out
+    /// of a backup.
+    fn file_path(&self, name: &str) -> io::Result<Option<PathBuf>> {
+        let Some(files) = &self.files else {
+            return Ok(Some(self.path.join(name)));
+        };
+        files.get(name).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No such file or directory: {}",
+                    self.path.join(name).display()
+                ),
+            )
+        })
+    }
+
+    pub(crate) fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        match self.file_path(name)? {
+            Some(path) => std::fs::read(path),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub(crate) fn read_to_string(&self, name: &str) -> io::Result<String> {
+        String::from_utf8(self.read(name)?)
+            .map_err(|_| invalid(format!("{} is not valid UTF-8", name)))
+    }
+
+    /// Opens a file of compressed blocks, which are read as needed.
+    pub(crate) fn read_compressed(&self, name: &str) -> io::Result<CompressedFile> {
+        let file: Box<dyn ReadSeek> = match self.file_path(name)? {
+            Some(path) => Box::new(File::open(path)?),
+            None => Box::new(Cursor::new(Vec::new())),
+        };
+        CompressedFile::open(file, &self.path.join(name))
+    }
+
+    /// Reads and decompresses a whole file of compressed blocks.
+    pub(crate) fn read_decompressed(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.read_compressed(name)?.read_all()
+    }
+
+    fn file_names(&self) -> io::Result<Vec<String>> {
+        let mut names: Vec<String> = match &self.files {
+            Some(files) => files.keys().cloned().collect(),
+            None => std::fs::read_dir(&self.path)?
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .collect(),
+        };
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Reads a back-quoted name at the start of `line`, returning it and the
+/// rest of the line.
+fn read_back_quoted(line: &str) -> Option<(String, &str)> {
+    let mut chars = line.strip_prefix('`')?.char_indices();
+    let mut name = String::new();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => name.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                '0' => '\0',
+                c => c,
+            }),
+            '`' => return Some((name, &line[idx + 2..])),
+            c => name.push(c),
+        }
+    }
+    None
+}
+
+/// Reads `columns.txt`, a `columns format version: 1` line, an `N columns:`
+/// line and a back-quoted name and type per column.
+fn read_part_columns(dir: &PartDir) -> io::Result<Vec<(String, ColumnType)>> {
+    let text = dir.read_to_string("columns.txt")?;
+    let mut lines = text.lines();
+    if lines.next() != Some("columns format version: 1") {
+        return Err(invalid("Unknown columns.txt format".to_string()));
+    }
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.strip_suffix(" columns:"))
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(|| invalid("Invalid column count in columns.txt".to_string()))?;
+
+    let columns = lines
+        .take(count)
+        .map(|line| {
+            let (name, type_str) = read_back_quoted(line)
+                .ok_or_else(|| invalid(format!("Invalid column in columns.txt: {}", line)))?;
+            Ok((name, parse_column_type(type_str.trim()).0))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if columns.len() != count {
+        return Err(invalid(format!(
+            "columns.txt lists {} of {} columns",
+            columns.len(),
+            count
+        )));
+    }
+    Ok(columns)
+}
+
+fn read_part_rows(dir: &PartDir) -> io::Result<u64> {
+    dir.read_to_string("count.txt")?
+        .trim()
+        .parse()
+        .map_err(|_| invalid("Invalid row count in count.txt".to_string()))
+}
+
+/// Names of the columns `serialization.json` marks as sparse. Parts written
+/// before sparse serialization existed have no such file.
+fn read_sparse_columns(dir: &PartDir) -> io::Result<Vec<String>> {
+    let Ok(text) = dir.read_to_string("serialization.json") else {
+        return Ok(Vec::new());
+    };
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|err| invalid(format!("Invalid serialization.json: {}", err)))?;
+    let columns = json["columns"].as_array().cloned().unwrap_or_default();
+
+    let mut sparse = Vec::new();
+    for column in columns {
+        let name = column["name"].as_str().unwrap_or_default().to_string();
+        let sparse_subcolumn = column["subcolumns"]
+            .as_array()
+            .is_some_and(|subcolumns| subcolumns.iter().any(|sub| sub["kind"] == "Sparse"));
+        if sparse_subcolumn {
+            return Err(invalid(format!(
+                "Cannot read column {}, its sparse subcolumns are not supported",
+                name
+            )));
+        }
+        if column["kind"] == "Sparse" {
+            sparse.push(name);
+        }
+    }
+    Ok(sparse)
+}
+
+/// Whether a part can be read as the streams of `column_type`. The types
+/// whose structure is written in a stream prefix are not supported.
+pub(crate) fn supported(column_type: &ColumnType) -> bool {
+    match column_type {
+        ColumnType::Nullable(inner) | ColumnType::LowCardinality(inner) => supported(inner),
+        ColumnType::Array(inner) => supported(inner),
+        ColumnType::Map(key, value) => supported(key) && supported(value),
+        ColumnType::Tuple(elements) => elements.iter().all(|(_, t)| supported(t)),
+        ColumnType::Variant(_)
+        | ColumnType::Dynamic(_)
+        | ColumnType::Json(_)
+        | ColumnType::Unsupported(_) => false,
+        _ => true,
+    }
+}
+
+/// The storage type of a geo column with the element names its streams are
+/// stored under: ClickHouse's `Point` is an unnamed `Tuple(Float64, Float64)`.
+fn geo_stream_type(column_type: ColumnType) -> ColumnType {
+    match column_type {
+        ColumnType::Array(inner) => ColumnType::Array(Box::new(geo_stream_type(*inner))),
+        ColumnType::Tuple(elements) => ColumnType::Tuple(
+            elements
+                .into_iter()
+                .enumerate()
+                .map(|(idx, (_, element_type))| ((idx + 1).to_string(), element_type))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Rows in a granule of a part with fixed granularity, whose `.mrk` marks
+/// do not record them.
+const DEFAULT_GRANULARITY: u64 = 8192;
+
+/// A mark of a Wide part's stream: where a granule starts, as the offset of a
+/// compressed block and an offset in its data, and its row count.
+struct Mark {
+    compressed: u64,
+    decompressed: u64,
+    rows: u64,
+}
+
+/// Reads the marks of a stream, from `.cmrk2`, `.mrk2` or the older `.mrk`
+/// without row counts.
+fn read_marks(dir: &PartDir, name: &str) -> io::Result<Vec<Mark>> {
+    let compressed = format!("{}.cmrk2", name);
+    let adaptive = format!("{}.mrk2", name);
+    let (marks, adaptive) = if dir.exists(&compressed) {
+        (dir.read_decompressed(&compressed)?, true)
+    } else if dir.exists(&adaptive) {
+        (dir.read(&adaptive)?, true)
+    } else {
+        (dir.read(&format!("{}.mrk", name))?, false)
+    };
+    let mut reader = Cursor::new(marks);
+    let mut marks = Vec::new();
+    while (reader.position() as usize) < reader.get_ref().len() {
+        marks.push(Mark {
+            compressed: reader.read_u64::<LittleEndian>()?,
+            decompressed: reader.read_u64::<LittleEndian>()?,
+            rows: match adaptive {
+                true => reader.read_u64::<LittleEndian>()?,
+                false => DEFAULT_GRANULARITY,
+            },
+        });
+    }
+    Ok(marks)
+}
+
+/// The row counts of the granules of a Wide part, from the marks of any of
+/// its streams, which all share them.
+fn read_wide_granules(dir: &PartDir, rows: u64) -> io::Result<Vec<u64>> {
+    let names: Vec<String> = dir
+        .file_names()?
+        .into_iter()
+        .filter_map(|name| {
+            [".cmrk2", ".mrk2", ".mrk"]
+                .iter()
+                .find_map(|extension| name.strip_suffix(extension))
+                .map(str::to_string)
+        })
+        // Dictionaries are written whenever they fill up, not per granule.
+        .filter(|name| !name.ends_with(".dict"))
+        .collect();
+    let name = names
+        .first()
+        .ok_or_else(|| invalid("Part has no marks".to_string()))?;
+
+    let mut remaining = rows;
+    Ok(read_marks(dir, name)?
+        .into_iter()
+        .map(|mark| {
+            let rows = mark.rows.min(remaining);
+            remaining -= rows;
+            rows
+        })
+        .collect())
+}
+
+/// A stream of a Wide part, and its marks once a granule is sought in it.
+struct WideStream {
+    file: CompressedFile,
+    marks: Option<Vec<Mark>>,
+}
+
+impl Read for WideStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+/// The streams of a part, by the name of their file without `.bin`.
+enum PartStreams<'a> {
+    /// Each stream in a file of its own, opened when first read. With a
+    /// granule set, each stream starts at the granule's mark when first
+    /// read for it.
+    Wide {
+        dir: &'a PartDir,
+        files: HashMap<String, WideStream>,
+        granule: Option<usize>,
+        sought: HashSet<String>,
+    },
+    /// Every stream of a column in one run of `data.bin`, a granule at a
+    /// time.
+    Compact(&'a mut CompressedFile),
+}
+
+impl PartStreams<'_> {
+    fn file_name(name: &str) -> String {
+        format!("{}.bin", name)
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        match self {
+            PartStreams::Wide { dir, files, .. } => {
+                files.contains_key(name) || dir.exists(&PartStreams::file_name(name))
+            }
+            PartStreams::Compact(_) => true,
+        }
+    }
+
+    fn stream(&mut self, name: &str) -> io::Result<&mut dyn Read> {
+        match self {
+            PartStreams::Wide {
+                dir,
+                files,
+                granule,
+                sought,
+            } => {
+                if !files.contains_key(name) {
+                    let file_name = PartStreams::file_name(name);
+                    let file = dir.read_compressed(&file_name).map_err(|err| {
+                        let path = dir.path().join(&file_name);
+                        io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+                    })?;
+                    let stream = WideStream { file, marks: None };
+                    files.insert(name.to_string(), stream);
+                }
+                let stream = files.get_mut(name).unwrap();
+                if let Some(granule) = *granule {
+                    if sought.insert(name.to_string()) {
+                        if stream.marks.is_none() {
+                            stream.marks = Some(read_marks(dir, name)?);
+                        }
+                        let mark = stream.marks.as_deref().unwrap_or_default().get(granule);
+                        let mark = mark.ok_or_else(|| {
+                            invalid(format!("{} has no mark for granule {}", name, granule))
+                        })?;
+                        stream.file.seek(mark.compressed, mark.decompressed)?;
+                    }
+                }
+                Ok(stream)
+            }
+            PartStreams::Compact(file) => Ok(&mut **file),
+        }
+    }
+
+    /// Starts reading every stream at the mark of `granule` when next read,
+    /// or where the last read stopped for `None`.
+    fn seek_granule(&mut self, granule: Option<usize>) {
+        if let PartStreams::Wide {
+            granule: current,
+            sought,
+            ..
+        } = self
+        {
+            *current = granule;
+            sought.clear();
+        }
+    }
+
+    /// The stream of a tuple element. Named elements have the dot before
+    /// them escaped in newer parts.
+    fn element(&self, stream: &str, element: &str) -> String {
+        let escaped = format!("{}%2E{}", stream, escape_file_name(element));
+        if self.exists(&escaped) {
+            escaped
+        } else {
+            format!("{}.{}", stream, escape_file_name(element))
+        }
+    }
+}
+
+/// Reads the stream prefixes of a column, which only LowCardinality keys
+/// have among the supported types.
+fn read_part_prefix(
+    streams: &mut PartStreams,
+    stream: &str,
+    column_type: &ColumnType,
+) -> io::Result<()> {
+    match column_type {
+        ColumnType::Nullable(inner) | ColumnType::Array(inner) => {
+            read_part_prefix(streams, stream, inner)
+        }
+        ColumnType::LowCardinality(_) => {
+            low_cardinality::read_prefix(&mut streams.stream(&format!("{}.dict", stream))?)
+        }
+        ColumnType::Map(key, value) => {
+            read_part_prefix(streams, &streams.element(stream, "keys"), key)?;
+            read_part_prefix(streams, &streams.element(stream, "values"), value)
+        }
+        ColumnType::Tuple(elements) => {
+            for (name, element_type) in elements {
+                read_part_prefix(streams, &streams.element(stream, name), element_type)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the sizes of `rows` arrays, which parts store as lengths rather
+/// than the running offsets of a Native block.
+fn read_array_sizes(reader: &mut dyn Read, rows: u64) -> io::Result<Vec<u64>> {
+    let mut offsets = Vec::with_capacity(rows as usize);
+    let mut offset = 0;
+    for _ in 0..rows {
+        offset += reader.read_u64::<LittleEndian>()?;
+        offsets.push(offset);
+    }
+    Ok(offsets)
+}
+
+/// Reads the positions of the non-default rows of a sparse column, written
+/// a granule at a time, each ending with `END_OF_GRANULE_FLAG`.
+fn read_granule_sparse_offsets(mut reader: &mut dyn Read, rows: u64) -> io::Result<Vec<u64>> {
+    let mut offsets = Vec::new();
+    let mut position = 0u64;
+    while position < rows {
+        let group_size = read_var_u64(&mut reader)?;
+        if group_size & END_OF_GRANULE_FLAG != 0 {
+            position += group_size & !END_OF_GRANULE_FLAG;
+            continue;
+        }
+        position += group_size;
+        offsets.push(position);
+        position += 1;
+    }
+    if position != rows {
+        return Err(invalid(format!(
+            "Sparse offsets cover {} rows, expected {}",
+            position, rows
+        )));
+    }
+    Ok(offsets)
+}
+
+/// Reads `rows` values of a column from its streams, named after `stream`.
+/// `nested_table` is set for the columns of a flattened `Nested`, which
+/// share the sizes of their outer arrays.
+fn read_part_data(
+    streams: &mut PartStreams,
+    stream: &str,
+    nested_table: Option<&str>,
+    column_type: &ColumnType,
+    level: usize,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    if rows == 0 {
+        return Ok(Vec::new());
+    }
+    let sizes_stream = || match nested_table {
+        Some(table) if level == 0 => format!("{}.size0", table),
+        _ => format!("{}.size{}", stream, level),
+    };
+    match column_type {
+        ColumnType::Nullable(inner) => {
+            let mut null_map = vec![0u8; rows as usize];
+            streams
+                .stream(&format!("{}.null", stream))?
+                .read_exact(&mut null_map)?;
+            let values = read_part_data(streams, stream, nested_table, inner, level, rows)?;
+            Ok(null_map
+                .into_iter()
+                .zip(values)
+                .map(|(is_null, value)| {
+                    if is_null != 0 {
+                        ColumnData::Null
+                    } else {
+                        value
+                    }
+                })
+                .collect())
+        }
+        ColumnType::LowCardinality(inner) => {
+            let dictionary = format!("{}.dict", stream);
+            match streams {
+                PartStreams::Wide { files, .. } => {
+                    let mut keys = files.remove(&dictionary).ok_or_else(|| {
+                        invalid(format!("Missing LowCardinality dictionary {}", dictionary))
+                    })?;
+                    let data = low_cardinality::read_shared_low_cardinality_data(
+                        &mut streams.stream(stream)?,
+                        Some(&mut keys),
+                        inner,
+                        rows,
+                    );
+                    if let PartStreams::Wide { files, .. } = streams {
+                        files.insert(dictionary, keys);
+                    }
+                    data
+                }
+                PartStreams::Compact(file) => {
+                    low_cardinality::read_low_cardinality_data(&mut **file, inner, rows)
+                }
+            }
+        }
+        ColumnType::Array(inner) => {
+            let offsets = read_array_sizes(streams.stream(&sizes_stream())?, rows)?;
+            let total = offsets.last().copied().unwrap_or(0);
+            let values = read_part_data(streams, stream, nested_table, inner, level + 1, total)?;
+            Ok(split_by_offsets(&offsets, values))
+        }
+        ColumnType::Map(key, value) => {
+            let offsets = read_array_sizes(streams.stream(&sizes_stream())?, rows)?;
+            let total = offsets.last().copied().unwrap_or(0);
+            let keys_stream = streams.element(stream, "keys");
+            let keys = read_part_data(streams, &keys_stream, None, key, level + 1, total)?;
+            let values_stream = streams.element(stream, "values");
+            let values = read_part_data(streams, &values_stream, None, value, level + 1, total)?;
+            let entries = keys
+                .into_iter()
+                .zip(values)
+                .map(|(k, v)| ColumnData::Tuple(vec![k, v]))
+                .collect();
+            Ok(split_by_offsets(&offsets, entries))
+        }
+        ColumnType::Tuple(elements) => {
+            let mut rows_data: Vec<Vec<ColumnData>> = (0..rows)
+                .map(|_| Vec::with_capacity(elements.len()))
+                .collect();
+            for (name, element_type) in elements {
+                let element_stream = streams.element(stream, name);
+                let values =
+                    read_part_data(streams, &element_stream, None, element_type, level, rows)?;
+                for (row, value) in rows_data.iter_mut().zip(values) {
+                    row.push(value);
+                }
+            }
+            Ok(rows_data.into_iter().map(ColumnData::Tuple).collect())
+        }
+        ColumnType::Geo(kind) => {
+            let storage_type = geo_stream_type(geo::storage_type(*kind));
+            read_part_data(streams, stream, nested_table, &storage_type, level, rows)
+        }
+        _ => read_column_data(&mut streams.stream(stream)?, column_type, rows),
+    }
+}
+
+/// Reads a sparse column of a Wide part: the positions of its non-default
+/// rows, then their values.
+fn read_sparse_part_data(
+    streams: &mut PartStreams,
+    stream: &str,
+    column_type: &ColumnType,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    let offsets_stream = format!("{}.sparse.idx", stream);
+    let offsets = read_granule_sparse_offsets(streams.stream(&offsets_stream)?, rows)?;
+    let values = read_part_data(streams, stream, None, column_type, 0, offsets.len() as u64)?;
+
+    let mut data = vec![default_value(column_type); rows as usize];
+    for (offset, value) in offsets.into_iter().zip(values) {
+        data[offset as usize] = value;
+    }
+    Ok(data)
+}
+
+/// The names a column's streams are stored under, and the table a
+/// flattened `Nested` column belongs to.
+pub(crate) fn stream_names(name: &str) -> (String, Option<String>) {
+    let nested_table = name
+        .split_once('.')
+        .filter(|(table, rest)| !table.is_empty() && !rest.is_empty())
+        .map(|(table, _)| escape_file_name(table));
+    (escape_file_name(name), nested_table)
+}
+
+/// A mark of a Compact part.
+struct CompactGranule {
+    /// Where each column starts in `data.bin`, as the offset of a compressed
+    /// block and an offset in its data.
+    positions: Vec<(u64, u64)>,
+    rows: u64,
+}
+
+/// Reads the marks of a Compact part: for every granule, the position of
+/// each column in `data.bin` followed by the granule's row count.
+fn read_compact_marks(dir: &PartDir, columns: usize) -> io::Result<Vec<CompactGranule>> {
+    let marks = if dir.exists("data.cmrk3") {
+        dir.read_decompressed("data.cmrk3")?
+    } else {
+        dir.read("data.mrk3")?
+    };
+    let mut reader = Cursor::new(marks);
+    let mut granules = Vec::new();
+    while (reader.position() as usize) < reader.get_ref().len() {
+        let positions = (0..columns)
+            .map(|_| {
+                Ok((
+                    reader.read_u64::<LittleEndian>()?,
+                    reader.read_u64::<LittleEndian>()?,
+                ))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let rows = reader.read_u64::<LittleEndian>()?;
+        granules.push(CompactGranule { positions, rows });
+    }
+    Ok(granules)
+}
+
+/// Whether a column can be read from any granule of a Wide part. The index
+/// chunks of a LowCardinality column can refer to a dictionary written
+/// before the granule.
+fn seekable(column_type: &ColumnType) -> bool {
+    match column_type {
+        ColumnType::LowCardinality(_) => false,
+        ColumnType::Nullable(inner) | ColumnType::Array(inner) => seekable(inner),
+        ColumnType::Map(key, value) => seekable(key) && seekable(value),
+        ColumnType::Tuple(elements) => elements.iter().all(|(_, t)| seekable(t)),
+        _ => true,
+    }
+}
+
+fn read_wide_column(
+    streams: &mut PartStreams,
+    name: &str,
+    column_type: &ColumnType,
+    sparse: bool,
+    rows: u64,
+) -> io::Result<Vec<ColumnData>> {
+    let (stream, nested_table) = stream_names(name);
+    if sparse {
+        read_sparse_part_data(streams, &stream, column_type, rows)
+    } else {
+        read_part_data(
+            streams,
+            &stream,
+            nested_table.as_deref(),
+            column_type,
+            0,
+            rows,
+        )
+    }
+}
+
+/// Reads a Wide part, a column at a time. With granules skipped, the columns
+/// that allow it are read from the marks of the selected granules, the
+/// others whole and trimmed.
+pub(crate) fn read_wide_part(
+    dir: &PartDir,
+    schema: &[(String, ColumnType)],
+    rows: u64,
+    filter: &PartFilter,
+) -> io::Result<Vec<Vec<ColumnData>>> {
+    let granule_rows = match filter.is_empty() {
+        true => Vec::new(),
+        false => read_wide_granules(dir, rows)?,
+    };
+    let selected = filter.select_granules(dir, schema, granule_rows.len())?;
+    let skipping = selected.contains(&false);
+
+    let sparse = read_sparse_columns(dir)?;
+    let mut streams = PartStreams::Wide {
+        dir,
+        files: HashMap::new(),
+        granule: None,
+        sought: HashSet::new(),
+    };
+    schema
+        .iter()
+        .map(|(name, column_type)| {
+            let sparse = sparse.contains(name);
+            streams.seek_granule(None);
+            read_part_prefix(&mut streams, &stream_names(name).0, column_type)?;
+            if !skipping {
+                return read_wide_column(&mut streams, name, column_type, sparse, rows);
+            }
+            if !seekable(column_type) {
+                let data = read_wide_column(&mut streams, name, column_type, sparse, rows)?;
+                return Ok(pruning::keep_granules(data, &granule_rows, &selected));
+            }
+            let mut data = Vec::new();
+            for (granule, (&rows, _)) in granule_rows
+                .iter()
+                .zip(&selected)
+                .enumerate()
+                .filter(|(_, (&rows, &keep))| keep && rows > 0)
+            {
+                streams.seek_granule(Some(granule));
+                data.append(&mut read_wide_column(
+                    &mut streams,
+                    name,
+                    column_type,
+                    sparse,
+                    rows,
+                )?);
+            }
+            Ok(data)
+        })
+        .collect()
+}
+
+/// Reads a Compact part granule by granule. Every column of a granule is
+/// written with its own stream prefix, starting at the column's mark.
+fn read_compact_part(
+    dir: &PartDir,
+    schema: &[(String, ColumnType)],
+    filter: &PartFilter,
+) -> io::Result<Vec<Vec<ColumnData>>> {
+    let mut data = dir.read_compressed("data.bin")?;
+    let marks = read_compact_marks(dir, schema.len())?;
+    let selected = filter.select_granules(dir, schema, marks.len())?;
+    let mut columns: Vec<Vec<ColumnData>> = vec![Vec::new(); schema.len()];
+
+    for (CompactGranule { positions, rows }, _) in
+        marks.into_iter().zip(selected).filter(|(_, keep)| *keep)
+    {
+        for (((name, column_type), column), (compressed, decompressed)) in
+            schema.iter().zip(&mut columns).zip(positions)
+        {
+            if rows == 0 {
+                continue;
+            }
+            data.seek(compressed, decompressed)?;
+            let mut streams = PartStreams::Compact(&mut data);
+            let (stream, nested_table) = stream_names(name);
+            read_part_prefix(&mut streams, &stream, column_type)?;
+            column.append(&mut read_part_data(
+                &mut streams,
+                &stream,
+                nested_table.as_deref(),
+                column_type,
+                0,
+                rows,
+            )?);
+        }
+    }
+    Ok(columns)
+}
+
+/// The columns of a part, from `columns.txt` or from `metadata` for a part
+/// without one.
+pub(crate) fn read_part_schema(
+    dir: &PartDir,
+    metadata: Option<&[(String, ColumnType)]>,
+) -> io::Result<TableSchema> {
+    let schema = match (read_part_columns(dir), metadata) {
+        (Err(err), Some(columns)) if err.kind() == io::ErrorKind::NotFound => columns.to_vec(),
+        (Err(err), None) if err.kind() == io::ErrorKind::NotFound && dir.exists("count.txt") => {
+            return Err(io::Error::new(
+                err.kind(),
+                format!(
+                    "{} has no columns.txt, pass the table's metadata file as metadata",
+                    dir.path().display()
+                ),
+            ))
+        }
+        (schema, _) => schema?,
+    };
+    if let Some((name, _)) = schema.iter().find(|(_, t)| !supported(t)) {
+        return Err(invalid(format!(
+            "Cannot read column {} from a part, its type is not supported",
+            name
+        )));
+    }
+    Ok(schema)
+}
+
+/// The columns a scan of parts of `schema` returns, resolved as
+/// `resolve_part_columns` does without reading their values: the types
+/// parts can store do not depend on them.
+pub(crate) fn schema_columns(
+    schema: &[(String, ColumnType)],
+    options: &NativeOptions,
+) -> io::Result<Vec<Column>> {
+    let columns = schema
+        .iter()
+        .map(|(name, type_)| Column {
+            name: name.clone(),
+            type_: type_.clone(),
+            data: Vec::new(),
+        })
+        .collect();
+    resolve_part_columns(columns, options)
+}
+
+/// The rows a scan of a part returns, from `count.txt`: all of them, or at
+/// most all of them with a filter, and none when `filter` skips the part.
+pub(crate) fn part_cardinality(
+    dir: &PartDir,
+    schema: &[(String, ColumnType)],
+    filter: &PartFilter,
+) -> io::Result<u64> {
+    if filter.skips_part(dir, schema)? {
+        return Ok(0);
+    }
+    read_part_rows(dir)
+}
+
+/// Reads the columns of a Wide or Compact MergeTree part, skipping what
+/// `filter` rules out, as stored: they are resolved once every part of a
+/// scan is read. `metadata` lists the columns of a part without
+/// `columns.txt`.
+pub(crate) fn read_part_columns_data(
+    dir: &PartDir,
+    filter: &PartFilter,
+    metadata: Option<&[(String, ColumnType)]>,
+) -> io::Result<Vec<Column>> {
+    let schema = read_part_schema(dir, metadata)?;
+    filter.check(&schema)?;
+    let rows = read_part_rows(dir)?;
+
+    let data = if filter.skips_part(dir, &schema)? {
+        vec![Vec::new(); schema.len()]
+    } else if dir.exists("data.bin") {
+        read_compact_part(dir, &schema, filter)?
+    } else {
+        read_wide_part(dir, &schema, rows, filter)?
+    };
+
+    let mut columns = Vec::with_capacity(schema.len());
+    let selected_rows = data.first().map_or(0, Vec::len);
+    for ((name, type_), data) in schema.into_iter().zip(data) {
+        let expected = match filter.is_empty() {
+            true => rows as usize,
+            false => selected_rows,
+        };
+        if data.len() != expected {
+            return Err(invalid(format!(
+                "Column {} has {} rows, expected {}",
+                name,
+                data.len(),
+                expected
+            )));
+        }
+        columns.push(Column { name, type_, data });
+    }
+    Ok(columns)
+}
+
+/// Turns the columns read from parts into their DuckDB shape.
+pub(crate) fn resolve_part_columns(
+    mut columns: Vec<Column>,
+    options: &NativeOptions,
+) -> io::Result<Vec<Column>> {
+    for column in &mut columns {
+        resolve_column(&mut column.type_, column.data.iter_mut().collect(), options)?;
+    }
+    if options.group_nested {
+        columns = nested::group_nested_columns(columns)?;
+    }
+    Ok(columns)
+}
+
+fn read_part(
+    path: &str,
+    options: &NativeOptions,
+    filter: &PartFilter,
+    metadata: Option<&[(String, ColumnType)]>,
+) -> io::Result<Vec<Column>> {
+    let columns = read_part_columns_data(&PartDir::new(path), filter, metadata)?;
+    resolve_part_columns(columns, options)
+}
+
+#[derive(Debug)]
+struct PartBindData {
+    path: String,
+    options: NativeOptions,
+    filter: PartFilter,
+    metadata: Option<TableSchema>,
+}
+
+struct ClickHousePartVTab;
+
+impl VTab for ClickHousePartVTab {
+    type InitData = ClickHouseInitData;
+    type BindData = PartBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let path = bind.get_parameter(0).to_string();
+        let options = NativeOptions::from_bind(bind)?;
+        let filter = PartFilter::from_bind(bind)?;
+        let metadata = metadata_schema(bind)?;
+
+        // The values are only read in init: the schema and row count are
+        // in the part's metadata files.
+        let dir = PartDir::new(&path);
+        let schema = read_part_schema(&dir, metadata.as_deref())?;
+        filter.check(&schema)?;
+        let rows = part_cardinality(&dir, &schema, &filter)?;
+        bind_schema(
+            bind,
+            &schema_columns(&schema, &options)?,
+            Some((rows, filter.is_empty())),
+        );
+
+        Ok(PartBindData {
+            path,
+            options,
+            filter,
+            metadata,
+        })
+    }
+
+    fn init(info: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        let bind_data = info.get_bind_data::<PartBindData>();
+        let bind_data = unsafe { &*bind_data };
+
+        let columns = read_part(
+            &bind_data.path,
+            &bind_data.options,
+            &bind_data.filter,
+            bind_data.metadata.as_deref(),
+        )?;
+        Ok(ClickHouseInitData::new(columns))
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        func.get_init_data().scan(output)?;
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        Some(part_named_parameters())
+    }
+}
+
+/// The options of the part readers: those of `clickhouse_native`, the
+/// table's metadata file and the ranges that prune parts and granules.
+pub(crate) fn part_named_parameters() -> Vec<(String, LogicalTypeHandle)> {
+    let mut parameters = NativeOptions::named_parameters();
+    parameters.push((
+        "metadata".to_string(),
+        LogicalTypeHandle::from(LogicalTypeId::Varchar),
+    ));
+    parameters.push((
+        "primary_key".to_string(),
+        LogicalTypeHandle::from(LogicalTypeId::Varchar),
+    ));
+    // STRUCTs of any shape, so declared as ANY.
+    for name in ["range_min", "range_max"] {
+        parameters.push((
+            name.to_string(),
+            LogicalTypeHandle::from(LogicalTypeId::Any),
+        ));
+    }
+    parameters
+}
+
+pub fn register_part_function(con: &Connection) -> Result<(), Box<dyn Error>> {
+    con.register_table_function::<ClickHousePartVTab>("clickhouse_part")?;
+    Ok(())
+}
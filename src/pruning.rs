@@ -0,0 +1,359 @@
+use super::{
+    coerce,
+    part::{escape_file_name, PartDir},
+    read_row_value, ColumnData, ColumnType,
+};
+use duckdb::{
+    core::LogicalTypeId,
+    vtab::{BindInfo, Value},
+};
+use std::{
+    cmp::Ordering,
+    error::Error,
+    io::{self, BufRead, Cursor},
+};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A value of a key or partition column, comparable with a bound.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Key {
+    Integer(i128),
+    Float(f64),
+    Text(String),
+}
+
+/// Days from 1970-01-01 to a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses `YYYY-MM-DD` into days since the epoch.
+fn parse_date(text: &str) -> Option<i64> {
+    let mut parts = text.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    ((1..=12).contains(&month) && (1..=31).contains(&day))
+        .then(|| days_from_civil(year, month, day))
+}
+
+/// Parses `YYYY-MM-DD[ HH:MM:SS[.ffffff]]`, optionally with a `T`, into
+/// microseconds since the epoch.
+fn parse_timestamp(text: &str) -> Option<i64> {
+    let (date, time) = text.split_once([' ', 'T']).unwrap_or((text, "00:00:00"));
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut parts = time.splitn(3, ':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next().unwrap_or("0").parse().ok()?;
+    let seconds: i64 = parts.next().unwrap_or("0").parse().ok()?;
+    let micros: i64 = match fraction {
+        "" => 0,
+        fraction => format!("{:0<6}", fraction).get(..6)?.parse().ok()?,
+    };
+    let seconds = parse_date(date)? * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    Some(seconds * 1_000_000 + micros)
+}
+
+/// The type a column's values are compared as, past wrappers that do not
+/// change the value.
+fn key_type(column_type: &ColumnType) -> &ColumnType {
+    match column_type {
+        ColumnType::Nullable(inner) | ColumnType::LowCardinality(inner) => key_type(inner),
+        other => other,
+    }
+}
+
+/// Parses a bound given for a column of `column_type`.
+fn parse_bound(text: &str, column_type: &ColumnType) -> Option<Key> {
+    let text = text.trim();
+    Some(match key_type(column_type) {
+        ColumnType::String | ColumnType::FixedString(_) => Key::Text(text.to_string()),
+        ColumnType::Float32 | ColumnType::Float64 => Key::Float(text.parse().ok()?),
+        ColumnType::Date | ColumnType::Date32 => Key::Integer(parse_date(text)? as i128),
+        ColumnType::DateTime(_) => Key::Integer(parse_timestamp(text)? as i128),
+        ColumnType::DateTime64(precision, tz) => {
+            // Read the way `scale_datetime64` keeps them, in nanoseconds
+            // above microsecond precision without a time zone.
+            let micros = parse_timestamp(text)? as i128;
+            Key::Integer(if *precision > 6 && tz.is_none() {
+                micros * 1000
+            } else {
+                micros
+            })
+        }
+        ColumnType::UInt8
+        | ColumnType::UInt16
+        | ColumnType::UInt32
+        | ColumnType::UInt64
+        | ColumnType::Int
+        | ColumnType::Int8
+        | ColumnType::Int16
+        | ColumnType::Int32
+        | ColumnType::Int64 => Key::Integer(text.parse().ok()?),
+        _ => return None,
+    })
+}
+
+fn key(value: &ColumnData) -> Option<Key> {
+    Some(match value {
+        ColumnData::String(s) => Key::Text(s.clone()),
+        ColumnData::Float32(v) => Key::Float(*v as f64),
+        ColumnData::Float64(v) => Key::Float(*v),
+        ColumnData::Date(days) => Key::Integer(*days as i128),
+        ColumnData::Timestamp(v) => Key::Integer(*v as i128),
+        value => Key::Integer(coerce::integer_value(value)?),
+    })
+}
+
+fn compare(a: &Key, b: &Key) -> Option<Ordering> {
+    a.partial_cmp(b)
+}
+
+/// Inclusive bounds on a column, from `range_min` and `range_max`.
+#[derive(Debug, Clone)]
+struct Range {
+    column: String,
+    min: Option<String>,
+    max: Option<String>,
+}
+
+/// Parsed bounds of a range for one part's column type.
+struct Bounds {
+    min: Option<Key>,
+    max: Option<Key>,
+}
+
+impl Bounds {
+    /// Whether values between `low` and `high`, inclusive, may fall in the
+    /// bounds. An unknown side, such as a NULL key, never excludes.
+    fn overlaps(&self, low: Option<&Key>, high: Option<&Key>) -> bool {
+        let above_max = match (&self.max, low) {
+            (Some(max), Some(low)) => compare(low, max) == Some(Ordering::Greater),
+            _ => false,
+        };
+        let below_min = match (&self.min, high) {
+            (Some(min), Some(high)) => compare(high, min) == Some(Ordering::Less),
+            _ => false,
+        };
+        !above_max && !below_min
+    }
+}
+
+fn struct_parameter(name: &str, value: &Value) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    match value.logical_type_id() {
+        LogicalTypeId::Struct => coerce::struct_fields(&value.to_string()),
+        _ => None,
+    }
+    .ok_or_else(|| format!("{} must be a STRUCT such as {{'d': '2024-01-01'}}", name).into())
+}
+
+/// The `primary_key`, `range_min` and `range_max` parameters of
+/// `clickhouse_part`, which skip the parts and granules whose key and
+/// partition values cannot fall in the ranges.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PartFilter {
+    primary_key: Vec<String>,
+    ranges: Vec<Range>,
+}
+
+impl PartFilter {
+    pub(crate) fn from_bind(bind: &BindInfo) -> Result<Self, Box<dyn Error>> {
+        let primary_key = bind
+            .get_named_parameter("primary_key")
+            .map(|value| {
+                value
+                    .to_string()
+                    .split(',')
+                    .map(|name| name.trim().trim_matches('`').to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut ranges: Vec<Range> = Vec::new();
+        for (parameter, is_min) in [("range_min", true), ("range_max", false)] {
+            let Some(value) = bind.get_named_parameter(parameter) else {
+                continue;
+            };
+            for (column, bound) in struct_parameter(parameter, &value)? {
+                let index = match ranges.iter().position(|range| range.column == column) {
+                    Some(index) => index,
+                    None => {
+                        ranges.push(Range {
+                            column,
+                            min: None,
+                            max: None,
+                        });
+                        ranges.len() - 1
+                    }
+                };
+                if is_min {
+                    ranges[index].min = Some(bound);
+                } else {
+                    ranges[index].max = Some(bound);
+                }
+            }
+        }
+        Ok(PartFilter {
+            primary_key,
+            ranges,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The bounds of `column` for a part, if a range is given for it.
+    fn bounds(&self, column: &str, schema: &[(String, ColumnType)]) -> io::Result<Option<Bounds>> {
+        let Some(range) = self.ranges.iter().find(|range| range.column == column) else {
+            return Ok(None);
+        };
+        let (_, column_type) = schema
+            .iter()
+            .find(|(name, _)| name == column)
+            .ok_or_else(|| invalid(format!("Column {} is not in the part", column)))?;
+        let parse = |bound: &Option<String>| -> io::Result<Option<Key>> {
+            bound
+                .as_deref()
+                .map(|text| {
+                    parse_bound(text, column_type).ok_or_else(|| {
+                        invalid(format!("Invalid bound '{}' for column {}", text, column))
+                    })
+                })
+                .transpose()
+        };
+        Ok(Some(Bounds {
+            min: parse(&range.min)?,
+            max: parse(&range.max)?,
+        }))
+    }
+
+    /// Checks that every range names a column of the part with a type that
+    /// can be compared.
+    pub(crate) fn check(&self, schema: &[(String, ColumnType)]) -> io::Result<()> {
+        for range in &self.ranges {
+            self.bounds(&range.column, schema)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the part's `minmax_<column>.idx` files, written for the
+    /// columns of the partition key, rule it out.
+    pub(crate) fn skips_part(
+        &self,
+        dir: &PartDir,
+        schema: &[(String, ColumnType)],
+    ) -> io::Result<bool> {
+        for range in &self.ranges {
+            let name = format!("minmax_{}.idx", escape_file_name(&range.column));
+            if !dir.exists(&name) {
+                continue;
+            }
+            let bounds = self.bounds(&range.column, schema)?.unwrap();
+            let (_, column_type) = schema
+                .iter()
+                .find(|(name, _)| *name == range.column)
+                .unwrap();
+            let mut reader = Cursor::new(dir.read(&name)?);
+            let min = read_row_value(&mut reader, column_type)?;
+            let max = read_row_value(&mut reader, column_type)?;
+            if !bounds.overlaps(key(&min).as_ref(), key(&max).as_ref()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Which of the granules may hold rows in the range of the first
+    /// `primary_key` column, from the key values `primary.idx` or
+    /// `primary.cidx` holds for the first row of every mark. The rows of a
+    /// granule lie between its mark's key and the next one.
+    pub(crate) fn select_granules(
+        &self,
+        dir: &PartDir,
+        schema: &[(String, ColumnType)],
+        granules: usize,
+    ) -> io::Result<Vec<bool>> {
+        let all = vec![true; granules];
+        let Some(first_key) = self.primary_key.first() else {
+            return Ok(all);
+        };
+        let Some(bounds) = self.bounds(first_key, schema)? else {
+            return Ok(all);
+        };
+
+        let key_types = self
+            .primary_key
+            .iter()
+            .map(|column| {
+                schema
+                    .iter()
+                    .find(|(name, _)| name == column)
+                    .map(|(_, column_type)| column_type)
+                    .ok_or_else(|| invalid(format!("Key column {} is not in the part", column)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let index = if dir.exists("primary.cidx") {
+            dir.read_decompressed("primary.cidx")?
+        } else if dir.exists("primary.idx") {
+            dir.read("primary.idx")?
+        } else {
+            return Ok(all);
+        };
+        let mut reader = Cursor::new(index);
+        let mut first_values = Vec::new();
+        while !reader.fill_buf()?.is_empty() {
+            let mut values = key_types
+                .iter()
+                .map(|column_type| read_row_value(&mut reader, column_type))
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(|_| invalid("primary.idx does not match primary_key".to_string()))?;
+            first_values.push(key(&values.swap_remove(0)));
+        }
+        // With a final mark the index also holds the key of the last row.
+        if first_values.len() != granules && first_values.len() + 1 != granules {
+            return Err(invalid(format!(
+                "primary.idx has {} marks, the part has {}, check primary_key",
+                first_values.len(),
+                granules
+            )));
+        }
+
+        Ok((0..granules)
+            .map(|granule| {
+                let low = first_values.get(granule).cloned().flatten();
+                let high = first_values.get(granule + 1).cloned().flatten();
+                bounds.overlaps(low.as_ref(), high.as_ref())
+            })
+            .collect())
+    }
+}
+
+/// Keeps the rows of the selected granules of a column read whole.
+pub(crate) fn keep_granules(
+    data: Vec<ColumnData>,
+    granule_rows: &[u64],
+    selected: &[bool],
+) -> Vec<ColumnData> {
+    let mut data = data.into_iter();
+    let mut kept = Vec::new();
+    for (&rows, &keep) in granule_rows.iter().zip(selected) {
+        let granule = data.by_ref().take(rows as usize);
+        if keep {
+            kept.extend(granule);
+        } else {
+            granule.for_each(drop);
+        }
+    }
+    kept
+}
@@ -0,0 +1,30 @@
+use libduckdb_sys as ffi;
+use std::ffi::{c_char, c_void, CStr};
+
+/// File name suffixes read with `clickhouse_native` when used as a table name.
+const NATIVE_SUFFIXES: [&str; 3] = [".native", ".clickhouse", ".native.zst"];
+
+unsafe extern "C" fn native_replacement_scan(
+    info: ffi::duckdb_replacement_scan_info,
+    table_name: *const c_char,
+    _data: *mut c_void,
+) {
+    let name = CStr::from_ptr(table_name).to_string_lossy().to_lowercase();
+    if !NATIVE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        return;
+    }
+    ffi::duckdb_replacement_scan_set_function_name(info, c"clickhouse_native".as_ptr());
+    let mut path = ffi::duckdb_create_varchar(table_name);
+    ffi::duckdb_replacement_scan_add_parameter(info, path);
+    ffi::duckdb_destroy_value(&mut path);
+}
+
+/// Lets `SELECT * FROM 'events.native'` read the file with `clickhouse_native`,
+/// as DuckDB does for `.parquet` and `.csv` paths.
+///
+/// # Safety
+///
+/// `db` must be the database the extension is being loaded into.
+pub(crate) unsafe fn register_replacement_scan(db: ffi::duckdb_database) {
+    ffi::duckdb_add_replacement_scan(db, Some(native_replacement_scan), std::ptr::null_mut(), None);
+}
@@ -0,0 +1,328 @@
+use super::{
+    binary_types, bind_columns, bind_schema, check_block_types, looks_like_type_name, metadata,
+    open_native_file, parse_column_type, parse_tuple_element, read_row_value, read_string,
+    read_var_u64, resolve_columns, split_type_params, without_values, ClickHouseInitData, Column,
+    ColumnType, NativeOptions, ReadBlock, StreamedInput,
+};
+use duckdb::{
+    core::{DataChunkHandle, LogicalTypeHandle, LogicalTypeId},
+    vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
+    Connection, Result,
+};
+use std::{
+    error::Error,
+    io::{self, BufRead},
+};
+
+/// Rows decoded at a time from a RowBinary stream, ClickHouse's default
+/// `max_block_size`.
+const STREAM_BATCH_ROWS: usize = 65_536;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The RowBinary variants, which differ only in the header before the rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RowBinaryFormat {
+    RowBinary,
+    WithNames,
+    WithNamesAndTypes,
+}
+
+impl RowBinaryFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "rowbinary" => Some(RowBinaryFormat::RowBinary),
+            "rowbinarywithnames" => Some(RowBinaryFormat::WithNames),
+            "rowbinarywithnamesandtypes" => Some(RowBinaryFormat::WithNamesAndTypes),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RowBinaryOptions {
+    format: RowBinaryFormat,
+    /// The `columns` structure, such as `id UInt32, s String`.
+    columns: Option<Vec<(String, ColumnType)>>,
+    native: NativeOptions,
+}
+
+impl RowBinaryOptions {
+    fn from_bind(bind: &BindInfo) -> Result<Self, Box<dyn Error>> {
+        let columns = bind.get_named_parameter("columns").map(|v| {
+            split_type_params(&v.to_string())
+                .iter()
+                .enumerate()
+                .map(|(idx, element)| parse_tuple_element(element, idx + 1))
+                .collect::<Vec<_>>()
+        });
+        let format = match bind.get_named_parameter("format").map(|v| v.to_string()) {
+            // Without an explicit structure the header is the only place the
+            // schema can come from.
+            None if columns.is_some() => RowBinaryFormat::RowBinary,
+            None => RowBinaryFormat::WithNamesAndTypes,
+            Some(name) => RowBinaryFormat::parse(&name)
+                .ok_or_else(|| format!("Unknown RowBinary format '{}'", name))?,
+        };
+        if columns.as_ref().is_some_and(Vec::is_empty) {
+            return Err("columns must list at least one column".into());
+        }
+        if format != RowBinaryFormat::WithNamesAndTypes && columns.is_none() {
+            return Err("RowBinary and RowBinaryWithNames need the columns parameter".into());
+        }
+        Ok(RowBinaryOptions {
+            format,
+            columns,
+            native: NativeOptions::from_bind(bind)?,
+        })
+    }
+}
+
+/// Reads the header of a `WithNames` or `WithNamesAndTypes` stream and works
+/// out the schema of the rows, with the `columns` structure standing in for
+/// or overriding the types of the header.
+fn read_header(
+    reader: &mut impl BufRead,
+    options: &RowBinaryOptions,
+) -> io::Result<Vec<(String, ColumnType)>> {
+    if options.format == RowBinaryFormat::RowBinary {
+        return Ok(options.columns.clone().unwrap_or_default());
+    }
+
+    let count = read_var_u64(reader)?;
+    let names = (0..count)
+        .map(|_| read_string(reader))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let explicit = options.columns.as_deref().unwrap_or_default();
+    if let Some((name, _)) = explicit.iter().find(|(name, _)| !names.contains(name)) {
+        return Err(invalid(format!("Column {} is not in the header", name)));
+    }
+    let explicit_type = |name: &str| {
+        explicit
+            .iter()
+            .find(|(explicit_name, _)| explicit_name == name)
+            .map(|(_, column_type)| column_type.clone())
+    };
+
+    if options.format == RowBinaryFormat::WithNames {
+        return names
+            .into_iter()
+            .map(|name| match explicit_type(&name) {
+                Some(column_type) => Ok((name, column_type)),
+                None => Err(invalid(format!("Column {} is missing from columns", name))),
+            })
+            .collect();
+    }
+
+    let mut schema = Vec::with_capacity(names.len());
+    let mut binary = options.native.binary_types;
+    for name in names {
+        let binary = match binary {
+            Some(binary) => binary,
+            None => *binary.insert(!looks_like_type_name(reader.fill_buf()?)),
+        };
+        let type_str = if binary {
+            binary_types::read_binary_type(reader)?
+        } else {
+            read_string(reader)?
+        };
+        let column_type = explicit_type(&name).unwrap_or_else(|| parse_column_type(&type_str).0);
+        schema.push((name, column_type));
+    }
+    Ok(schema)
+}
+
+fn read_rowbinary(
+    reader: &mut impl BufRead,
+    options: &RowBinaryOptions,
+) -> io::Result<Vec<Column>> {
+    let mut columns = read_header_columns(reader, options)?;
+    read_rows(reader, &mut columns, 0, usize::MAX)?;
+    resolve_columns(columns, &options.native)
+}
+
+fn read_header_columns(
+    reader: &mut impl BufRead,
+    options: &RowBinaryOptions,
+) -> io::Result<Vec<Column>> {
+    let columns: Vec<Column> = read_header(reader, options)?
+        .into_iter()
+        .map(|(name, type_)| Column {
+            name,
+            type_,
+            data: Vec::new(),
+        })
+        .collect();
+    // Unlike a Native block, a row cannot be skipped past a value whose size
+    // is unknown.
+    if let Some(column) = columns
+        .iter()
+        .find(|column| metadata::contains_unsupported(&column.type_))
+    {
+        return Err(invalid(format!(
+            "Cannot read column {} from RowBinary, its type is not supported",
+            column.name
+        )));
+    }
+    Ok(columns)
+}
+
+/// Appends up to `max_rows` rows to `columns`, the first of which is row
+/// `first_row` of the file. Returns the number of rows read.
+fn read_rows(
+    reader: &mut impl BufRead,
+    columns: &mut [Column],
+    first_row: usize,
+    max_rows: usize,
+) -> io::Result<usize> {
+    let mut rows = 0;
+    while rows < max_rows && !reader.fill_buf()?.is_empty() {
+        for column in columns.iter_mut() {
+            let value = read_row_value(reader, &column.type_).map_err(|err| {
+                invalid(format!(
+                    "Cannot read row {} of column {}: {}",
+                    first_row + rows + 1,
+                    column.name,
+                    err
+                ))
+            })?;
+            column.data.push(value);
+        }
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+/// A RowBinary stream, read `STREAM_BATCH_ROWS` rows at a time.
+struct RowBinaryStream {
+    reader: Box<dyn BufRead + Send>,
+    /// The header columns, without values.
+    header: Vec<Column>,
+    /// The columns of the first batch as bound.
+    bound: Vec<Column>,
+    options: NativeOptions,
+    rows: usize,
+    batches: usize,
+}
+
+impl RowBinaryStream {
+    /// Reads the header and the first batch of rows of `reader`, returning
+    /// their columns for the schema and the stream to read the others from.
+    fn open(
+        mut reader: Box<dyn BufRead + Send>,
+        options: &RowBinaryOptions,
+    ) -> io::Result<(Vec<Column>, Self)> {
+        let mut columns = read_header_columns(&mut reader, options)?;
+        let header = without_values(&columns);
+        let rows = read_rows(&mut reader, &mut columns, 0, STREAM_BATCH_ROWS)?;
+        let columns = resolve_columns(columns, &options.native)?;
+
+        let stream = RowBinaryStream {
+            reader,
+            header,
+            bound: without_values(&columns),
+            options: options.native.clone(),
+            rows,
+            batches: 1,
+        };
+        Ok((columns, stream))
+    }
+}
+
+impl ReadBlock for RowBinaryStream {
+    fn read_block(&mut self) -> io::Result<Option<Vec<Column>>> {
+        let mut columns = without_values(&self.header);
+        let rows = read_rows(&mut self.reader, &mut columns, self.rows, STREAM_BATCH_ROWS)?;
+        if rows == 0 {
+            return Ok(None);
+        }
+        self.rows += rows;
+
+        let columns = resolve_columns(columns, &self.options)?;
+        check_block_types(&self.bound, &columns, self.batches)?;
+        self.batches += 1;
+        Ok(Some(columns))
+    }
+}
+
+#[derive(Debug)]
+struct RowBinaryBindData {
+    filepath: String,
+    options: RowBinaryOptions,
+    /// Set when the file is a pipe or a device, opened once in `bind`.
+    streamed: Option<StreamedInput>,
+}
+
+struct ClickHouseRowBinaryVTab;
+
+impl VTab for ClickHouseRowBinaryVTab {
+    type InitData = ClickHouseInitData;
+    type BindData = RowBinaryBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let filepath = bind.get_parameter(0).to_string();
+        let options = RowBinaryOptions::from_bind(bind)?;
+
+        let mut reader = open_native_file(&filepath)?;
+        let mut streamed = None;
+        if StreamedInput::is_stream(&filepath) {
+            let (columns, rest) = RowBinaryStream::open(reader, &options)?;
+            bind_schema(bind, &columns, None);
+            streamed = Some(StreamedInput::new(columns, Box::new(rest)));
+        } else {
+            let columns = read_rowbinary(&mut reader, &options)?;
+            bind_columns(bind, &columns);
+        }
+
+        Ok(RowBinaryBindData {
+            filepath,
+            options,
+            streamed,
+        })
+    }
+
+    fn init(info: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        let bind_data = info.get_bind_data::<RowBinaryBindData>();
+        let bind_data = unsafe { &*bind_data };
+        if let Some(streamed) = &bind_data.streamed {
+            return Ok(streamed.take(&bind_data.filepath)?);
+        }
+        let mut reader = open_native_file(&bind_data.filepath)?;
+
+        let columns = read_rowbinary(&mut reader, &bind_data.options)?;
+        Ok(ClickHouseInitData::new(columns))
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        func.get_init_data().scan(output)?;
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        let mut parameters = NativeOptions::named_parameters();
+        parameters.push((
+            "columns".to_string(),
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        ));
+        parameters.push((
+            "format".to_string(),
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        ));
+        Some(parameters)
+    }
+}
+
+pub fn register_rowbinary(con: &Connection) -> Result<(), Box<dyn Error>> {
+    con.register_table_function::<ClickHouseRowBinaryVTab>("clickhouse_rowbinary")?;
+    Ok(())
+}
@@ -0,0 +1,355 @@
+use super::{
+    bind_schema,
+    part::{
+        part_cardinality, part_named_parameters, read_part_columns_data, read_part_schema,
+        resolve_part_columns, schema_columns, unescape_file_name, PartDir,
+    },
+    pruning::PartFilter,
+    table_metadata::{metadata_schema, TableSchema},
+    ClickHouseInitData, Column, ColumnType, NativeOptions,
+};
+use duckdb::{
+    core::{DataChunkHandle, LogicalTypeHandle, LogicalTypeId},
+    vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
+    Connection, Result,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A part name, `<partition>_<min block>_<max block>_<level>` with an
+/// optional `_<mutation>`.
+#[derive(Debug, Clone, PartialEq)]
+struct PartInfo {
+    partition: String,
+    min_block: u64,
+    max_block: u64,
+    level: u64,
+    mutation: u64,
+}
+
+impl PartInfo {
+    /// Parses a part name as ClickHouse does, with the partition ID up to
+    /// the first `_`. Other directories such as `detached` or `tmp_merge_*`
+    /// are not parts.
+    fn parse(name: &str) -> Option<Self> {
+        let mut fields = name.split('_');
+        let partition = fields.next().filter(|id| !id.is_empty())?.to_string();
+        let numbers = fields
+            .map(|field| field.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        let (min_block, max_block, level, mutation) = match numbers[..] {
+            [min_block, max_block, level] => (min_block, max_block, level, 0),
+            [min_block, max_block, level, mutation] => (min_block, max_block, level, mutation),
+            _ => return None,
+        };
+        (min_block <= max_block).then_some(PartInfo {
+            partition,
+            min_block,
+            max_block,
+            level,
+            mutation,
+        })
+    }
+
+    /// Whether merging or mutating made this part out of `other`.
+    fn contains(&self, other: &PartInfo) -> bool {
+        self.partition == other.partition
+            && self.min_block <= other.min_block
+            && other.max_block <= self.max_block
+            && self.level >= other.level
+            && self.mutation >= other.mutation
+            && self != other
+    }
+}
+
+/// Keeps the parts no other part covers, the active parts of the table, in
+/// block order.
+fn active_parts<T>(parts: Vec<(PartInfo, T)>) -> Vec<T> {
+    let infos: Vec<PartInfo> = parts.iter().map(|(info, _)| info.clone()).collect();
+    let mut active: Vec<(PartInfo, T)> = parts
+        .into_iter()
+        .filter(|(info, _)| !infos.iter().any(|other| other.contains(info)))
+        .collect();
+    active.sort_by(|(a, _), (b, _)| (&a.partition, a.min_block).cmp(&(&b.partition, b.min_block)));
+    active.into_iter().map(|(_, part)| part).collect()
+}
+
+/// The parts of a table's data directory.
+fn read_table_parts(dir: &Path) -> io::Result<Vec<PartDir>> {
+    let mut parts = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(info) = entry.file_name().to_str().and_then(PartInfo::parse) else {
+            continue;
+        };
+        if entry.file_type()?.is_dir() {
+            parts.push((info, PartDir::new(entry.path())));
+        }
+    }
+    Ok(active_parts(parts))
+}
+
+/// The text of the XML element `tag` directly in `xml`, unescaped.
+fn xml_element(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(
+        xml[start..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// A file listed in `.backup`: the file its contents are stored in, or
+/// `None` for an empty file, which backups leave out.
+struct BackupFile {
+    name: String,
+    data_file: Option<String>,
+}
+
+/// Reads the `<contents>` of a backup's `.backup` metadata.
+fn read_backup_files(dir: &Path) -> io::Result<Vec<BackupFile>> {
+    let xml = std::fs::read_to_string(dir.join(".backup"))?;
+    let mut files = Vec::new();
+    for file in xml.split("<file>").skip(1) {
+        let file = file.split("</file>").next().unwrap_or_default();
+        let name = xml_element(file, "name")
+            .ok_or_else(|| invalid("File without a name in .backup".to_string()))?;
+        let size: u64 = xml_element(file, "size")
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| invalid(format!("File {} without a size in .backup", name)))?;
+        let base_size: u64 = xml_element(file, "base_size")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or_default();
+        if xml_element(file, "use_base").as_deref() == Some("true") || base_size > 0 {
+            return Err(invalid(format!(
+                "{} is stored in the base backup, incremental backups are not supported",
+                name
+            )));
+        }
+        if xml_element(file, "encrypted_by_disk").as_deref() == Some("true") {
+            return Err(invalid(format!(
+                "{} is encrypted, encrypted backups are not supported",
+                name
+            )));
+        }
+        let data_file = (size > 0).then(|| xml_element(file, "data_file").unwrap_or(name.clone()));
+        files.push(BackupFile { name, data_file });
+    }
+    Ok(files)
+}
+
+/// The parts of a table in a BACKUP, stored under
+/// `data/<database>/<table>/<part>/`, with a `shards/<n>/replicas/<n>/`
+/// prefix for backups made `ON CLUSTER`.
+fn read_backup_parts(dir: &Path, table: Option<&str>) -> io::Result<Vec<PartDir>> {
+    // Parts by table, then by the path of their directory in the backup.
+    let mut tables: BTreeMap<String, BTreeMap<String, HashMap<String, Option<PathBuf>>>> =
+        BTreeMap::new();
+    for file in read_backup_files(dir)? {
+        let mut segments: Vec<&str> = file.name.split('/').collect();
+        if segments.len() > 4 && segments[0] == "shards" && segments[2] == "replicas" {
+            segments.drain(..4);
+        }
+        let [_, database, name, _, path @ ..] = segments.as_slice() else {
+            continue;
+        };
+        if segments[0] != "data" || path.is_empty() {
+            continue;
+        }
+        let table = format!(
+            "{}.{}",
+            unescape_file_name(database),
+            unescape_file_name(name)
+        );
+        let part_dir = file.name[..file.name.len() - path.join("/").len() - 1].to_string();
+        tables
+            .entry(table)
+            .or_default()
+            .entry(part_dir)
+            .or_default()
+            .insert(path.join("/"), file.data_file.map(|data| dir.join(data)));
+    }
+
+    let parts = match table {
+        Some(table) => tables
+            .remove(table)
+            .ok_or_else(|| invalid(format!("Table {} is not in the backup", table)))?,
+        None if tables.len() > 1 => {
+            let names: Vec<&str> = tables.keys().map(String::as_str).collect();
+            return Err(invalid(format!(
+                "The backup holds tables {}, pick one with table_name := 'database.table'",
+                names.join(", ")
+            )));
+        }
+        None => tables.into_values().next().unwrap_or_default(),
+    };
+    let parts = parts
+        .into_iter()
+        .filter_map(|(part_dir, files)| {
+            let name = part_dir.rsplit('/').next()?;
+            Some((
+                PartInfo::parse(name)?,
+                PartDir::in_backup(dir.join(&part_dir), files),
+            ))
+        })
+        .collect();
+    Ok(active_parts(parts))
+}
+
+/// The active parts of a MergeTree table directory or of a table in a
+/// BACKUP directory.
+fn table_parts(path: &str, table: Option<&str>) -> io::Result<Vec<PartDir>> {
+    let dir = Path::new(path);
+    if dir.is_file() {
+        return Err(invalid(format!(
+            "{} is not a directory, extract backup archives first",
+            path
+        )));
+    }
+    let parts = if dir.join(".backup").exists() {
+        read_backup_parts(dir, table)?
+    } else {
+        read_table_parts(dir)?
+    };
+    if parts.is_empty() {
+        return Err(invalid(format!("No active parts in {}", path)));
+    }
+    Ok(parts)
+}
+
+/// Reads the active parts of a table, one after the other.
+fn read_table_dir(
+    path: &str,
+    table: Option<&str>,
+    options: &NativeOptions,
+    filter: &PartFilter,
+    metadata: Option<&[(String, ColumnType)]>,
+) -> io::Result<Vec<Column>> {
+    let parts = table_parts(path, table)?;
+
+    let mut columns: Option<(Vec<Column>, PathBuf)> = None;
+    for part in &parts {
+        let part_columns = read_part_columns_data(part, filter, metadata)?;
+        let Some((columns, first)) = &mut columns else {
+            columns = Some((part_columns, part.path().to_path_buf()));
+            continue;
+        };
+        let same = columns.len() == part_columns.len()
+            && columns.iter().zip(&part_columns).all(|(a, b)| {
+                a.name == b.name && format!("{:?}", a.type_) == format!("{:?}", b.type_)
+            });
+        if !same {
+            return Err(invalid(format!(
+                "Part {} has other columns than {}, parts from before an ALTER are not supported",
+                part.path().display(),
+                first.display()
+            )));
+        }
+        for (column, part_column) in columns.iter_mut().zip(part_columns) {
+            column.data.extend(part_column.data);
+        }
+    }
+    let (columns, _) = columns.ok_or_else(|| invalid(format!("No active parts in {}", path)))?;
+    resolve_part_columns(columns, options)
+}
+
+#[derive(Debug)]
+struct TableDirBindData {
+    path: String,
+    table: Option<String>,
+    options: NativeOptions,
+    filter: PartFilter,
+    metadata: Option<TableSchema>,
+}
+
+struct ClickHouseTableDirVTab;
+
+impl VTab for ClickHouseTableDirVTab {
+    type InitData = ClickHouseInitData;
+    type BindData = TableDirBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let path = bind.get_parameter(0).to_string();
+        let table = bind
+            .get_named_parameter("table_name")
+            .map(|v| v.to_string());
+        let options = NativeOptions::from_bind(bind)?;
+        let filter = PartFilter::from_bind(bind)?;
+        let metadata = metadata_schema(bind)?;
+
+        // The values are only read in init. Every part has the columns of
+        // the first one, which init checks, and its row count in count.txt.
+        let parts = table_parts(&path, table.as_deref())?;
+        let schema = read_part_schema(&parts[0], metadata.as_deref())?;
+        filter.check(&schema)?;
+        let rows = parts
+            .iter()
+            .map(|part| part_cardinality(part, &schema, &filter))
+            .sum::<io::Result<u64>>()?;
+        bind_schema(
+            bind,
+            &schema_columns(&schema, &options)?,
+            Some((rows, filter.is_empty())),
+        );
+
+        Ok(TableDirBindData {
+            path,
+            table,
+            options,
+            filter,
+            metadata,
+        })
+    }
+
+    fn init(info: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        let bind_data = info.get_bind_data::<TableDirBindData>();
+        let bind_data = unsafe { &*bind_data };
+
+        let columns = read_table_dir(
+            &bind_data.path,
+            bind_data.table.as_deref(),
+            &bind_data.options,
+            &bind_data.filter,
+            bind_data.metadata.as_deref(),
+        )?;
+        Ok(ClickHouseInitData::new(columns))
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        func.get_init_data().scan(output)?;
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+
+    fn named_parameters() -> Option<Vec<(String, LogicalTypeHandle)>> {
+        let mut parameters = part_named_parameters();
+        parameters.push((
+            "table_name".to_string(),
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        ));
+        Some(parameters)
+    }
+}
+
+pub fn register_table_dir_function(con: &Connection) -> Result<(), Box<dyn Error>> {
+    con.register_table_function::<ClickHouseTableDirVTab>("clickhouse_table_dir")?;
+    Ok(())
+}
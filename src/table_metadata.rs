@@ -0,0 +1,647 @@
+use super::{
+    bind_columns, logical_type,
+    metadata::{contains_unsupported, type_name},
+    parse_column_type,
+    part::{escape_file_name, unescape_file_name},
+    ClickHouseInitData, Column, ColumnData, ColumnType,
+};
+use duckdb::{
+    core::{DataChunkHandle, LogicalTypeHandle, LogicalTypeId},
+    vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab},
+    Connection, Result,
+};
+use std::{
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Word,
+    /// A `'string'` literal.
+    String,
+    /// A back-quoted or double-quoted identifier.
+    Identifier,
+    Open,
+    Close,
+    Comma,
+    Other,
+}
+
+/// A token of a statement, by its position in the text and its nesting in
+/// brackets.
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+    depth: usize,
+}
+
+/// Splits SQL into tokens, skipping whitespace and comments.
+fn tokenize(text: &str) -> Vec<Token> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let byte = bytes[pos];
+        let kind = match byte {
+            b' ' | b'\t' | b'\r' | b'\n' => {
+                pos += 1;
+                continue;
+            }
+            b'-' if bytes.get(pos + 1) == Some(&b'-') => {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(pos + 1) == Some(&b'*') => {
+                pos = text[pos + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| pos + end + 4);
+                continue;
+            }
+            b'\'' | b'`' | b'"' => {
+                pos += 1;
+                while pos < bytes.len() {
+                    match bytes[pos] {
+                        b'\\' => pos += 2,
+                        quote if quote == byte => {
+                            pos += 1;
+                            // A doubled quote stands for itself.
+                            if bytes.get(pos) != Some(&byte) {
+                                break;
+                            }
+                            pos += 1;
+                        }
+                        _ => pos += 1,
+                    }
+                }
+                match byte {
+                    b'\'' => TokenKind::String,
+                    _ => TokenKind::Identifier,
+                }
+            }
+            b'(' | b'[' => {
+                pos += 1;
+                TokenKind::Open
+            }
+            b')' | b']' => {
+                pos += 1;
+                depth = depth.saturating_sub(1);
+                TokenKind::Close
+            }
+            b',' => {
+                pos += 1;
+                TokenKind::Comma
+            }
+            byte if byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80 => {
+                while pos < bytes.len()
+                    && (bytes[pos].is_ascii_alphanumeric()
+                        || bytes[pos] == b'_'
+                        || bytes[pos] >= 0x80)
+                {
+                    pos += 1;
+                }
+                TokenKind::Word
+            }
+            _ => {
+                pos += 1;
+                TokenKind::Other
+            }
+        };
+        tokens.push(Token {
+            kind,
+            start,
+            end: pos.min(bytes.len()),
+            depth,
+        });
+        if kind == TokenKind::Open {
+            depth += 1;
+        }
+    }
+    tokens
+}
+
+/// Removes the quotes of a string literal or quoted identifier.
+fn unquote(text: &str) -> String {
+    let quote = text.chars().next().unwrap_or_default();
+    let inner = text
+        .strip_prefix(quote)
+        .and_then(|inner| inner.strip_suffix(quote))
+        .unwrap_or(text);
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unquoted.push('\n'),
+                Some('t') => unquoted.push('\t'),
+                Some('0') => unquoted.push('\0'),
+                Some(c) => unquoted.push(c),
+                None => {}
+            },
+            c if c == quote => {
+                unquoted.push(c);
+                chars.next();
+            }
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+fn is_word(text: &str, token: &Token, words: &[&str]) -> bool {
+    token.kind == TokenKind::Word
+        && words
+            .iter()
+            .any(|word| text[token.start..token.end].eq_ignore_ascii_case(word))
+}
+
+/// A column of a table definition.
+#[derive(Debug, Clone)]
+struct ColumnDefinition {
+    name: String,
+    type_str: String,
+    default_kind: Option<String>,
+    default_expression: Option<String>,
+    comment: Option<String>,
+}
+
+impl ColumnDefinition {
+    /// Whether the column is written to the table's files: `ALIAS` and
+    /// `EPHEMERAL` columns are computed instead.
+    fn stored(&self) -> bool {
+        !matches!(self.default_kind.as_deref(), Some("ALIAS" | "EPHEMERAL"))
+    }
+}
+
+/// The words that end a column's default expression.
+const COLUMN_CLAUSES: &[&str] = &[
+    "CODEC",
+    "COMMENT",
+    "TTL",
+    "SETTINGS",
+    "STATISTICS",
+    "PRIMARY",
+];
+
+/// Parses one element of a column list, `None` for the indexes,
+/// projections and constraints listed with the columns.
+fn parse_column(text: &str) -> Option<ColumnDefinition> {
+    let tokens = tokenize(text);
+    let first = tokens.first()?;
+    if is_word(
+        text,
+        first,
+        &["INDEX", "PROJECTION", "CONSTRAINT", "PRIMARY", "STATISTICS"],
+    ) {
+        return None;
+    }
+    let name = match first.kind {
+        TokenKind::Identifier => unquote(&text[first.start..first.end]),
+        _ => text[first.start..first.end].to_string(),
+    };
+
+    // A type name with its arguments, then NULL or NOT NULL.
+    let mut idx = 1;
+    let type_start = tokens.get(idx).map_or(text.len(), |token| token.start);
+    let mut type_end = type_start;
+    if tokens.get(idx).is_some_and(|token| {
+        token.kind == TokenKind::Word
+            && !is_word(
+                text,
+                token,
+                &[
+                    "DEFAULT",
+                    "MATERIALIZED",
+                    "ALIAS",
+                    "EPHEMERAL",
+                    "NULL",
+                    "NOT",
+                ],
+            )
+    }) {
+        type_end = tokens[idx].end;
+        idx += 1;
+        if tokens
+            .get(idx)
+            .is_some_and(|token| token.kind == TokenKind::Open)
+        {
+            while idx < tokens.len() {
+                type_end = tokens[idx].end;
+                idx += 1;
+                if tokens[idx - 1].kind == TokenKind::Close && tokens[idx - 1].depth == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    let mut type_str = text[type_start..type_end].to_string();
+    if tokens
+        .get(idx)
+        .is_some_and(|token| is_word(text, token, &["NOT"]))
+    {
+        idx += 2;
+    } else if tokens
+        .get(idx)
+        .is_some_and(|token| is_word(text, token, &["NULL"]))
+    {
+        type_str = format!("Nullable({})", type_str);
+        idx += 1;
+    }
+
+    let mut column = ColumnDefinition {
+        name,
+        type_str,
+        default_kind: None,
+        default_expression: None,
+        comment: None,
+    };
+    let rest = &tokens[idx.min(tokens.len())..];
+    for (at, token) in rest
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| token.depth == 0)
+    {
+        if is_word(
+            text,
+            token,
+            &["DEFAULT", "MATERIALIZED", "ALIAS", "EPHEMERAL"],
+        ) {
+            let end = rest[at + 1..]
+                .iter()
+                .find(|next| next.depth == 0 && is_word(text, next, COLUMN_CLAUSES))
+                .map_or(text.len(), |next| next.start);
+            let start = rest.get(at + 1).map_or(end, |next| next.start.min(end));
+            column.default_kind = Some(text[token.start..token.end].to_uppercase());
+            column.default_expression = Some(text[start..end].trim())
+                .filter(|e| !e.is_empty())
+                .map(str::to_string);
+        } else if is_word(text, token, &["COMMENT"]) {
+            column.comment = rest
+                .get(at + 1)
+                .filter(|next| next.kind == TokenKind::String)
+                .map(|next| unquote(&text[next.start..next.end]));
+        }
+    }
+    Some(column)
+}
+
+/// A table as its `ATTACH` statement in `metadata/<database>/<table>.sql`
+/// defines it.
+#[derive(Debug, Clone, Default)]
+struct TableDefinition {
+    database: String,
+    table: String,
+    uuid: Option<String>,
+    engine: Option<String>,
+    partition_key: Option<String>,
+    sorting_key: Option<String>,
+    primary_key: Option<String>,
+    columns: Vec<ColumnDefinition>,
+}
+
+/// The words that start the clauses after the column list.
+const TABLE_CLAUSES: &[&str] = &[
+    "ENGINE",
+    "PARTITION",
+    "ORDER",
+    "PRIMARY",
+    "SAMPLE",
+    "TTL",
+    "SETTINGS",
+    "COMMENT",
+    "AS",
+    "LIFETIME",
+    "LAYOUT",
+    "SOURCE",
+    "RANGE",
+];
+
+/// Parses an `ATTACH TABLE` or `CREATE TABLE` statement, or a view or
+/// dictionary. `None` for the `ATTACH DATABASE` of `metadata/<db>.sql`.
+fn parse_statement(text: &str) -> Option<TableDefinition> {
+    let tokens = tokenize(text);
+    let words: Vec<String> = tokens
+        .iter()
+        .take(4)
+        .map(|token| text[token.start..token.end].to_uppercase())
+        .collect();
+    if words.get(1).is_some_and(|word| word == "DATABASE") {
+        return None;
+    }
+    let mut table = TableDefinition::default();
+
+    // The column list is the first bracket before the clauses.
+    let clause_start = |from: usize| {
+        tokens[from..]
+            .iter()
+            .position(|token| token.depth == 0 && is_word(text, token, TABLE_CLAUSES))
+            .map_or(tokens.len(), |at| from + at)
+    };
+    let mut idx = 0;
+    while idx < tokens.len() && tokens[idx].kind != TokenKind::Open {
+        if is_word(text, &tokens[idx], &["UUID"]) {
+            table.uuid = tokens
+                .get(idx + 1)
+                .filter(|token| token.kind == TokenKind::String)
+                .map(|token| unquote(&text[token.start..token.end]));
+        }
+        if idx == clause_start(idx) {
+            break;
+        }
+        idx += 1;
+    }
+    if tokens
+        .get(idx)
+        .is_some_and(|token| token.kind == TokenKind::Open)
+    {
+        let mut element_start = tokens.get(idx + 1).map_or(text.len(), |token| token.start);
+        idx += 1;
+        while idx < tokens.len() {
+            let token = tokens[idx];
+            let closes = token.kind == TokenKind::Close && token.depth == 0;
+            if (token.kind == TokenKind::Comma && token.depth == 1) || closes {
+                table
+                    .columns
+                    .extend(parse_column(text[element_start..token.start].trim()));
+                element_start = token.end;
+            }
+            idx += 1;
+            if closes {
+                break;
+            }
+        }
+    }
+
+    // Each clause runs to the next one. The query of a view comes last.
+    let mut at = clause_start(idx.min(tokens.len()));
+    while at < tokens.len() && !is_word(text, &tokens[at], &["AS"]) {
+        let next = clause_start(at + 1);
+        let mut value_start = at + 1;
+        while tokens.get(value_start).is_some_and(|token| {
+            is_word(text, token, &["BY", "KEY"]) || &text[token.start..token.end] == "="
+        }) {
+            value_start += 1;
+        }
+        let end = tokens.get(next).map_or(text.len(), |token| token.start);
+        let value = tokens
+            .get(value_start)
+            .filter(|_| value_start < next)
+            .map(|token| {
+                text[token.start..end]
+                    .trim()
+                    .trim_end_matches(';')
+                    .to_string()
+            });
+        match text[tokens[at].start..tokens[at].end]
+            .to_uppercase()
+            .as_str()
+        {
+            "ENGINE" => table.engine = value,
+            "PARTITION" => table.partition_key = value,
+            "ORDER" => table.sorting_key = value,
+            "PRIMARY" => table.primary_key = value,
+            _ => {}
+        }
+        at = next;
+    }
+    // Without PRIMARY KEY, ClickHouse indexes the sorting key.
+    if table.primary_key.is_none() {
+        table.primary_key = table.sorting_key.clone();
+    }
+    Some(table)
+}
+
+impl TableDefinition {
+    /// Where the table's data lives in the ClickHouse directory: under its
+    /// UUID in an Atomic database, by name in an Ordinary one.
+    fn data_path(&self) -> Option<String> {
+        self.engine.as_ref()?;
+        Some(match &self.uuid {
+            Some(uuid) => format!("store/{}/{}/", &uuid[..uuid.len().min(3)], uuid),
+            None => format!(
+                "data/{}/{}/",
+                escape_file_name(&self.database),
+                escape_file_name(&self.table)
+            ),
+        })
+    }
+
+    /// The columns written to the table's files, with the columns of a
+    /// `Nested` flattened to arrays as parts store them.
+    fn stored_columns(&self) -> Vec<(String, ColumnType)> {
+        let mut columns = Vec::new();
+        for column in self.columns.iter().filter(|column| column.stored()) {
+            let column_type = parse_column_type(&column.type_str).0;
+            let nested = column.type_str.trim_start().starts_with("Nested(");
+            match column_type {
+                ColumnType::Array(element) if nested => {
+                    let ColumnType::Tuple(elements) = *element else {
+                        unreachable!("Nested parses to an array of tuples")
+                    };
+                    for (name, element_type) in elements {
+                        let name = format!("{}.{}", column.name, name);
+                        columns.push((name, ColumnType::Array(Box::new(element_type))));
+                    }
+                }
+                column_type => columns.push((column.name.clone(), column_type)),
+            }
+        }
+        columns
+    }
+}
+
+/// Reads the table a metadata file defines, named after the file and its
+/// database directory, as the statement itself names the table `_`.
+fn read_metadata_file(path: &Path) -> io::Result<Option<TableDefinition>> {
+    let text = std::fs::read_to_string(path)?;
+    let Some(mut table) = parse_statement(&text) else {
+        return Ok(None);
+    };
+    let name = |path: Option<&Path>| {
+        let name = path
+            .and_then(|path| path.file_stem())
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        unescape_file_name(name)
+    };
+    table.table = name(Some(path));
+    table.database = name(path.parent());
+    Ok(Some(table))
+}
+
+fn sql_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "sql"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads the tables of a metadata file, of a database's metadata directory,
+/// or of every database under `metadata/`, also found from the ClickHouse
+/// data directory holding it.
+fn read_metadata(path: &str) -> io::Result<Vec<TableDefinition>> {
+    let mut dir = PathBuf::from(path);
+    if dir.is_file() {
+        return Ok(read_metadata_file(&dir)?.into_iter().collect());
+    }
+    if dir.join("metadata").is_dir() {
+        dir = dir.join("metadata");
+    }
+    let mut files = sql_files(&dir)?;
+    if files.is_empty() || files.iter().all(|file| file.with_extension("").is_dir()) {
+        // The metadata root: `<db>.sql` files beside `<db>/` directories.
+        let mut databases: Vec<PathBuf> = std::fs::read_dir(&dir)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        databases.sort();
+        files = Vec::new();
+        for database in databases {
+            files.extend(sql_files(&database)?);
+        }
+    }
+    let mut tables = Vec::new();
+    for file in files {
+        tables.extend(read_metadata_file(&file)?);
+    }
+    Ok(tables)
+}
+
+/// The names and types of a table's stored columns.
+pub(crate) type TableSchema = Vec<(String, ColumnType)>;
+
+/// The stored columns of the table defined in the metadata file given as
+/// the `metadata` parameter, for the readers of table files that do not
+/// list their columns.
+pub(crate) fn metadata_schema(bind: &BindInfo) -> Result<Option<TableSchema>, Box<dyn Error>> {
+    let Some(path) = bind.get_named_parameter("metadata") else {
+        return Ok(None);
+    };
+    let path = path.to_string();
+    let table = read_metadata_file(Path::new(&path))?
+        .ok_or_else(|| invalid(format!("{} does not define a table", path)))?;
+    let columns = table.stored_columns();
+    if columns.is_empty() {
+        return Err(invalid(format!("{} does not list the table's columns", path)).into());
+    }
+    Ok(Some(columns))
+}
+
+/// The rows of `clickhouse_metadata`, a column of a table each.
+fn metadata_columns(tables: &[TableDefinition]) -> Vec<Column> {
+    let text = || ColumnType::Nullable(Box::new(ColumnType::String));
+    let mut columns: Vec<Column> = [
+        ("database", ColumnType::String),
+        ("table_name", ColumnType::String),
+        ("uuid", text()),
+        ("engine", text()),
+        ("partition_key", text()),
+        ("sorting_key", text()),
+        ("primary_key", text()),
+        ("data_path", text()),
+        ("position", ColumnType::Int64),
+        ("name", ColumnType::String),
+        ("type", ColumnType::String),
+        ("duckdb_type", ColumnType::String),
+        ("supported", ColumnType::Bool),
+        ("default_kind", text()),
+        ("default_expression", text()),
+        ("comment", text()),
+    ]
+    .into_iter()
+    .map(|(name, type_)| Column {
+        name: name.to_string(),
+        type_,
+        data: Vec::new(),
+    })
+    .collect();
+
+    let value = |text: &Option<String>| match text {
+        Some(text) => ColumnData::String(text.clone()),
+        None => ColumnData::Null,
+    };
+    for table in tables {
+        for (position, column) in table.columns.iter().enumerate() {
+            let column_type = parse_column_type(&column.type_str).0;
+            let row = [
+                ColumnData::String(table.database.clone()),
+                ColumnData::String(table.table.clone()),
+                value(&table.uuid),
+                value(&table.engine),
+                value(&table.partition_key),
+                value(&table.sorting_key),
+                value(&table.primary_key),
+                value(&table.data_path()),
+                ColumnData::Int64(position as i64 + 1),
+                ColumnData::String(column.name.clone()),
+                ColumnData::String(column.type_str.clone()),
+                ColumnData::String(type_name(&logical_type(&column_type))),
+                ColumnData::Bool(!contains_unsupported(&column_type)),
+                value(&column.default_kind),
+                value(&column.default_expression),
+                value(&column.comment),
+            ];
+            for (column, value) in columns.iter_mut().zip(row) {
+                column.data.push(value);
+            }
+        }
+    }
+    columns
+}
+
+#[derive(Debug)]
+struct MetadataBindData {
+    path: String,
+}
+
+struct ClickHouseMetadataVTab;
+
+impl VTab for ClickHouseMetadataVTab {
+    type InitData = ClickHouseInitData;
+    type BindData = MetadataBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn Error>> {
+        let path = bind.get_parameter(0).to_string();
+        let columns = metadata_columns(&read_metadata(&path)?);
+        bind_columns(bind, &columns);
+        Ok(MetadataBindData { path })
+    }
+
+    fn init(info: &InitInfo) -> Result<Self::InitData, Box<dyn Error>> {
+        let bind_data = info.get_bind_data::<MetadataBindData>();
+        let bind_data = unsafe { &*bind_data };
+
+        let columns = metadata_columns(&read_metadata(&bind_data.path)?);
+        Ok(ClickHouseInitData::new(columns))
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn Error>> {
+        func.get_init_data().scan(output)?;
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![LogicalTypeHandle::from(LogicalTypeId::Varchar)])
+    }
+}
+
+pub fn register_table_metadata_function(con: &Connection) -> Result<(), Box<dyn Error>> {
+    con.register_table_function::<ClickHouseMetadataVTab>("clickhouse_metadata")?;
+    Ok(())
+}
@@ -0,0 +1,327 @@
+use super::{default_value, low_cardinality, ColumnData, ColumnType};
+use std::io::{self, Write};
+
+pub(crate) fn write_var_u64(out: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub(crate) fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_var_u64(out, bytes.len() as u64)?;
+    out.write_all(bytes)
+}
+
+fn tuple_element_name(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("`{}`", name.replace('`', "\\`"))
+    }
+}
+
+/// Renders a type the way ClickHouse writes it in a column header.
+pub(crate) fn type_name(column_type: &ColumnType) -> String {
+    match column_type {
+        ColumnType::String => "String".to_string(),
+        ColumnType::FixedString(size) => format!("FixedString({})", size),
+        ColumnType::UInt8 => "UInt8".to_string(),
+        ColumnType::UInt16 => "UInt16".to_string(),
+        ColumnType::UInt32 => "UInt32".to_string(),
+        ColumnType::UInt64 => "UInt64".to_string(),
+        ColumnType::Int | ColumnType::Int32 => "Int32".to_string(),
+        ColumnType::Int8 => "Int8".to_string(),
+        ColumnType::Int16 => "Int16".to_string(),
+        ColumnType::Int64 => "Int64".to_string(),
+        ColumnType::Float32 => "Float32".to_string(),
+        ColumnType::Float64 => "Float64".to_string(),
+        ColumnType::Bool => "Bool".to_string(),
+        ColumnType::Date => "Date".to_string(),
+        ColumnType::Date32 => "Date32".to_string(),
+        ColumnType::DateTime(None) => "DateTime".to_string(),
+        ColumnType::DateTime(Some(tz)) => format!("DateTime('{}')", tz),
+        ColumnType::DateTime64(precision, None) => format!("DateTime64({})", precision),
+        ColumnType::DateTime64(precision, Some(tz)) => {
+            format!("DateTime64({}, '{}')", precision, tz)
+        }
+        ColumnType::Decimal(precision, scale) => format!("Decimal({}, {})", precision, scale),
+        ColumnType::Time => "Time".to_string(),
+        ColumnType::Time64(precision) => format!("Time64({})", precision),
+        ColumnType::Uuid => "UUID".to_string(),
+        ColumnType::Nullable(inner) => format!("Nullable({})", type_name(inner)),
+        ColumnType::LowCardinality(inner) => format!("LowCardinality({})", type_name(inner)),
+        ColumnType::Array(inner) => format!("Array({})", type_name(inner)),
+        ColumnType::Map(key, value) => format!("Map({}, {})", type_name(key), type_name(value)),
+        ColumnType::Tuple(elements) => format!(
+            "Tuple({})",
+            elements
+                .iter()
+                .map(|(name, element_type)| {
+                    format!("{} {}", tuple_element_name(name), type_name(element_type))
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        other => unreachable!("{:?} is never written", other),
+    }
+}
+
+/// Writes the serialization prefixes of a column, nested types first in
+/// element order, as `read_prefix` expects them.
+fn write_prefix(out: &mut impl Write, column_type: &ColumnType) -> io::Result<()> {
+    match column_type {
+        ColumnType::Nullable(inner) | ColumnType::Array(inner) => write_prefix(out, inner),
+        ColumnType::Map(key, value) => {
+            write_prefix(out, key)?;
+            write_prefix(out, value)
+        }
+        ColumnType::Tuple(elements) => elements
+            .iter()
+            .try_for_each(|(_, element_type)| write_prefix(out, element_type)),
+        ColumnType::LowCardinality(_) => low_cardinality::write_prefix(out),
+        _ => Ok(()),
+    }
+}
+
+fn items(value: &ColumnData) -> &[ColumnData] {
+    match value {
+        ColumnData::Array(items) => items,
+        _ => &[],
+    }
+}
+
+fn write_offsets(out: &mut impl Write, values: &[&ColumnData]) -> io::Result<()> {
+    let mut offset = 0u64;
+    for value in values {
+        offset += items(value).len() as u64;
+        out.write_all(&offset.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes the values of a column in Native layout, the inverse of
+/// `read_column_data`. NULLs outside of Nullable types are written as the
+/// type's default value.
+pub(crate) fn write_column_data(
+    out: &mut impl Write,
+    column_type: &ColumnType,
+    values: &[&ColumnData],
+) -> io::Result<()> {
+    match column_type {
+        ColumnType::Nullable(inner) => {
+            let null_map: Vec<u8> = values
+                .iter()
+                .map(|value| matches!(value, ColumnData::Null) as u8)
+                .collect();
+            out.write_all(&null_map)?;
+            return write_column_data(out, inner, values);
+        }
+        ColumnType::LowCardinality(inner) => {
+            return low_cardinality::write_low_cardinality_data(out, inner, values)
+        }
+        ColumnType::Array(inner) => {
+            write_offsets(out, values)?;
+            let children: Vec<&ColumnData> = values.iter().flat_map(|v| items(v)).collect();
+            return write_column_data(out, inner, &children);
+        }
+        ColumnType::Map(key, value) => {
+            write_offsets(out, values)?;
+            let entries: Vec<&ColumnData> = values.iter().flat_map(|v| items(v)).collect();
+            for (idx, element_type) in [key, value].into_iter().enumerate() {
+                let children: Vec<&ColumnData> = entries
+                    .iter()
+                    .map(|entry| match entry {
+                        ColumnData::Tuple(pair) => &pair[idx],
+                        other => other,
+                    })
+                    .collect();
+                write_column_data(out, element_type, &children)?;
+            }
+            return Ok(());
+        }
+        ColumnType::Tuple(elements) => {
+            for (idx, (_, element_type)) in elements.iter().enumerate() {
+                let children: Vec<&ColumnData> = values
+                    .iter()
+                    .map(|value| match value {
+                        ColumnData::Tuple(items) => &items[idx],
+                        _ => &ColumnData::Null,
+                    })
+                    .collect();
+                write_column_data(out, element_type, &children)?;
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let default = default_value(column_type);
+    for value in values {
+        let value = match value {
+            ColumnData::Null => &default,
+            value => *value,
+        };
+        write_value(out, column_type, value)?;
+    }
+    Ok(())
+}
+
+fn write_value(
+    out: &mut impl Write,
+    column_type: &ColumnType,
+    value: &ColumnData,
+) -> io::Result<()> {
+    match (column_type, value) {
+        (ColumnType::String, ColumnData::String(s)) => write_bytes(out, s.as_bytes()),
+        (ColumnType::String, ColumnData::Blob(bytes)) => write_bytes(out, bytes),
+        (ColumnType::Bool, ColumnData::Bool(v)) => out.write_all(&[*v as u8]),
+        (ColumnType::UInt8, ColumnData::UInt8(v)) => out.write_all(&[*v]),
+        (ColumnType::UInt16, ColumnData::UInt16(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::UInt32, ColumnData::UInt32(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::UInt64, ColumnData::UInt64(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Int8, ColumnData::Int8(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Int16, ColumnData::Int16(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Int32, ColumnData::Int32(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Int64, ColumnData::Int64(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Float32, ColumnData::Float32(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Float64, ColumnData::Float64(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Date32, ColumnData::Date(v)) => out.write_all(&v.to_le_bytes()),
+        // Timestamps and times are kept in the unit of the type they are
+        // written as.
+        (ColumnType::DateTime(_), ColumnData::Timestamp(v)) => {
+            out.write_all(&(*v as u32).to_le_bytes())
+        }
+        (ColumnType::DateTime64(..), ColumnData::Timestamp(v))
+        | (ColumnType::Time64(_), ColumnData::Time(v)) => out.write_all(&v.to_le_bytes()),
+        (ColumnType::Decimal(precision, _), ColumnData::Decimal(v)) => match precision {
+            0..=9 => out.write_all(&(*v as i32).to_le_bytes()),
+            10..=18 => out.write_all(&(*v as i64).to_le_bytes()),
+            _ => out.write_all(&v.to_le_bytes()),
+        },
+        (ColumnType::Uuid, ColumnData::Uuid(v)) => {
+            // The inverse of `uuid_from_parts`: high then low half.
+            let bits = (*v as u128) ^ (1u128 << 127);
+            out.write_all(&((bits >> 64) as u64).to_le_bytes())?;
+            out.write_all(&(bits as u64).to_le_bytes())
+        }
+        (column_type, value) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Cannot write {:?} as {}", value, type_name(column_type)),
+        )),
+    }
+}
+
+/// Writes one Native block: the column and row counts, then each column's
+/// name, type, prefixes and values.
+pub(crate) fn write_native_block(
+    out: &mut impl Write,
+    columns: &[(String, ColumnType)],
+    data: &[Vec<ColumnData>],
+) -> io::Result<()> {
+    let rows = data.first().map_or(0, Vec::len);
+    write_var_u64(out, columns.len() as u64)?;
+    write_var_u64(out, rows as u64)?;
+    for ((name, column_type), values) in columns.iter().zip(data) {
+        write_bytes(out, name.as_bytes())?;
+        write_bytes(out, type_name(column_type).as_bytes())?;
+        if rows > 0 {
+            write_prefix(out, column_type)?;
+            let values: Vec<&ColumnData> = values.iter().collect();
+            write_column_data(out, column_type, &values)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a single value in its per-row form, the inverse of
+/// `read_row_value`. NULLs outside of Nullable types are written as the
+/// type's default value.
+pub(crate) fn write_row_value(
+    out: &mut impl Write,
+    column_type: &ColumnType,
+    value: &ColumnData,
+) -> io::Result<()> {
+    match column_type {
+        ColumnType::Nullable(inner) => match value {
+            ColumnData::Null => out.write_all(&[1]),
+            value => {
+                out.write_all(&[0])?;
+                write_row_value(out, inner, value)
+            }
+        },
+        ColumnType::LowCardinality(inner) => write_row_value(out, inner, value),
+        ColumnType::Array(inner) => {
+            write_var_u64(out, items(value).len() as u64)?;
+            items(value)
+                .iter()
+                .try_for_each(|item| write_row_value(out, inner, item))
+        }
+        ColumnType::Map(key, value_type) => {
+            write_var_u64(out, items(value).len() as u64)?;
+            for entry in items(value) {
+                let (k, v) = match entry {
+                    ColumnData::Tuple(pair) => (&pair[0], &pair[1]),
+                    _ => (&ColumnData::Null, &ColumnData::Null),
+                };
+                write_row_value(out, key, k)?;
+                write_row_value(out, value_type, v)?;
+            }
+            Ok(())
+        }
+        ColumnType::Tuple(elements) => {
+            for (idx, (_, element_type)) in elements.iter().enumerate() {
+                let element = match value {
+                    ColumnData::Tuple(items) => &items[idx],
+                    _ => &ColumnData::Null,
+                };
+                write_row_value(out, element_type, element)?;
+            }
+            Ok(())
+        }
+        _ => match value {
+            ColumnData::Null => write_value(out, column_type, &default_value(column_type)),
+            value => write_value(out, column_type, value),
+        },
+    }
+}
+
+/// Writes the header of `RowBinaryWithNames`, or of
+/// `RowBinaryWithNamesAndTypes` when `with_types` is set.
+pub(crate) fn write_rowbinary_header(
+    out: &mut impl Write,
+    columns: &[(String, ColumnType)],
+    with_types: bool,
+) -> io::Result<()> {
+    write_var_u64(out, columns.len() as u64)?;
+    for (name, _) in columns {
+        write_bytes(out, name.as_bytes())?;
+    }
+    if with_types {
+        for (_, column_type) in columns {
+            write_bytes(out, type_name(column_type).as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes rows in RowBinary, one value of each column after the other.
+pub(crate) fn write_rowbinary_rows(
+    out: &mut impl Write,
+    columns: &[(String, ColumnType)],
+    data: &[Vec<ColumnData>],
+) -> io::Result<()> {
+    let rows = data.first().map_or(0, Vec::len);
+    for row in 0..rows {
+        for ((_, column_type), values) in columns.iter().zip(data) {
+            write_row_value(out, column_type, &values[row])?;
+        }
+    }
+    Ok(())
+}
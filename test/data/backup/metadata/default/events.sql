@@ -0,0 +1 @@
+CREATE TABLE default.events (`id` UInt64, `d` Date, `s` String, `n` Nullable(Int32), `a` Array(String), `t` Tuple(x Int8, y String), `lc` LowCardinality(String), `m` Map(String, UInt16), `v` UInt32) ENGINE = MergeTree PARTITION BY toYYYYMM(d) ORDER BY id SETTINGS index_granularity = 3
\ No newline at end of file
@@ -0,0 +1,2 @@
+ATTACH DATABASE _ UUID '2f2d5c3e-8b1a-4e0f-9c4d-7a6b5e4d3c2b'
+ENGINE = Atomic
@@ -0,0 +1,8 @@
+ATTACH TABLE _
+(
+    `key` String,
+    `hits` UInt64 NULL,
+    `doubled` UInt64 MATERIALIZED hits * 2 CODEC(Delta, LZ4),
+    `seen` DateTime DEFAULT now()
+)
+ENGINE = Log
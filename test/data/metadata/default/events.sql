@@ -0,0 +1,18 @@
+ATTACH TABLE _ UUID '6b4c6a4e-4d6f-4c2a-9d6e-2f0c5b1e7a10'
+(
+    `id` UInt64,
+    `d` Date,
+    `s` String DEFAULT 'x' COMMENT 'the \'s\' column',
+    `n` Nullable(Int32) CODEC(ZSTD(1)),
+    `a` Array(String),
+    `t` Tuple(x Int8, y String),
+    `lc` LowCardinality(String),
+    `m` Map(String, UInt16),
+    `v` UInt32,
+    `total` UInt64 ALIAS id + v,
+    INDEX s_index s TYPE bloom_filter GRANULARITY 1
+)
+ENGINE = MergeTree
+PARTITION BY toYYYYMM(d)
+ORDER BY id
+SETTINGS index_granularity = 3
@@ -0,0 +1,8 @@
+ATTACH VIEW _ UUID 'a1b2c3d4-0000-4000-8000-000000000002'
+(
+    `id` UInt64,
+    `visits` Nested(day Date, hits UInt32)
+) AS
+SELECT id, visits.day, visits.hits
+FROM default.events
+ORDER BY id DESC
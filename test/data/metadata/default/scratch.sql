@@ -0,0 +1,8 @@
+ATTACH TABLE _ UUID 'a1b2c3d4-0000-4000-8000-000000000001'
+(
+    `id` UInt64,
+    `s` String,
+    `n` Nullable(Int32),
+    `a` Array(String)
+)
+ENGINE = TinyLog